@@ -0,0 +1,108 @@
+//! CPU core benchmarks
+//!
+//! Every benchmark measures the throughput of `step()` in instructions per second. Run with
+//! `cargo bench`; results serve as the baseline for performance work on the CPU core and
+//! memory implementations.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rusty64::cpu::mos6502::{assemble, RESET_VECTOR};
+use rusty64::cpu::{Cpu, Mos6502};
+use rusty64::mem::{Addressable, Ram};
+use std::io;
+
+/// Number of instructions to execute per benchmark iteration
+const INSTRUCTIONS: u64 = 10_000;
+
+/// Create a CPU that runs the given program at $1000
+fn cpu_with_program(source: &str) -> Mos6502<Ram> {
+    let mut mem = Ram::with_capacity(0xffff);
+    mem.copy_from_slice(0x1000, &assemble(source, 0x1000).unwrap());
+    mem.set_le(RESET_VECTOR, 0x1000_u16);
+    let mut cpu = Mos6502::new(mem);
+    cpu.step(); // process reset
+    cpu
+}
+
+/// Create a CPU that runs the instruction test ROM by Ruud Baltissen
+fn ruud_baltissen_cpu() -> Mos6502<Ram> {
+    // The ROM expects cleared RAM
+    let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+    cpu.memory_mut()
+        .load_file(0xe000, "share/test/ttl6502_v10.rom")
+        .unwrap();
+    // Decimal mode isn't supported yet, skip its tests
+    cpu.add_trap(0xf5b6, |cpu| cpu.set_pc(0xf5e6));
+    cpu.step(); // process reset
+    cpu
+}
+
+/// Step the given CPU for a fixed number of instructions
+fn run(cpu: &mut Mos6502<Ram>) {
+    for _ in 0..INSTRUCTIONS {
+        cpu.step();
+    }
+}
+
+fn programs(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mos6502");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+
+    let mut cpu = cpu_with_program(
+        "
+        start:  LDX #$00
+        loop:   DEX
+                BNE loop
+                JMP start
+        ",
+    );
+    group.bench_function("busy_loop", |b| b.iter(|| run(&mut cpu)));
+    cpu.set_trace_writer(Some(Box::new(io::sink())));
+    group.bench_function("busy_loop_traced", |b| b.iter(|| run(&mut cpu)));
+
+    let mut cpu = cpu_with_program(
+        "
+                LDA #$00        ; source $2000
+                STA $FB
+                LDA #$20
+                STA $FC
+                LDA #$00        ; destination $3000
+                STA $FD
+                LDA #$30
+                STA $FE
+        start:  LDY #$00
+        copy:   LDA ($FB),Y
+                STA ($FD),Y
+                INY
+                BNE copy
+                JMP start
+        ",
+    );
+    group.bench_function("memory_copy", |b| b.iter(|| run(&mut cpu)));
+
+    let mut cpu = cpu_with_program(
+        "
+        start:  LDX #$20
+                JSR recurse
+                JMP start
+        recurse:
+                DEX
+                BEQ done
+                JSR recurse
+        done:   RTS
+        ",
+    );
+    group.bench_function("subroutine_recursion", |b| b.iter(|| run(&mut cpu)));
+
+    group.bench_function("ruud_baltissen_rom", |b| {
+        b.iter_batched(
+            ruud_baltissen_cpu,
+            |mut cpu| run(&mut cpu),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, programs);
+criterion_main!(benches);
@@ -1,6 +1,6 @@
 //! Generic addresses
 
-use std::{fmt, mem};
+use std::{fmt, iter, mem};
 
 /// A trait for all 16-bit address types
 pub trait Address: Copy + Ord + Eq + fmt::UpperHex {
@@ -10,8 +10,25 @@ pub trait Address: Copy + Ord + Eq + fmt::UpperHex {
     /// The address as an unsigned integer
     fn to_u16(&self) -> u16;
 
+    /// The page number of the address (high byte)
+    fn page(&self) -> u8 {
+        (self.to_u16() >> 8) as u8
+    }
+
+    /// Returns true if both addresses are on the same page
+    fn same_page(&self, other: Self) -> bool {
+        self.page() == other.page()
+    }
+
+    /// Iterate over successive addresses from this address up to but not including the given end
+    /// address, wrapping around at the end of the address space
+    fn range(&self, end: Self) -> impl Iterator<Item = Self> + Clone {
+        let len = end.to_u16().wrapping_sub(self.to_u16());
+        iter::successors(Some(*self), |addr| Some(addr.offset(1))).take(len as usize)
+    }
+
     /// Return an object for displaying the address
-    fn display(&self) -> Display<Self> {
+    fn display(&self) -> Display<'_, Self> {
         Display { addr: self }
     }
 }
@@ -22,11 +39,8 @@ impl Address for u16 {
     }
 
     fn offset(&self, offset: i16) -> u16 {
-        if offset < 0 {
-            self.wrapping_sub(-offset as u16)
-        } else {
-            self.wrapping_add(offset as u16)
-        }
+        // Adding the two's complement subtracts negative offsets (including -32768)
+        self.wrapping_add(offset as u16)
     }
 }
 
@@ -60,6 +74,24 @@ mod tests {
     fn offset_wrapping() {
         assert_eq!(0xffff.offset(1), 0x0000);
         assert_eq!(0x0000.offset(-1), 0xffff);
+        assert_eq!(0x8000.offset(i16::MIN), 0x0000);
+        assert_eq!(0x1234.offset(i16::MIN), 0x9234);
+    }
+
+    #[test]
+    fn ranges() {
+        let addrs: Vec<u16> = 0x00fe.range(0x0102).collect();
+        assert_eq!(addrs, [0x00fe, 0x00ff, 0x0100, 0x0101]);
+        let addrs: Vec<u16> = 0xfffe.range(0x0002).collect();
+        assert_eq!(addrs, [0xfffe, 0xffff, 0x0000, 0x0001]);
+        assert_eq!(0x1234.range(0x1234).count(), 0);
+    }
+
+    #[test]
+    fn pages() {
+        assert_eq!(0x12ff.page(), 0x12);
+        assert!(0x1200.same_page(0x12ff));
+        assert!(!0x12ff.same_page(0x1300));
     }
 
     #[test]
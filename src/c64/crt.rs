@@ -0,0 +1,244 @@
+//! C64 cartridge images (CRT)
+//!
+//! File format: https://vice-emu.sourceforge.io/vice_17.html#SEC391
+
+use crate::addr::Address;
+use crate::mem::{Addressable, Rom};
+use std::ops::RangeInclusive;
+use std::{error, fmt};
+
+/// Signature at the start of every CRT file
+const SIGNATURE: &[u8; 16] = b"C64 CARTRIDGE   ";
+/// Signature at the start of every CHIP packet
+const CHIP_SIGNATURE: &[u8; 4] = b"CHIP";
+/// Length of the CHIP packet header
+const CHIP_HEADER_LEN: usize = 0x10;
+/// Address where the cartridge ROM is mapped (ROML, followed by ROMH for 16K cartridges)
+const ROM_START: u16 = 0x8000;
+/// Maximum size of a normal cartridge ROM (ROML and ROMH)
+const ROM_MAX_SIZE: usize = 0x4000;
+
+/// An error that occured while parsing a cartridge image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CrtError {
+    /// The image doesn't start with the CRT signature
+    InvalidSignature,
+    /// The image ends in the middle of the header or a CHIP packet
+    Truncated,
+    /// Cartridge hardware type that isn't supported (yet)
+    UnsupportedType(u16),
+    /// CHIP packet with a load address and size that doesn't fit the cartridge type
+    InvalidChip(u16, u16),
+    /// The image doesn't contain any ROM
+    NoRom,
+}
+
+impl fmt::Display for CrtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CrtError::InvalidSignature => write!(f, "Invalid CRT signature"),
+            CrtError::Truncated => write!(f, "Truncated CRT image"),
+            CrtError::UnsupportedType(hw) => write!(f, "Unsupported cartridge type ({})", hw),
+            CrtError::InvalidChip(addr, size) => write!(
+                f,
+                "Invalid CHIP packet ({} bytes at {})",
+                size,
+                addr.display()
+            ),
+            CrtError::NoRom => write!(f, "CRT image without ROM"),
+        }
+    }
+}
+
+impl error::Error for CrtError {}
+
+/// A normal (type 0) cartridge with 8K ROM at $8000 or 16K ROM at $8000-$BFFF. Addresses are
+/// relative to $8000, so the cartridge can be mapped to its `range` directly.
+pub struct Cartridge {
+    name: String,
+    exrom: bool,
+    game: bool,
+    rom: Rom,
+}
+
+impl Cartridge {
+    /// Parse the given CRT image
+    pub fn from_crt(data: &[u8]) -> Result<Cartridge, CrtError> {
+        if !data.starts_with(SIGNATURE) {
+            return Err(CrtError::InvalidSignature);
+        }
+        let header_len = read_be32(data, 0x10)? as usize;
+        let hardware_type = read_be16(data, 0x16)?;
+        if hardware_type != 0 {
+            return Err(CrtError::UnsupportedType(hardware_type));
+        }
+        let lines = data.get(0x18..0x1a).ok_or(CrtError::Truncated)?;
+        let name = data.get(0x20..0x40).ok_or(CrtError::Truncated)?;
+        let name = name.iter().take_while(|&&b| b != 0).map(|&b| b as char);
+        // ROM contents as seen from $8000, unused parts read as open bus
+        let mut rom = Vec::new();
+        let mut offset = header_len;
+        while offset < data.len() {
+            let packet = &data[offset..];
+            if !packet.starts_with(CHIP_SIGNATURE) {
+                return Err(CrtError::InvalidSignature);
+            }
+            let packet_len = read_be32(packet, 0x04)? as usize;
+            let addr = read_be16(packet, 0x0c)?;
+            let size = read_be16(packet, 0x0e)?;
+            let image = packet
+                .get(CHIP_HEADER_LEN..CHIP_HEADER_LEN + size as usize)
+                .ok_or(CrtError::Truncated)?;
+            let start = addr.wrapping_sub(ROM_START) as usize;
+            let end = start + size as usize;
+            if addr < ROM_START || end > ROM_MAX_SIZE {
+                return Err(CrtError::InvalidChip(addr, size));
+            }
+            if rom.len() < end {
+                rom.resize(end, 0xff);
+            }
+            rom[start..end].copy_from_slice(image);
+            offset += packet_len.max(CHIP_HEADER_LEN);
+        }
+        if rom.is_empty() {
+            return Err(CrtError::NoRom);
+        }
+        Ok(Cartridge {
+            name: name.collect(),
+            exrom: lines[0] != 0,
+            game: lines[1] != 0,
+            rom: Rom::from_bytes(rom),
+        })
+    }
+
+    /// Returns the name of the cartridge
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the level of the EXROM line (false if the cartridge pulls it low, which maps
+    /// ROML to $8000)
+    pub fn exrom(&self) -> bool {
+        self.exrom
+    }
+
+    /// Returns the level of the GAME line (false if the cartridge pulls it low, which maps ROMH
+    /// to $A000 for 16K cartridges)
+    pub fn game(&self) -> bool {
+        self.game
+    }
+
+    /// Returns the address range the cartridge ROM is mapped to
+    pub fn range(&self) -> RangeInclusive<u16> {
+        ROM_START..=ROM_START + (self.rom.capacity() - 1) as u16
+    }
+}
+
+impl Addressable for Cartridge {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        self.rom.get(addr)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        self.rom.set(addr, data);
+    }
+}
+
+fn read_be16(data: &[u8], offset: usize) -> Result<u16, CrtError> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(CrtError::Truncated),
+    }
+}
+
+fn read_be32(data: &[u8], offset: usize) -> Result<u32, CrtError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        None => Err(CrtError::Truncated),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{Banked, Ram};
+
+    fn crt(exrom: u8, game: u8, chips: &[(u16, &[u8])]) -> Vec<u8> {
+        let mut data = SIGNATURE.to_vec();
+        data.extend_from_slice(&[0x00, 0x00, 0x00, 0x40, 0x01, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(&[exrom, game, 0, 0, 0, 0, 0, 0]);
+        let mut name = b"TEST CART".to_vec();
+        name.resize(32, 0);
+        data.extend_from_slice(&name);
+        for &(addr, image) in chips {
+            data.extend_from_slice(CHIP_SIGNATURE);
+            data.extend_from_slice(&(image.len() as u32 + 0x10).to_be_bytes());
+            data.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+            data.extend_from_slice(&addr.to_be_bytes());
+            data.extend_from_slice(&(image.len() as u16).to_be_bytes());
+            data.extend_from_slice(image);
+        }
+        data
+    }
+
+    #[test]
+    fn normal_8k_cartridge() {
+        let mut image = vec![0; 0x2000];
+        image[0x0004..0x0009].copy_from_slice(b"\xc3\xc2\xcd80"); // CBM80 signature
+        image[0x1fff] = 0x42;
+        let cartridge = Cartridge::from_crt(&crt(0, 1, &[(0x8000, &image)])).unwrap();
+        assert_eq!(cartridge.name(), "TEST CART");
+        assert!(!cartridge.exrom());
+        assert!(cartridge.game());
+        assert_eq!(cartridge.range(), 0x8000..=0x9fff);
+        assert_eq!(cartridge.get(0x0004), 0xc3);
+        // Map into banked memory while EXROM is pulled low
+        let exrom = cartridge.exrom();
+        let mut mem = Banked::new(exrom);
+        mem.map(0x0000..=0xffff, Ram::zeroed(0xffff), |_| true);
+        mem.map_read_only(cartridge.range(), cartridge, |&exrom| !exrom);
+        assert_eq!(mem.get(0x9fff), 0x42);
+        assert_eq!(mem.get(0xa000), 0x00);
+        mem.set_config(true);
+        assert_eq!(mem.get(0x9fff), 0x00);
+    }
+
+    #[test]
+    fn normal_16k_cartridge_with_two_chips() {
+        let roml = [0x11; 0x2000];
+        let romh = [0x22; 0x2000];
+        let data = crt(0, 0, &[(0xa000, &romh), (0x8000, &roml)]);
+        let cartridge = Cartridge::from_crt(&data).unwrap();
+        assert!(!cartridge.game());
+        assert_eq!(cartridge.range(), 0x8000..=0xbfff);
+        assert_eq!(cartridge.get(0x1fff), 0x11);
+        assert_eq!(cartridge.get(0x2000), 0x22);
+    }
+
+    #[test]
+    fn invalid_images() {
+        assert!(matches!(
+            Cartridge::from_crt(b"C64 TAPE"),
+            Err(CrtError::InvalidSignature)
+        ));
+        let data = crt(0, 1, &[(0x8000, &[0; 0x2000])]);
+        assert!(matches!(
+            Cartridge::from_crt(&data[..0x1000]),
+            Err(CrtError::Truncated)
+        ));
+        assert!(matches!(
+            Cartridge::from_crt(&crt(0, 1, &[])),
+            Err(CrtError::NoRom)
+        ));
+        assert!(matches!(
+            Cartridge::from_crt(&crt(1, 0, &[(0xe000, &[0; 0x2000])])),
+            Err(CrtError::InvalidChip(0xe000, 0x2000))
+        ));
+        let mut data = crt(0, 1, &[(0x8000, &[0; 0x2000])]);
+        data[0x17] = 32; // Simons' BASIC
+        assert!(matches!(
+            Cartridge::from_crt(&data),
+            Err(CrtError::UnsupportedType(32))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+//! C64 disk images (D64)
+//!
+//! File format: http://unusedino.de/ec64/technical/formats/d64.html
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::{error, fmt};
+
+/// Size of a 35 track disk image without error information
+const IMAGE_SIZE: usize = 174848;
+/// Number of tracks
+const TRACKS: u8 = 35;
+/// Size of a sector
+const SECTOR_SIZE: usize = 256;
+/// Track of the BAM and directory
+const DIRECTORY_TRACK: u8 = 18;
+/// Size of a directory entry
+const ENTRY_SIZE: usize = 32;
+/// Padding character of names
+const PADDING: u8 = 0xa0;
+
+/// An error that occured while reading a disk image
+#[derive(Debug)]
+pub enum D64Error {
+    /// The image file couldn't be read
+    Io(io::Error),
+    /// The image doesn't have the size of a 35 track disk
+    InvalidSize(usize),
+    /// A sector chain links to a sector that doesn't exist or is linked already
+    InvalidSector(u8, u8),
+    /// No file with the given name exists
+    FileNotFound(String),
+}
+
+impl fmt::Display for D64Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            D64Error::Io(ref err) => write!(f, "Unable to read disk image: {}", err),
+            D64Error::InvalidSize(size) => write!(f, "Invalid disk image size ({} bytes)", size),
+            D64Error::InvalidSector(track, sector) => {
+                write!(f, "Invalid sector link ({}/{})", track, sector)
+            }
+            D64Error::FileNotFound(ref name) => write!(f, "File not found ({})", name),
+        }
+    }
+}
+
+impl error::Error for D64Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            D64Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for D64Error {
+    fn from(err: io::Error) -> D64Error {
+        D64Error::Io(err)
+    }
+}
+
+/// Type of a file on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    /// Deleted
+    Del,
+    /// Sequential
+    Seq,
+    /// Program
+    Prg,
+    /// User
+    Usr,
+    /// Relative
+    Rel,
+}
+
+/// A file in the directory of a disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// Name of the file (PETSCII characters without padding)
+    pub name: String,
+    /// Type of the file
+    pub file_type: FileType,
+    /// Size of the file in blocks (sectors) as stored in the directory
+    pub blocks: u16,
+    /// Track and sector of the first sector of the file
+    pub start: (u8, u8),
+}
+
+/// A 35 track disk image. Only supports extracting files, there's no emulation of the disk
+/// drive.
+pub struct D64 {
+    data: Vec<u8>,
+}
+
+impl D64 {
+    /// Read the disk image from the given file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<D64, D64Error> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        D64::from_bytes(data)
+    }
+
+    /// Create a disk image with the given contents
+    pub fn from_bytes(data: Vec<u8>) -> Result<D64, D64Error> {
+        if data.len() != IMAGE_SIZE {
+            return Err(D64Error::InvalidSize(data.len()));
+        }
+        Ok(D64 { data })
+    }
+
+    /// Returns the name of the disk
+    pub fn name(&self) -> String {
+        petscii_name(&self.sector(DIRECTORY_TRACK, 0).unwrap()[0x90..0xa0])
+    }
+
+    /// Returns all files in the directory (except scratched ones). An invalid link in the
+    /// directory chain ends the directory.
+    pub fn directory(&self) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+        let _ = self.follow_chain(DIRECTORY_TRACK, 1, |sector, _| {
+            for entry in sector.chunks(ENTRY_SIZE) {
+                // Scratched files and unused entries have the closed bit cleared
+                if entry[2] & 0x80 == 0 {
+                    continue;
+                }
+                let file_type = match entry[2] & 0x07 {
+                    0 => FileType::Del,
+                    1 => FileType::Seq,
+                    2 => FileType::Prg,
+                    3 => FileType::Usr,
+                    _ => FileType::Rel,
+                };
+                entries.push(DirEntry {
+                    name: petscii_name(&entry[0x05..0x15]),
+                    file_type,
+                    blocks: u16::from_le_bytes([entry[0x1e], entry[0x1f]]),
+                    start: (entry[0x03], entry[0x04]),
+                });
+            }
+        });
+        entries
+    }
+
+    /// Returns the contents of the file with the given name. For programs, the first two bytes
+    /// are the load address.
+    pub fn read_file(&self, name: &str) -> Result<Vec<u8>, D64Error> {
+        let entry = self
+            .directory()
+            .into_iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| D64Error::FileNotFound(name.to_string()))?;
+        let mut data = Vec::new();
+        let (track, sector) = entry.start;
+        self.follow_chain(track, sector, |sector, used| {
+            data.extend_from_slice(&sector[2..used]);
+        })?;
+        Ok(data)
+    }
+
+    /// Follow the sector chain starting at the given sector and invoke the given function with
+    /// the contents of every sector and the number of used bytes in it. The first two bytes of
+    /// every sector link to the next sector, or give the index of the last used byte in the
+    /// last sector.
+    fn follow_chain<F>(&self, mut track: u8, mut sector: u8, mut f: F) -> Result<(), D64Error>
+    where
+        F: FnMut(&[u8], usize),
+    {
+        let mut visited = Vec::new();
+        loop {
+            if visited.contains(&(track, sector)) {
+                return Err(D64Error::InvalidSector(track, sector));
+            }
+            visited.push((track, sector));
+            let data = self
+                .sector(track, sector)
+                .ok_or(D64Error::InvalidSector(track, sector))?;
+            if data[0] == 0 {
+                f(data, (data[1] as usize + 1).max(2));
+                return Ok(());
+            }
+            f(data, SECTOR_SIZE);
+            (track, sector) = (data[0], data[1]);
+        }
+    }
+
+    /// Returns the contents of the given sector
+    fn sector(&self, track: u8, sector: u8) -> Option<&[u8]> {
+        if track == 0 || track > TRACKS || sector >= sectors_per_track(track) {
+            return None;
+        }
+        let index: usize = (1..track).map(|t| sectors_per_track(t) as usize).sum();
+        let offset = (index + sector as usize) * SECTOR_SIZE;
+        Some(&self.data[offset..offset + SECTOR_SIZE])
+    }
+}
+
+/// Returns the number of sectors of the given track
+fn sectors_per_track(track: u8) -> u8 {
+    match track {
+        1..=17 => 21,
+        18..=24 => 19,
+        25..=30 => 18,
+        _ => 17,
+    }
+}
+
+/// Convert the given padded name to a string
+fn petscii_name(name: &[u8]) -> String {
+    name.iter()
+        .take_while(|&&b| b != PADDING)
+        .map(|&b| match b {
+            0x20..=0x7e => b as char,
+            _ => '?',
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk() -> D64 {
+        D64::open("share/test/hello.d64").unwrap()
+    }
+
+    #[test]
+    fn directory() {
+        let disk = disk();
+        assert_eq!(disk.name(), "TEST DISK");
+        assert_eq!(
+            disk.directory(),
+            [
+                DirEntry {
+                    name: "HELLO".to_string(),
+                    file_type: FileType::Prg,
+                    blocks: 2,
+                    start: (17, 0),
+                },
+                DirEntry {
+                    name: "DATA".to_string(),
+                    file_type: FileType::Seq,
+                    blocks: 1,
+                    start: (19, 0),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn read_file() {
+        let disk = disk();
+        let program = disk.read_file("HELLO").unwrap();
+        assert_eq!(program.len(), 300);
+        assert_eq!(program[..6], [0x01, 0x08, 0x0b, 0x08, 0x0a, 0x00]);
+        assert_eq!(disk.read_file("DATA").unwrap(), b"HELLO");
+        assert!(matches!(
+            disk.read_file("MISSING"),
+            Err(D64Error::FileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_image() {
+        assert!(matches!(
+            D64::from_bytes(vec![0; 1000]),
+            Err(D64Error::InvalidSize(1000))
+        ));
+    }
+}
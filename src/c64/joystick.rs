@@ -0,0 +1,148 @@
+//! C64 joystick
+//!
+//! Control ports: https://www.c64-wiki.com/wiki/Control_Port
+
+use crate::io::Cia;
+use std::cell::Cell;
+use std::rc::Rc;
+
+const UP: u8 = 0x01;
+const DOWN: u8 = 0x02;
+const LEFT: u8 = 0x04;
+const RIGHT: u8 = 0x08;
+const FIRE: u8 = 0x10;
+
+/// Direction a joystick is pushed to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Direction {
+    /// Not pushed into any direction
+    #[default]
+    Center,
+    /// Up
+    Up,
+    /// Up and right
+    UpRight,
+    /// Right
+    Right,
+    /// Down and right
+    DownRight,
+    /// Down
+    Down,
+    /// Down and left
+    DownLeft,
+    /// Left
+    Left,
+    /// Up and left
+    UpLeft,
+}
+
+impl Direction {
+    /// Returns the switches closed by this direction
+    fn switches(self) -> u8 {
+        match self {
+            Direction::Center => 0,
+            Direction::Up => UP,
+            Direction::UpRight => UP | RIGHT,
+            Direction::Right => RIGHT,
+            Direction::DownRight => DOWN | RIGHT,
+            Direction::Down => DOWN,
+            Direction::DownLeft => DOWN | LEFT,
+            Direction::Left => LEFT,
+            Direction::UpLeft => UP | LEFT,
+        }
+    }
+}
+
+/// Control port a joystick is plugged into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ControlPort {
+    /// Control port 1, read through CIA1 port B ($DC01)
+    One,
+    /// Control port 2, read through CIA1 port A ($DC00)
+    Two,
+}
+
+/// State of a digital joystick. Clones of the joystick refer to the same state, so a frontend can
+/// move the joystick while the CIA reads it.
+///
+/// Every closed switch pulls its line low: bit 0 is up, bit 1 down, bit 2 left, bit 3 right and
+/// bit 4 fire. Since the lines are shared with the keyboard matrix, a joystick in control port 1
+/// also shows up as pressed keys.
+#[derive(Debug, Clone, Default)]
+pub struct Joystick(Rc<Cell<u8>>);
+
+impl Joystick {
+    /// Create a new joystick in center position with the fire button released
+    pub fn new() -> Joystick {
+        Joystick::default()
+    }
+
+    /// Push the joystick into the given direction
+    pub fn set_direction(&self, direction: Direction) {
+        self.0.set(self.0.get() & FIRE | direction.switches());
+    }
+
+    /// Press or release the fire button
+    pub fn set_fire(&self, pressed: bool) {
+        let switches = self.0.get() & !FIRE;
+        self.0.set(if pressed { switches | FIRE } else { switches });
+    }
+
+    /// Returns true if the fire button is pressed
+    pub fn is_fire_pressed(&self) -> bool {
+        self.0.get() & FIRE != 0
+    }
+
+    /// Returns the levels the joystick drives to the port it is connected to (active-low)
+    pub fn levels(&self) -> u8 {
+        !self.0.get()
+    }
+
+    /// Connect the joystick to the given control port, i.e. to the corresponding port of the
+    /// given CIA (CIA1 on a C64)
+    pub fn connect(&self, cia: &mut Cia, port: ControlPort) {
+        let joystick = self.clone();
+        match port {
+            ControlPort::One => cia.add_port_b_input(move |_| joystick.levels()),
+            ControlPort::Two => cia.add_port_a_input(move |_| joystick.levels()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::c64::{Key, Keyboard};
+    use crate::mem::Addressable;
+
+    #[test]
+    fn up_and_fire() {
+        let joystick = Joystick::new();
+        let mut cia = Cia::new();
+        joystick.connect(&mut cia, ControlPort::Two);
+        assert_eq!(cia.get(0x00), 0xff);
+        joystick.set_direction(Direction::Up);
+        joystick.set_fire(true);
+        assert_eq!(cia.get(0x00), 0xee);
+        assert_eq!(cia.get(0x01), 0xff);
+        joystick.set_direction(Direction::DownLeft);
+        assert_eq!(cia.get(0x00), 0xe9);
+        joystick.set_fire(false);
+        joystick.set_direction(Direction::Center);
+        assert_eq!(cia.get(0x00), 0xff);
+    }
+
+    #[test]
+    fn shared_with_keyboard() {
+        let joystick = Joystick::new();
+        let keyboard = Keyboard::new();
+        let mut cia = Cia::new();
+        keyboard.connect(&mut cia);
+        joystick.connect(&mut cia, ControlPort::One);
+        cia.set(0x02, 0xff); // all port A pins are outputs
+        cia.set(0x00, 0x00); // select all rows
+        keyboard.press(Key::LeftShift);
+        joystick.set_direction(Direction::Right);
+        assert_eq!(cia.get(0x01), 0x77);
+    }
+}
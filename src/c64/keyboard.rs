@@ -0,0 +1,255 @@
+//! C64 keyboard
+//!
+//! Keyboard matrix: https://www.c64-wiki.com/wiki/Keyboard#Hardware
+
+use crate::io::Cia;
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Keys of the C64 keyboard. The value of a key is its position in the keyboard matrix
+/// (row * 8 + column). RESTORE isn't part of the matrix, it is wired to the NMI line instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Key {
+    // Row 0
+    /// INST/DEL
+    Delete = 0,
+    /// RETURN
+    Return = 1,
+    /// CRSR left/right
+    CursorRight = 2,
+    /// F7/F8
+    F7 = 3,
+    /// F1/F2
+    F1 = 4,
+    /// F3/F4
+    F3 = 5,
+    /// F5/F6
+    F5 = 6,
+    /// CRSR up/down
+    CursorDown = 7,
+    // Row 1
+    /// 3
+    Num3 = 8,
+    /// W
+    W = 9,
+    /// A
+    A = 10,
+    /// 4
+    Num4 = 11,
+    /// Z
+    Z = 12,
+    /// S
+    S = 13,
+    /// E
+    E = 14,
+    /// Left SHIFT
+    LeftShift = 15,
+    // Row 2
+    /// 5
+    Num5 = 16,
+    /// R
+    R = 17,
+    /// D
+    D = 18,
+    /// 6
+    Num6 = 19,
+    /// C
+    C = 20,
+    /// F
+    F = 21,
+    /// T
+    T = 22,
+    /// X
+    X = 23,
+    // Row 3
+    /// 7
+    Num7 = 24,
+    /// Y
+    Y = 25,
+    /// G
+    G = 26,
+    /// 8
+    Num8 = 27,
+    /// B
+    B = 28,
+    /// H
+    H = 29,
+    /// U
+    U = 30,
+    /// V
+    V = 31,
+    // Row 4
+    /// 9
+    Num9 = 32,
+    /// I
+    I = 33,
+    /// J
+    J = 34,
+    /// 0
+    Num0 = 35,
+    /// M
+    M = 36,
+    /// K
+    K = 37,
+    /// O
+    O = 38,
+    /// N
+    N = 39,
+    // Row 5
+    /// +
+    Plus = 40,
+    /// P
+    P = 41,
+    /// L
+    L = 42,
+    /// -
+    Minus = 43,
+    /// .
+    Period = 44,
+    /// :
+    Colon = 45,
+    /// @
+    At = 46,
+    /// ,
+    Comma = 47,
+    // Row 6
+    /// £
+    Pound = 48,
+    /// *
+    Asterisk = 49,
+    /// ;
+    Semicolon = 50,
+    /// CLR/HOME
+    Home = 51,
+    /// Right SHIFT
+    RightShift = 52,
+    /// =
+    Equals = 53,
+    /// ↑
+    UpArrow = 54,
+    /// /
+    Slash = 55,
+    // Row 7
+    /// 1
+    Num1 = 56,
+    /// ←
+    LeftArrow = 57,
+    /// CTRL
+    Control = 58,
+    /// 2
+    Num2 = 59,
+    /// SPACE
+    Space = 60,
+    /// C=
+    Commodore = 61,
+    /// Q
+    Q = 62,
+    /// RUN/STOP
+    RunStop = 63,
+}
+
+impl Key {
+    /// Returns the row of the key in the keyboard matrix (bit of CIA1 port A)
+    pub fn row(self) -> u8 {
+        self as u8 >> 3
+    }
+
+    /// Returns the column of the key in the keyboard matrix (bit of CIA1 port B)
+    pub fn column(self) -> u8 {
+        self as u8 & 0x07
+    }
+}
+
+/// State of the C64 keyboard matrix. Clones of the keyboard refer to the same matrix, so a
+/// frontend can press keys while the CIA scans the matrix.
+///
+/// The keyboard is scanned by pulling rows low on CIA1 port A ($DC00) and reading the columns
+/// on port B ($DC01). Pressed keys connect their row and column, so the column of a pressed key
+/// in a selected row reads low.
+#[derive(Debug, Clone, Default)]
+pub struct Keyboard(Rc<Cell<[u8; 8]>>);
+
+impl Keyboard {
+    /// Create a new keyboard with no keys pressed
+    pub fn new() -> Keyboard {
+        Keyboard::default()
+    }
+
+    /// Press the given key
+    pub fn press(&self, key: Key) {
+        let mut matrix = self.0.get();
+        matrix[key.row() as usize] |= 1 << key.column();
+        self.0.set(matrix);
+    }
+
+    /// Release the given key
+    pub fn release(&self, key: Key) {
+        let mut matrix = self.0.get();
+        matrix[key.row() as usize] &= !(1 << key.column());
+        self.0.set(matrix);
+    }
+
+    /// Release all keys
+    pub fn release_all(&self) {
+        self.0.set([0; 8]);
+    }
+
+    /// Returns true if the given key is pressed
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.0.get()[key.row() as usize] & (1 << key.column()) != 0
+    }
+
+    /// Returns the column levels for the given row levels. Rows that are pulled low select
+    /// their row, pressed keys in selected rows pull their column low.
+    pub fn scan(&self, rows: u8) -> u8 {
+        let matrix = self.0.get();
+        let pressed = (0..8)
+            .filter(|row| rows & (1 << row) == 0)
+            .fold(0, |columns, row| columns | matrix[row]);
+        !pressed
+    }
+
+    /// Connect the keyboard to the ports of the given CIA (CIA1 on a C64)
+    pub fn connect(&self, cia: &mut Cia) {
+        let keyboard = self.clone();
+        cia.add_port_b_input(move |rows| keyboard.scan(rows));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Addressable;
+
+    #[test]
+    fn key_positions() {
+        assert_eq!((Key::Delete.row(), Key::Delete.column()), (0, 0));
+        assert_eq!((Key::A.row(), Key::A.column()), (1, 2));
+        assert_eq!((Key::RunStop.row(), Key::RunStop.column()), (7, 7));
+    }
+
+    #[test]
+    fn scan_matrix() {
+        let keyboard = Keyboard::new();
+        let mut cia = Cia::new();
+        keyboard.connect(&mut cia);
+        cia.set(0x02, 0xff); // all port A pins are outputs
+        keyboard.press(Key::A);
+        assert!(keyboard.is_pressed(Key::A));
+        // Select row 1
+        cia.set(0x00, 0xfd);
+        assert_eq!(cia.get(0x01), 0xfb);
+        // Select row 0
+        cia.set(0x00, 0xfe);
+        assert_eq!(cia.get(0x01), 0xff);
+        // Select all rows
+        keyboard.press(Key::Space);
+        cia.set(0x00, 0x00);
+        assert_eq!(cia.get(0x01), 0xeb);
+        keyboard.release(Key::A);
+        assert_eq!(cia.get(0x01), 0xef);
+        keyboard.release_all();
+        assert_eq!(cia.get(0x01), 0xff);
+    }
+}
@@ -0,0 +1,200 @@
+//! Host keyboard to C64 keyboard mapping
+
+use super::{Key, Keyboard};
+use std::collections::HashMap;
+
+/// Default mapping of host key names to C64 keys. The mapping is positional for a US keyboard
+/// layout, i.e. host keys map to the C64 key at the same position rather than the one with the
+/// same symbol. Keys that need SHIFT on a C64 (cursor up/left, insert, F2/F4/F6/F8) map to
+/// several keys.
+const DEFAULT_MAP: &[(&str, &[Key])] = &[
+    ("A", &[Key::A]),
+    ("B", &[Key::B]),
+    ("C", &[Key::C]),
+    ("D", &[Key::D]),
+    ("E", &[Key::E]),
+    ("F", &[Key::F]),
+    ("G", &[Key::G]),
+    ("H", &[Key::H]),
+    ("I", &[Key::I]),
+    ("J", &[Key::J]),
+    ("K", &[Key::K]),
+    ("L", &[Key::L]),
+    ("M", &[Key::M]),
+    ("N", &[Key::N]),
+    ("O", &[Key::O]),
+    ("P", &[Key::P]),
+    ("Q", &[Key::Q]),
+    ("R", &[Key::R]),
+    ("S", &[Key::S]),
+    ("T", &[Key::T]),
+    ("U", &[Key::U]),
+    ("V", &[Key::V]),
+    ("W", &[Key::W]),
+    ("X", &[Key::X]),
+    ("Y", &[Key::Y]),
+    ("Z", &[Key::Z]),
+    ("0", &[Key::Num0]),
+    ("1", &[Key::Num1]),
+    ("2", &[Key::Num2]),
+    ("3", &[Key::Num3]),
+    ("4", &[Key::Num4]),
+    ("5", &[Key::Num5]),
+    ("6", &[Key::Num6]),
+    ("7", &[Key::Num7]),
+    ("8", &[Key::Num8]),
+    ("9", &[Key::Num9]),
+    ("-", &[Key::Plus]),
+    ("=", &[Key::Minus]),
+    ("\\", &[Key::Pound]),
+    ("`", &[Key::LeftArrow]),
+    ("[", &[Key::At]),
+    ("]", &[Key::Asterisk]),
+    (";", &[Key::Colon]),
+    ("'", &[Key::Semicolon]),
+    (",", &[Key::Comma]),
+    (".", &[Key::Period]),
+    ("/", &[Key::Slash]),
+    ("Space", &[Key::Space]),
+    ("Return", &[Key::Return]),
+    ("Backspace", &[Key::Delete]),
+    ("Insert", &[Key::LeftShift, Key::Delete]),
+    ("Home", &[Key::Home]),
+    ("Page Up", &[Key::UpArrow]),
+    ("Page Down", &[Key::Equals]),
+    ("Escape", &[Key::RunStop]),
+    ("Tab", &[Key::Control]),
+    ("Left Ctrl", &[Key::Commodore]),
+    ("Left Shift", &[Key::LeftShift]),
+    ("Right Shift", &[Key::RightShift]),
+    ("Down", &[Key::CursorDown]),
+    ("Right", &[Key::CursorRight]),
+    ("Up", &[Key::RightShift, Key::CursorDown]),
+    ("Left", &[Key::RightShift, Key::CursorRight]),
+    ("F1", &[Key::F1]),
+    ("F2", &[Key::LeftShift, Key::F1]),
+    ("F3", &[Key::F3]),
+    ("F4", &[Key::LeftShift, Key::F3]),
+    ("F5", &[Key::F5]),
+    ("F6", &[Key::LeftShift, Key::F5]),
+    ("F7", &[Key::F7]),
+    ("F8", &[Key::LeftShift, Key::F7]),
+];
+
+/// Translation of host keys to C64 keys. Host keys are identified by name (the names SDL uses
+/// for keycodes, like `A`, `Return` or `Left Shift`), so the mapping doesn't depend on a
+/// specific frontend. A host key can map to several C64 keys that are pressed together.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    keys: HashMap<String, Vec<Key>>,
+}
+
+impl KeyMap {
+    /// Create a new key map without any mapped keys
+    pub fn new() -> KeyMap {
+        KeyMap {
+            keys: HashMap::new(),
+        }
+    }
+
+    /// Map the given host key to the given C64 keys, replacing a previous mapping
+    pub fn map(&mut self, host_key: &str, keys: &[Key]) {
+        self.keys.insert(host_key.to_string(), keys.to_vec());
+    }
+
+    /// Remove the mapping of the given host key
+    pub fn unmap(&mut self, host_key: &str) {
+        self.keys.remove(host_key);
+    }
+
+    /// Returns the C64 keys the given host key maps to
+    pub fn get(&self, host_key: &str) -> Option<&[Key]> {
+        self.keys.get(host_key).map(Vec::as_slice)
+    }
+
+    /// Press the C64 keys the given host key maps to. Returns false if the host key isn't
+    /// mapped.
+    pub fn press(&self, keyboard: &Keyboard, host_key: &str) -> bool {
+        match self.get(host_key) {
+            Some(keys) => {
+                keys.iter().for_each(|&key| keyboard.press(key));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Release the C64 keys the given host key maps to. Returns false if the host key isn't
+    /// mapped.
+    pub fn release(&self, keyboard: &Keyboard, host_key: &str) -> bool {
+        match self.get(host_key) {
+            Some(keys) => {
+                keys.iter().for_each(|&key| keyboard.release(key));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Default for KeyMap {
+    /// Create a new key map with the default positional mapping
+    fn default() -> KeyMap {
+        let mut keymap = KeyMap::new();
+        for &(host_key, keys) in DEFAULT_MAP {
+            keymap.map(host_key, keys);
+        }
+        keymap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_map() {
+        let keymap = KeyMap::default();
+        let positions: Vec<Vec<(u8, u8)>> = ["A", "Return", "1", "Escape", "Left"]
+            .iter()
+            .map(|name| {
+                let keys = keymap.get(name).unwrap();
+                keys.iter().map(|key| (key.row(), key.column())).collect()
+            })
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                vec![(1, 2)],
+                vec![(0, 1)],
+                vec![(7, 0)],
+                vec![(7, 7)],
+                vec![(6, 4), (0, 2)],
+            ]
+        );
+        assert_eq!(keymap.get("Num Lock"), None);
+    }
+
+    #[test]
+    fn override_mapping() {
+        let mut keymap = KeyMap::default();
+        keymap.map("Right Ctrl", &[Key::Commodore]);
+        keymap.map("Z", &[Key::Y]);
+        keymap.unmap("Y");
+        assert_eq!(keymap.get("Right Ctrl"), Some(&[Key::Commodore][..]));
+        assert_eq!(keymap.get("Z"), Some(&[Key::Y][..]));
+        assert_eq!(keymap.get("Y"), None);
+    }
+
+    #[test]
+    fn feed_keyboard() {
+        let keymap = KeyMap::default();
+        let keyboard = Keyboard::new();
+        assert!(keymap.press(&keyboard, "F2"));
+        assert!(keyboard.is_pressed(Key::LeftShift));
+        assert!(keyboard.is_pressed(Key::F1));
+        assert!(keymap.release(&keyboard, "F2"));
+        assert!(!keyboard.is_pressed(Key::F1));
+        assert!(!keymap.press(&keyboard, "Num Lock"));
+    }
+}
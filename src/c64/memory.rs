@@ -0,0 +1,143 @@
+//! C64 memory map
+//!
+//! Memory map: http://www.c64-wiki.com/index.php/Memory_Map
+//! Bank switching: http://www.c64-wiki.com/index.php/Bank_Switching
+
+use crate::cpu::PortLines;
+use crate::mem::{Addressable, Banked};
+
+/// CPU port bit that banks in BASIC ROM (together with HIRAM)
+const LORAM: u8 = 0x01;
+/// CPU port bit that banks in KERNAL ROM
+const HIRAM: u8 = 0x02;
+/// CPU port bit that selects I/O (high) or character ROM (low) at $D000-$DFFF
+const CHAREN: u8 = 0x04;
+
+/// Returns true if the given line is high at the CPU port
+fn high(lines: &PortLines, line: u8) -> bool {
+    lines.levels() & line != 0
+}
+
+/// Create the memory as seen by the processor of a C64. The banking configuration is derived
+/// from the levels of the given CPU port lines (i.e. the data register masked by the DDR, with
+/// inputs pulled up), so the processor needs to be created with the same port lines using
+/// `Mos6510::with_port_lines`. Writing $01 then switches BASIC, KERNAL, character ROM and I/O
+/// in and out like on a real C64. Writes to ROM areas go to the RAM underneath.
+///
+/// The RAM needs to cover the whole address space, the ROMs and I/O are mapped to their
+/// standard locations (BASIC at $A000, character ROM and I/O at $D000, KERNAL at $E000).
+/// Cartridges aren't supported yet, i.e. GAME and EXROM are assumed to be high.
+pub fn memory_map<R, B, C, I, K>(
+    lines: PortLines,
+    ram: R,
+    basic: B,
+    characters: C,
+    io: I,
+    kernal: K,
+) -> Banked<PortLines>
+where
+    R: Addressable + 'static,
+    B: Addressable + 'static,
+    C: Addressable + 'static,
+    I: Addressable + 'static,
+    K: Addressable + 'static,
+{
+    let mut mem = Banked::new(lines);
+    mem.map(0x0000..=0xffff, ram, |_| true);
+    mem.map_read_only(0xa000..=0xbfff, basic, |lines: &PortLines| {
+        high(lines, LORAM) && high(lines, HIRAM)
+    });
+    mem.map_read_only(0xd000..=0xdfff, characters, |lines: &PortLines| {
+        (high(lines, LORAM) || high(lines, HIRAM)) && !high(lines, CHAREN)
+    });
+    mem.map(0xd000..=0xdfff, io, |lines: &PortLines| {
+        (high(lines, LORAM) || high(lines, HIRAM)) && high(lines, CHAREN)
+    });
+    mem.map_read_only(0xe000..=0xffff, kernal, |lines: &PortLines| {
+        high(lines, HIRAM)
+    });
+    mem
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::mos6502::assemble;
+    use crate::cpu::{Cpu, Mos6510};
+    use crate::mem::{Ram, Rom};
+
+    fn c64() -> Mos6510<Banked<PortLines>> {
+        let lines = PortLines::new();
+        let mut ram = Ram::zeroed(0xffff);
+        ram.fill(0xa000, 0x2000, 0x11);
+        ram.fill(0xd000, 0x1000, 0x22);
+        ram.fill(0xe000, 0x2000, 0x33);
+        let program = assemble("LDA #$36\nSTA $01\nLDA $A000\nLDX $E000", 0x1000);
+        ram.copy_from_slice(0x1000, &program.unwrap());
+        let mem = memory_map(
+            lines.clone(),
+            ram,
+            Rom::new("share/c64/basic.rom"),
+            Rom::new("share/c64/characters.rom"),
+            Ram::zeroed(0x0fff),
+            Rom::new("share/c64/kernal.rom"),
+        );
+        let mut cpu = Mos6510::with_port_lines(mem, lines);
+        cpu.step(); // RESET
+        cpu
+    }
+
+    #[test]
+    fn standard_configuration() {
+        let mut cpu = c64();
+        // $37: BASIC, I/O and KERNAL
+        assert_eq!(cpu.memory().get(0xa000), 0x94);
+        assert_eq!(cpu.memory().get(0xd000), 0x00);
+        assert_eq!(cpu.memory().get(0xe000), 0x85);
+        // Writes to ROM go to the RAM underneath, writes to I/O go to the I/O device
+        cpu.memory_mut().set(0xa000, 0x44);
+        cpu.memory_mut().set(0xd000, 0x55);
+        assert_eq!(cpu.memory().get(0xa000), 0x94);
+        assert_eq!(cpu.memory().get(0xd000), 0x55);
+        cpu.set_a(0x34);
+        cpu.set_pc(0x1002);
+        cpu.step(); // STA $01
+        assert_eq!(cpu.memory().get(0xa000), 0x44);
+        assert_eq!(cpu.memory().get(0xd000), 0x22);
+    }
+
+    #[test]
+    fn switch_basic_out() {
+        let mut cpu = c64();
+        cpu.set_pc(0x1000);
+        for _ in 0..4 {
+            cpu.step();
+        }
+        // $36: BASIC switched out, KERNAL still visible
+        assert_eq!(cpu.a(), 0x11);
+        assert_eq!(cpu.x(), 0x85);
+        assert_eq!(cpu.memory().get(0xd000), 0x00);
+    }
+
+    #[test]
+    fn switch_configurations() {
+        let mut cpu = c64();
+        let program = assemble("STA $01", 0x1000).unwrap();
+        cpu.memory_mut().copy_from_slice(0x1000, &program);
+        let switch = |cpu: &mut Mos6510<Banked<PortLines>>, value: u8| {
+            cpu.set_a(value);
+            cpu.set_pc(0x1000);
+            cpu.step();
+            [0xa000, 0xd000, 0xe000].map(|addr| cpu.memory().get(addr))
+        };
+        // Character ROM instead of I/O
+        assert_eq!(switch(&mut cpu, 0x33), [0x94, 0x3c, 0x85]);
+        // Only KERNAL
+        assert_eq!(switch(&mut cpu, 0x32), [0x11, 0x3c, 0x85]);
+        // All RAM
+        assert_eq!(switch(&mut cpu, 0x30), [0x11, 0x22, 0x33]);
+        assert_eq!(switch(&mut cpu, 0x34), [0x11, 0x22, 0x33]);
+        // Only I/O
+        assert_eq!(switch(&mut cpu, 0x35), [0x11, 0x00, 0x33]);
+    }
+}
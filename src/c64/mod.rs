@@ -0,0 +1,21 @@
+//! Commodore 64 specific hardware
+
+pub use self::crt::{Cartridge, CrtError};
+pub use self::d64::{D64Error, DirEntry, FileType, D64};
+pub use self::joystick::{ControlPort, Direction, Joystick};
+pub use self::keyboard::{Key, Keyboard};
+pub use self::keymap::KeyMap;
+pub use self::memory::memory_map;
+pub use self::screen::{render_text_screen, PALETTE, SCREEN_HEIGHT, SCREEN_WIDTH};
+pub use self::t64::{T64Error, TapeEntry, T64};
+
+pub mod rom_db;
+
+mod crt;
+mod d64;
+mod joystick;
+mod keyboard;
+mod keymap;
+mod memory;
+mod screen;
+mod t64;
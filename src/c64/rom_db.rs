@@ -0,0 +1,142 @@
+//! Known C64 ROM images
+//!
+//! Corrupted dumps or ROMs of other machines often look like emulator bugs, so ROM images can be
+//! verified against the CRC-32 checksums of known versions.
+
+use crate::mem::Rom;
+use log::warn;
+use std::{error, fmt};
+
+/// Kind of a C64 system ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RomKind {
+    /// KERNAL ROM ($E000-$FFFF)
+    Kernal,
+    /// BASIC ROM ($A000-$BFFF)
+    Basic,
+    /// Character ROM
+    Characters,
+}
+
+/// Known version of a C64 system ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RomVariant {
+    /// KERNAL revision 1 (901227-01)
+    KernalRev1,
+    /// KERNAL revision 2 (901227-02)
+    KernalRev2,
+    /// KERNAL revision 3 (901227-03)
+    KernalRev3,
+    /// JiffyDOS 6.01 KERNAL
+    KernalJiffyDos,
+    /// BASIC V2 (901226-01)
+    Basic,
+    /// Character ROM (901225-01)
+    Characters,
+}
+
+impl RomVariant {
+    /// Returns the kind of ROM this is a version of
+    pub fn kind(self) -> RomKind {
+        match self {
+            RomVariant::KernalRev1
+            | RomVariant::KernalRev2
+            | RomVariant::KernalRev3
+            | RomVariant::KernalJiffyDos => RomKind::Kernal,
+            RomVariant::Basic => RomKind::Basic,
+            RomVariant::Characters => RomKind::Characters,
+        }
+    }
+}
+
+/// CRC-32 checksums of known ROM versions
+const KNOWN_ROMS: &[(u32, RomVariant)] = &[
+    (0xdce782fa, RomVariant::KernalRev1),
+    (0xa5c687b3, RomVariant::KernalRev2),
+    (0xdbe3e7c7, RomVariant::KernalRev3),
+    (0x2f79984c, RomVariant::KernalJiffyDos),
+    (0xf833d117, RomVariant::Basic),
+    (0xec4272ee, RomVariant::Characters),
+];
+
+/// Error for a ROM image that isn't a known version of the expected kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownRom {
+    /// Expected kind of ROM
+    pub kind: RomKind,
+    /// CRC-32 checksum of the ROM image
+    pub crc32: u32,
+}
+
+impl fmt::Display for UnknownRom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown {:?} ROM (CRC-32 {:08x})", self.kind, self.crc32)
+    }
+}
+
+impl error::Error for UnknownRom {}
+
+/// Returns the known version of the given kind of ROM that the given ROM image is
+pub fn verify_known(rom: &Rom, kind: RomKind) -> Result<RomVariant, UnknownRom> {
+    let crc32 = rom.crc32();
+    KNOWN_ROMS
+        .iter()
+        .find(|&&(crc, variant)| crc == crc32 && variant.kind() == kind)
+        .map(|&(_, variant)| variant)
+        .ok_or(UnknownRom { kind, crc32 })
+}
+
+/// Check the given ROM image like `verify_known`. Unknown images are only logged unless
+/// `enforce` is set, since modified ROMs (e.g. fast loaders or patched KERNALs) are fine to use.
+pub fn check_known(
+    rom: &Rom,
+    kind: RomKind,
+    enforce: bool,
+) -> Result<Option<RomVariant>, UnknownRom> {
+    match verify_known(rom, kind) {
+        Ok(variant) => Ok(Some(variant)),
+        Err(err) if enforce => Err(err),
+        Err(err) => {
+            warn!("rom_db: {}", err);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_roms() {
+        let kernal = Rom::new("share/c64/kernal.rom");
+        assert_eq!(
+            verify_known(&kernal, RomKind::Kernal),
+            Ok(RomVariant::KernalRev3)
+        );
+        let basic = Rom::new("share/c64/basic.rom");
+        assert_eq!(verify_known(&basic, RomKind::Basic), Ok(RomVariant::Basic));
+        let characters = Rom::new("share/c64/characters.rom");
+        assert_eq!(
+            check_known(&characters, RomKind::Characters, true),
+            Ok(Some(RomVariant::Characters))
+        );
+    }
+
+    #[test]
+    fn unknown_roms() {
+        // CRC-32 of "123456789" is cbf43926
+        let rom = Rom::from_bytes(&b"123456789"[..]);
+        let err = UnknownRom {
+            kind: RomKind::Kernal,
+            crc32: 0xcbf43926,
+        };
+        assert_eq!(verify_known(&rom, RomKind::Kernal), Err(err));
+        assert_eq!(check_known(&rom, RomKind::Kernal, false), Ok(None));
+        assert_eq!(check_known(&rom, RomKind::Kernal, true), Err(err));
+        assert_eq!(err.to_string(), "Unknown Kernal ROM (CRC-32 cbf43926)");
+        // Known ROM of another kind
+        let basic = Rom::new("share/c64/basic.rom");
+        assert!(verify_known(&basic, RomKind::Kernal).is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! C64 text screen rendering
+//!
+//! Colors: https://www.pepto.de/projects/colorvic/
+
+use crate::mem::Addressable;
+
+/// Width of the text screen in characters
+const COLUMNS: usize = 40;
+/// Height of the text screen in characters
+const ROWS: usize = 25;
+/// Width of the rendered screen in pixels
+pub const SCREEN_WIDTH: usize = COLUMNS * 8;
+/// Height of the rendered screen in pixels
+pub const SCREEN_HEIGHT: usize = ROWS * 8;
+
+/// The 16 colors of the C64 as ARGB values
+pub const PALETTE: [u32; 16] = [
+    0xff000000, // black
+    0xffffffff, // white
+    0xff68372b, // red
+    0xff70a4b2, // cyan
+    0xff6f3d86, // purple
+    0xff588d43, // green
+    0xff352879, // blue
+    0xffb8c76f, // yellow
+    0xff6f4f25, // orange
+    0xff433900, // brown
+    0xff9a6759, // light red
+    0xff444444, // dark grey
+    0xff6c6c6c, // grey
+    0xff9ad284, // light green
+    0xff6c5eb5, // light blue
+    0xff959595, // light grey
+];
+
+/// Render the text screen in standard character mode into the given buffer of ARGB values
+/// (`SCREEN_WIDTH` * `SCREEN_HEIGHT` pixels, like the buffer of `ui::Screen`).
+///
+/// The screen codes are read from `mem` starting at `screen_addr` (usually $0400), the glyphs
+/// from the character set (8 bytes per screen code, e.g. the character ROM) and the foreground
+/// colors from color RAM (lower nibble). Unset pixels get the given background color
+/// (usually the value of $D021).
+pub fn render_text_screen<M, C, R>(
+    mem: &M,
+    screen_addr: u16,
+    charset: &C,
+    colors: &R,
+    background: u8,
+    buffer: &mut [u32],
+) where
+    M: Addressable,
+    C: Addressable,
+    R: Addressable,
+{
+    assert!(
+        buffer.len() >= SCREEN_WIDTH * SCREEN_HEIGHT,
+        "screen: Buffer too small ({} pixels)",
+        buffer.len()
+    );
+    let background = PALETTE[background as usize & 0x0f];
+    for row in 0..ROWS {
+        for column in 0..COLUMNS {
+            let offset = (row * COLUMNS + column) as u16;
+            let code = mem.get(screen_addr.wrapping_add(offset)) as u16;
+            let foreground = PALETTE[colors.get(offset) as usize & 0x0f];
+            for line in 0..8 {
+                let bits = charset.get(code * 8 + line as u16);
+                let start = (row * 8 + line) * SCREEN_WIDTH + column * 8;
+                for (x, pixel) in buffer[start..start + 8].iter_mut().enumerate() {
+                    *pixel = if bits & (0x80 >> x) != 0 {
+                        foreground
+                    } else {
+                        background
+                    };
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{Ram, Rom};
+
+    #[test]
+    fn render_filled_screen() {
+        let mut mem = Ram::zeroed(0xffff);
+        mem.fill(0x0400, 1000, 0x01); // screen code 1: A
+        let mut colors = Ram::zeroed(0x03ff);
+        colors.fill(0x0000, 1000, 0x0e);
+        colors.set(0x03e7, 0x01);
+        let charset = Rom::new("share/c64/characters.rom");
+        let mut buffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        render_text_screen(&mem, 0x0400, &charset, &colors, 0x06, &mut buffer);
+        // First glyph line of A is 00011000
+        let pixel = |x: usize, y: usize| buffer[y * SCREEN_WIDTH + x];
+        assert_eq!(pixel(0, 0), PALETTE[6]);
+        assert_eq!(pixel(3, 0), PALETTE[14]);
+        assert_eq!(pixel(4, 0), PALETTE[14]);
+        assert_eq!(pixel(5, 0), PALETTE[6]);
+        assert_eq!(pixel(11, 8), PALETTE[14]);
+        // Last glyph line of A is empty
+        assert_eq!(pixel(3, 7), PALETTE[6]);
+        // Last character is white
+        assert_eq!(pixel(315, 192), PALETTE[1]);
+    }
+}
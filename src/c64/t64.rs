@@ -0,0 +1,214 @@
+//! C64 tape archives (T64)
+//!
+//! File format: http://unusedino.de/ec64/technical/formats/t64.html
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::{error, fmt};
+
+/// Signature at the start of every T64 file (followed by "tape image file" or "tape file")
+const SIGNATURE: &[u8] = b"C64";
+/// Length of the header
+const HEADER_LEN: usize = 0x40;
+/// Length of a directory record
+const RECORD_LEN: usize = 0x20;
+
+/// An error that occured while reading a tape archive
+#[derive(Debug)]
+pub enum T64Error {
+    /// The archive file couldn't be read
+    Io(io::Error),
+    /// The archive doesn't start with the T64 signature
+    InvalidSignature,
+    /// The archive ends in the middle of the header, the directory or a file
+    Truncated,
+}
+
+impl fmt::Display for T64Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            T64Error::Io(ref err) => write!(f, "Unable to read tape archive: {}", err),
+            T64Error::InvalidSignature => write!(f, "Invalid T64 signature"),
+            T64Error::Truncated => write!(f, "Truncated tape archive"),
+        }
+    }
+}
+
+impl error::Error for T64Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            T64Error::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for T64Error {
+    fn from(err: io::Error) -> T64Error {
+        T64Error::Io(err)
+    }
+}
+
+/// A file in a tape archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TapeEntry {
+    /// Name of the file (PETSCII characters without padding)
+    pub name: String,
+    /// Address the file is loaded to
+    pub load_addr: u16,
+    /// Length of the file in bytes
+    pub len: usize,
+    offset: usize,
+}
+
+/// A tape archive, which holds files with their load address
+pub struct T64 {
+    name: String,
+    entries: Vec<TapeEntry>,
+    data: Vec<u8>,
+}
+
+impl T64 {
+    /// Read the tape archive from the given file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<T64, T64Error> {
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        T64::from_bytes(data)
+    }
+
+    /// Parse the given tape archive
+    pub fn from_bytes(data: Vec<u8>) -> Result<T64, T64Error> {
+        if !data.starts_with(SIGNATURE) {
+            return Err(T64Error::InvalidSignature);
+        }
+        let header = data.get(..HEADER_LEN).ok_or(T64Error::Truncated)?;
+        let used = u16::from_le_bytes([header[0x24], header[0x25]]) as usize;
+        let name = padded_name(&header[0x28..0x40]);
+        let mut entries = Vec::new();
+        for n in 0..used {
+            let offset = HEADER_LEN + n * RECORD_LEN;
+            let record = data
+                .get(offset..offset + RECORD_LEN)
+                .ok_or(T64Error::Truncated)?;
+            // Skip free records
+            if record[0] == 0 {
+                continue;
+            }
+            let load_addr = u16::from_le_bytes([record[2], record[3]]);
+            let end_addr = u16::from_le_bytes([record[4], record[5]]);
+            let offset =
+                u32::from_le_bytes([record[8], record[9], record[10], record[11]]) as usize;
+            if offset > data.len() {
+                return Err(T64Error::Truncated);
+            }
+            // Many archives were created with a wrong end address, so the length is limited to
+            // the remaining data
+            let len = (end_addr.wrapping_sub(load_addr) as usize).min(data.len() - offset);
+            entries.push(TapeEntry {
+                name: padded_name(&record[0x10..0x20]),
+                load_addr,
+                len,
+                offset,
+            });
+        }
+        Ok(T64 {
+            name,
+            entries,
+            data,
+        })
+    }
+
+    /// Returns the name of the tape
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns all files in the archive
+    pub fn entries(&self) -> &[TapeEntry] {
+        &self.entries
+    }
+
+    /// Returns the load address and contents of the file with the given index
+    pub fn read(&self, index: usize) -> Option<(u16, Vec<u8>)> {
+        self.entries.get(index).map(|entry| {
+            let data = &self.data[entry.offset..entry.offset + entry.len];
+            (entry.load_addr, data.to_vec())
+        })
+    }
+}
+
+/// Convert the given name padded with spaces (or zeros) to a string
+fn padded_name(name: &[u8]) -> String {
+    let name: String = name
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| match b {
+            0x20..=0x7e => b as char,
+            _ => '?',
+        })
+        .collect();
+    name.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive() -> Vec<u8> {
+        let mut data = b"C64S tape image file".to_vec();
+        data.resize(0x20, 0);
+        data.extend_from_slice(&[0x01, 0x01, 0x02, 0x00, 0x02, 0x00, 0x00, 0x00]);
+        data.extend_from_slice(b"TEST TAPE               ");
+        for (start, end, offset, name) in [
+            (0x0801_u16, 0x0806_u16, 0x80_u32, b"FIRST           "),
+            (0xc000, 0xc3c6, 0x85, b"SECOND          "),
+        ] {
+            data.extend_from_slice(&[0x01, 0x82]);
+            data.extend_from_slice(&start.to_le_bytes());
+            data.extend_from_slice(&end.to_le_bytes());
+            data.extend_from_slice(&[0x00, 0x00]);
+            data.extend_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(&[0x00; 4]);
+            data.extend_from_slice(name);
+        }
+        data.extend_from_slice(&[0x0b, 0x08, 0x0a, 0x00, 0x9e]);
+        data.extend_from_slice(&[0xa9, 0x00, 0x60]);
+        data
+    }
+
+    #[test]
+    fn entries() {
+        let tape = T64::from_bytes(archive()).unwrap();
+        assert_eq!(tape.name(), "TEST TAPE");
+        let entries: Vec<_> = tape
+            .entries()
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.load_addr, entry.len))
+            .collect();
+        // Wrong end address of second file is limited to the end of the archive
+        assert_eq!(entries, [("FIRST", 0x0801, 5), ("SECOND", 0xc000, 3)]);
+    }
+
+    #[test]
+    fn read() {
+        let tape = T64::from_bytes(archive()).unwrap();
+        assert_eq!(
+            tape.read(0),
+            Some((0x0801, vec![0x0b, 0x08, 0x0a, 0x00, 0x9e]))
+        );
+        assert_eq!(tape.read(1), Some((0xc000, vec![0xa9, 0x00, 0x60])));
+        assert_eq!(tape.read(2), None);
+    }
+
+    #[test]
+    fn invalid_archives() {
+        assert!(matches!(
+            T64::from_bytes(b"C128 tape".to_vec()),
+            Err(T64Error::InvalidSignature)
+        ));
+        let mut data = archive();
+        data.truncate(0x50);
+        assert!(matches!(T64::from_bytes(data), Err(T64Error::Truncated)));
+    }
+}
@@ -1,5 +1,25 @@
 //! Generic CPU handling
 
+/// Reason why running a CPU stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The cycle budget was used up
+    BudgetExhausted,
+    /// The CPU is halted (e.g. jammed by an illegal opcode) and doesn't execute instructions
+    Halted,
+    /// The next instruction is at a breakpoint
+    Breakpoint,
+}
+
+/// Outcome of running a CPU with a cycle budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// Number of cycles that were simulated
+    pub cycles: usize,
+    /// Reason why running stopped
+    pub reason: StopReason,
+}
+
 /// A generic trait for CPUs
 pub trait Cpu {
     /// Reset the CPU
@@ -8,4 +28,99 @@ pub trait Cpu {
     /// Do one step (execute the next instruction). Return the number of cycles that were
     /// simulated.
     fn step(&mut self) -> usize;
+
+    /// Interrupt the CPU (NMI)
+    fn nmi(&mut self);
+
+    /// Interrupt the CPU (IRQ)
+    fn irq(&mut self);
+
+    /// Returns the address of the next instruction to execute
+    fn pc(&self) -> u16;
+
+    /// Step until at least the given number of cycles were simulated (e.g. one frame). Since
+    /// instructions aren't interrupted, slightly more cycles may be simulated. Returns the number
+    /// of cycles that were simulated in excess, so the caller can carry them into the next run.
+    fn run_for(&mut self, cycles: usize) -> usize {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            elapsed += self.step();
+        }
+        elapsed - cycles
+    }
+
+    /// Returns true if the CPU is halted and won't execute any more instructions (until reset)
+    fn is_halted(&self) -> bool {
+        false
+    }
+
+    /// Returns true if the CPU would execute an instruction at a breakpoint in the next step
+    fn at_breakpoint(&self) -> bool {
+        false
+    }
+
+    /// Step until at least the given number of cycles were simulated, but stop early if the CPU
+    /// halts or is about to execute an instruction at a breakpoint. Like `run_for`, slightly
+    /// more cycles than budgeted may be simulated.
+    fn run(&mut self, budget: usize) -> RunOutcome {
+        let mut cycles = 0;
+        let reason = loop {
+            if self.is_halted() {
+                break StopReason::Halted;
+            }
+            if self.at_breakpoint() {
+                break StopReason::Breakpoint;
+            }
+            if cycles >= budget {
+                break StopReason::BudgetExhausted;
+            }
+            cycles += self.step();
+        };
+        RunOutcome { cycles, reason }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A CPU whose steps take 2, 3, 4, 2, 3, 4, ... cycles
+    struct FakeCpu(usize);
+
+    impl Cpu for FakeCpu {
+        fn reset(&mut self) {
+            self.0 = 0;
+        }
+
+        fn step(&mut self) -> usize {
+            self.0 += 1;
+            (self.0 - 1) % 3 + 2
+        }
+
+        fn nmi(&mut self) {}
+
+        fn irq(&mut self) {}
+
+        fn pc(&self) -> u16 {
+            self.0 as u16
+        }
+    }
+
+    #[test]
+    fn run_with_budget() {
+        let mut cpu = FakeCpu(0);
+        let outcome = cpu.run(8);
+        assert_eq!(outcome.cycles, 9); // 2 + 3 + 4
+        assert_eq!(outcome.reason, StopReason::BudgetExhausted);
+    }
+
+    #[test]
+    fn run_for_returns_overshoot() {
+        let mut cpu = FakeCpu(0);
+        assert_eq!(cpu.run_for(0), 0);
+        assert_eq!(cpu.run_for(8), 1); // 2 + 3 + 4
+        assert_eq!(cpu.0, 3);
+        assert_eq!(cpu.run_for(5), 0); // 2 + 3
+        assert_eq!(cpu.run_for(1), 3); // 4
+    }
 }
@@ -0,0 +1,59 @@
+//! Shared interrupt lines
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// An interrupt line that is shared by multiple interrupt sources (wired-OR, like the IRQ and
+/// NMI pins of a C64 CPU). Every source asserts and releases the line independently and the
+/// line is asserted as long as any source asserts it. Clones of a line refer to the same line,
+/// so every device can hold its own handle.
+#[derive(Debug, Clone, Default)]
+pub struct IrqLine(Rc<Cell<u32>>);
+
+impl IrqLine {
+    /// Create a new interrupt line that isn't asserted by any source
+    pub fn new() -> IrqLine {
+        IrqLine(Rc::new(Cell::new(0)))
+    }
+
+    /// Assert or release the line for the given source (0-31)
+    pub fn set(&self, source: u8, asserted: bool) {
+        let mask = 1 << source;
+        let sources = self.0.get();
+        self.0.set(if asserted {
+            sources | mask
+        } else {
+            sources & !mask
+        });
+    }
+
+    /// Returns true if the line is asserted by any source
+    pub fn is_asserted(&self) -> bool {
+        self.0.get() != 0
+    }
+
+    /// Returns a bitmask of the sources that currently assert the line
+    pub fn sources(&self) -> u32 {
+        self.0.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wired_or() {
+        let line = IrqLine::new();
+        let cia = line.clone();
+        let vic = line.clone();
+        assert!(!line.is_asserted());
+        cia.set(0, true);
+        vic.set(1, true);
+        assert_eq!(line.sources(), 0b11);
+        cia.set(0, false);
+        assert!(line.is_asserted());
+        vic.set(1, false);
+        assert!(!line.is_asserted());
+    }
+}
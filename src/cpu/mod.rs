@@ -1,10 +1,14 @@
 //! CPU handling
 
-pub use self::cpu::Cpu;
+pub use self::cpu::{Cpu, RunOutcome, StopReason};
+pub use self::irq_line::IrqLine;
 pub use self::mos6502::Mos6502;
-pub use self::mos6510::Mos6510;
+pub use self::mos6507::Mos6507;
+pub use self::mos6510::{Mos6510, Mos6510State, PortDecay, PortLines};
 
 #[allow(clippy::module_inception)]
 mod cpu;
-mod mos6502;
+mod irq_line;
+pub mod mos6502;
+mod mos6507;
 mod mos6510;
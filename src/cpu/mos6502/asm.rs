@@ -0,0 +1,459 @@
+//! MOS 6502 assembler
+//!
+//! A small two-pass assembler, mainly intended for writing test programs and for use in a
+//! monitor. It understands all official mnemonics with the operand syntax used by the
+//! disassembler (`#$12`, `$1234,X`, `($12),Y`, `A`, branches to absolute targets), labels
+//! (`loop:`) and the `.byte` and `.word` directives. Numbers can be given as hexadecimal (`$ff`),
+//! binary (`%1010`) or decimal (`42`). Comments start with `;`.
+
+use super::{AddressingMode, OPCODE_TABLE};
+use std::collections::HashMap;
+use std::{error, fmt};
+
+/// An error that occured while assembling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// Number of the source line that caused the error (starting at 1)
+    pub line: usize,
+    /// Kind of error
+    pub kind: AsmErrorKind,
+}
+
+/// Kind of error that occured while assembling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    /// Unknown instruction mnemonic
+    UnknownMnemonic(String),
+    /// Unknown directive
+    UnknownDirective(String),
+    /// Operand or value that can't be parsed
+    InvalidOperand(String),
+    /// Instruction doesn't support the given addressing mode
+    UnsupportedAddressingMode(String, AddressingMode),
+    /// Label that is used but never defined
+    UndefinedLabel(String),
+    /// Label that is defined more than once
+    DuplicateLabel(String),
+    /// Value that doesn't fit into the operand
+    ValueOutOfRange(u16),
+    /// Branch target that is too far away for a relative branch
+    BranchOutOfRange(u16),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            AsmErrorKind::UnknownMnemonic(ref s) => write!(f, "Unknown mnemonic ({})", s),
+            AsmErrorKind::UnknownDirective(ref s) => write!(f, "Unknown directive ({})", s),
+            AsmErrorKind::InvalidOperand(ref s) => write!(f, "Invalid operand ({})", s),
+            AsmErrorKind::UnsupportedAddressingMode(ref s, mode) => {
+                write!(f, "Unsupported addressing mode ({} {:?})", s, mode)
+            }
+            AsmErrorKind::UndefinedLabel(ref s) => write!(f, "Undefined label ({})", s),
+            AsmErrorKind::DuplicateLabel(ref s) => write!(f, "Duplicate label ({})", s),
+            AsmErrorKind::ValueOutOfRange(value) => {
+                write!(f, "Value out of range (${:04X})", value)
+            }
+            AsmErrorKind::BranchOutOfRange(target) => {
+                write!(f, "Branch target out of range (${:04X})", target)
+            }
+        }
+    }
+}
+
+impl error::Error for AsmError {}
+
+/// A value given as number or label
+#[derive(Debug, Clone)]
+enum Expr {
+    Number(u16),
+    Label(String),
+}
+
+impl Expr {
+    fn parse(s: &str) -> Option<Expr> {
+        let number = if let Some(hex) = s.strip_prefix('$') {
+            u16::from_str_radix(hex, 16).ok()
+        } else if let Some(bin) = s.strip_prefix('%') {
+            u16::from_str_radix(bin, 2).ok()
+        } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+            s.parse().ok()
+        } else if is_label(s) {
+            return Some(Expr::Label(s.to_string()));
+        } else {
+            None
+        };
+        number.map(Expr::Number)
+    }
+
+    /// Returns the value if it is already known
+    fn value(&self, labels: &HashMap<String, u16>) -> Option<u16> {
+        match *self {
+            Expr::Number(value) => Some(value),
+            Expr::Label(ref label) => labels.get(label).copied(),
+        }
+    }
+}
+
+/// Operand syntax as written in the source, before choosing an addressing mode
+#[derive(Debug)]
+enum Syntax {
+    None,
+    Accumulator,
+    Immediate(Expr),
+    Direct(Expr),
+    IndexedWithX(Expr),
+    IndexedWithY(Expr),
+    Indirect(Expr),
+    IndexedWithXIndirect(Expr),
+    IndirectIndexedWithY(Expr),
+}
+
+impl Syntax {
+    fn parse(s: &str) -> Option<Syntax> {
+        let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let upper = s.to_ascii_uppercase();
+        let expr = |end: usize| Expr::parse(&s[..end]);
+        Some(if s.is_empty() {
+            Syntax::None
+        } else if upper == "A" {
+            Syntax::Accumulator
+        } else if let Some(value) = s.strip_prefix('#') {
+            Syntax::Immediate(Expr::parse(value)?)
+        } else if let Some(inner) = s.strip_prefix('(') {
+            let inner_upper = &upper[1..];
+            if inner_upper.ends_with(",X)") {
+                Syntax::IndexedWithXIndirect(Expr::parse(&inner[..inner.len() - 3])?)
+            } else if inner_upper.ends_with("),Y") {
+                Syntax::IndirectIndexedWithY(Expr::parse(&inner[..inner.len() - 3])?)
+            } else {
+                Syntax::Indirect(Expr::parse(inner.strip_suffix(')')?)?)
+            }
+        } else if upper.ends_with(",X") {
+            Syntax::IndexedWithX(expr(s.len() - 2)?)
+        } else if upper.ends_with(",Y") {
+            Syntax::IndexedWithY(expr(s.len() - 2)?)
+        } else {
+            Syntax::Direct(Expr::parse(&s)?)
+        })
+    }
+}
+
+/// A statement whose size is known after the first pass
+enum Statement {
+    Instruction {
+        opcode: u8,
+        mode: AddressingMode,
+        expr: Option<Expr>,
+    },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+/// Returns true if the given string is a valid label name
+fn is_label(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Returns the opcode of the given mnemonic with the given addressing mode
+fn find_opcode(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    OPCODE_TABLE
+        .iter()
+        .position(|info| {
+            info.is_some_and(|info| info.mode == mode && info.instruction.to_string() == mnemonic)
+        })
+        .map(|opcode| opcode as u8)
+}
+
+/// Returns true if the given mnemonic is an official instruction
+fn is_mnemonic(mnemonic: &str) -> bool {
+    OPCODE_TABLE
+        .iter()
+        .flatten()
+        .any(|info| info.instruction.to_string() == mnemonic)
+}
+
+/// Choose the opcode and addressing mode for the given mnemonic and operand syntax. Zero page
+/// addressing is used if the value is already known and fits, absolute addressing otherwise.
+fn choose_opcode(
+    mnemonic: &str,
+    syntax: Syntax,
+    labels: &HashMap<String, u16>,
+) -> Result<(u8, AddressingMode, Option<Expr>), AsmErrorKind> {
+    use self::AddressingMode::*;
+    let (modes, expr): (&[AddressingMode], _) = match syntax {
+        Syntax::None => (&[Implied, Accumulator], None),
+        Syntax::Accumulator => (&[Accumulator], None),
+        Syntax::Immediate(expr) => (&[Immediate], Some(expr)),
+        Syntax::Direct(expr) if find_opcode(mnemonic, Relative).is_some() => {
+            (&[Relative], Some(expr))
+        }
+        Syntax::Direct(expr) => match expr.value(labels) {
+            Some(value) if value <= 0xff => (&[ZeroPage, Absolute], Some(expr)),
+            _ => (&[Absolute], Some(expr)),
+        },
+        Syntax::IndexedWithX(expr) => match expr.value(labels) {
+            Some(value) if value <= 0xff => {
+                (&[ZeroPageIndexedWithX, AbsoluteIndexedWithX], Some(expr))
+            }
+            _ => (&[AbsoluteIndexedWithX], Some(expr)),
+        },
+        Syntax::IndexedWithY(expr) => match expr.value(labels) {
+            Some(value) if value <= 0xff => {
+                (&[ZeroPageIndexedWithY, AbsoluteIndexedWithY], Some(expr))
+            }
+            _ => (&[AbsoluteIndexedWithY], Some(expr)),
+        },
+        Syntax::Indirect(expr) => (&[Indirect], Some(expr)),
+        Syntax::IndexedWithXIndirect(expr) => (&[ZeroPageIndexedWithXIndirect], Some(expr)),
+        Syntax::IndirectIndexedWithY(expr) => (&[ZeroPageIndirectIndexedWithY], Some(expr)),
+    };
+    modes
+        .iter()
+        .find_map(|&mode| find_opcode(mnemonic, mode).map(|opcode| (opcode, mode, expr.clone())))
+        .ok_or_else(|| AsmErrorKind::UnsupportedAddressingMode(mnemonic.to_string(), modes[0]))
+}
+
+/// Parse a comma separated list of values
+fn parse_list(s: &str) -> Result<Vec<Expr>, AsmErrorKind> {
+    s.split(',')
+        .map(|value| {
+            let value = value.trim();
+            Expr::parse(value).ok_or_else(|| AsmErrorKind::InvalidOperand(value.to_string()))
+        })
+        .collect()
+}
+
+/// Assemble the given source code for the given origin address. Returns the machine code.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, AsmError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+
+    // First pass: parse lines, define labels and determine the size of every statement
+    let mut pc = origin;
+    for (n, line) in source.lines().enumerate() {
+        let line_nr = n + 1;
+        let err = |kind| AsmError {
+            line: line_nr,
+            kind,
+        };
+        let mut line = line.split(';').next().unwrap_or("").trim();
+        while let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(err(AsmErrorKind::InvalidOperand(label.to_string())));
+            }
+            if labels.insert(label.to_string(), pc).is_some() {
+                return Err(err(AsmErrorKind::DuplicateLabel(label.to_string())));
+            }
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            continue;
+        }
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let statement = if let Some(directive) = word.strip_prefix('.') {
+            match directive.to_ascii_lowercase().as_str() {
+                "byte" => Statement::Bytes(parse_list(rest).map_err(err)?),
+                "word" => Statement::Words(parse_list(rest).map_err(err)?),
+                _ => return Err(err(AsmErrorKind::UnknownDirective(word.to_string()))),
+            }
+        } else {
+            let mnemonic = word.to_ascii_uppercase();
+            if !is_mnemonic(&mnemonic) {
+                return Err(err(AsmErrorKind::UnknownMnemonic(word.to_string())));
+            }
+            let syntax = Syntax::parse(rest)
+                .ok_or_else(|| err(AsmErrorKind::InvalidOperand(rest.trim().to_string())))?;
+            let (opcode, mode, expr) = choose_opcode(&mnemonic, syntax, &labels).map_err(err)?;
+            Statement::Instruction { opcode, mode, expr }
+        };
+        let len = match statement {
+            Statement::Instruction { mode, .. } => mode.len() as u16,
+            Statement::Bytes(ref values) => values.len() as u16,
+            Statement::Words(ref values) => values.len() as u16 * 2,
+        };
+        statements.push((line_nr, pc, statement));
+        pc = pc.wrapping_add(len);
+    }
+
+    // Second pass: resolve values and emit code
+    let mut code = Vec::new();
+    for (line, pc, statement) in statements {
+        let err = |kind| AsmError { line, kind };
+        let resolve = |expr: &Expr| match *expr {
+            Expr::Number(value) => Ok(value),
+            Expr::Label(ref label) => labels
+                .get(label)
+                .copied()
+                .ok_or_else(|| err(AsmErrorKind::UndefinedLabel(label.clone()))),
+        };
+        let byte =
+            |value: u16| u8::try_from(value).map_err(|_| err(AsmErrorKind::ValueOutOfRange(value)));
+        match statement {
+            Statement::Instruction { opcode, mode, expr } => {
+                code.push(opcode);
+                let value = match expr {
+                    Some(ref expr) => resolve(expr)?,
+                    None => continue,
+                };
+                if mode == AddressingMode::Relative {
+                    let offset = value as i32 - pc.wrapping_add(2) as i32;
+                    let offset = i8::try_from(offset)
+                        .map_err(|_| err(AsmErrorKind::BranchOutOfRange(value)))?;
+                    code.push(offset as u8);
+                } else if mode.len() == 2 {
+                    code.push(byte(value)?);
+                } else {
+                    code.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Statement::Bytes(values) => {
+                for expr in &values {
+                    code.push(byte(resolve(expr)?)?);
+                }
+            }
+            Statement::Words(values) => {
+                for expr in &values {
+                    code.extend_from_slice(&resolve(expr)?.to_le_bytes());
+                }
+            }
+        }
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::disassemble;
+    use super::*;
+    use crate::mem::{Addressable, Ram};
+
+    #[test]
+    fn addressing_modes() {
+        let source = "
+            NOP
+            ASL
+            ROL A
+            LDA #$12
+            LDA $12
+            LDA $12,X
+            LDX $12,Y
+            LDA $1234
+            LDA $1234,X
+            LDA $12,Y       ; no zero page mode, uses absolute
+            JMP ($1234)
+            LDA ($12,X)
+            lda ($12),y
+        ";
+        assert_eq!(
+            assemble(source, 0x1000).unwrap(),
+            [
+                0xea, 0x0a, 0x2a, 0xa9, 0x12, 0xa5, 0x12, 0xb5, 0x12, 0xb6, 0x12, 0xad, 0x34, 0x12,
+                0xbd, 0x34, 0x12, 0xb9, 0x12, 0x00, 0x6c, 0x34, 0x12, 0xa1, 0x12, 0xb1, 0x12,
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_and_directives() {
+        let source = "
+            .byte 1, $ff, %1010
+            .word $1234, start
+            start: RTS
+        ";
+        assert_eq!(
+            assemble(source, 0xc000).unwrap(),
+            [0x01, 0xff, 0x0a, 0x34, 0x12, 0x07, 0xc0, 0x60]
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let source = "
+            start:  LDX #$08
+            loop:   DEX
+                    BNE loop
+                    BEQ done
+                    JMP start
+            done:   RTS
+        ";
+        assert_eq!(
+            assemble(source, 0x1000).unwrap(),
+            [0xa2, 0x08, 0xca, 0xd0, 0xfd, 0xf0, 0x03, 0x4c, 0x00, 0x10, 0x60]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| assemble(source, 0x1000).unwrap_err();
+        assert_eq!(
+            error("NOP\nFOO"),
+            AsmError {
+                line: 2,
+                kind: AsmErrorKind::UnknownMnemonic("FOO".to_string())
+            }
+        );
+        assert_eq!(
+            error("JMP nowhere").kind,
+            AsmErrorKind::UndefinedLabel("nowhere".to_string())
+        );
+        assert_eq!(
+            error("a: NOP\na: NOP").kind,
+            AsmErrorKind::DuplicateLabel("a".to_string())
+        );
+        assert_eq!(
+            error("STA #$12").kind,
+            AsmErrorKind::UnsupportedAddressingMode("STA".to_string(), AddressingMode::Immediate)
+        );
+        assert_eq!(
+            error("LDA #$123").kind,
+            AsmErrorKind::ValueOutOfRange(0x0123)
+        );
+        assert_eq!(
+            error("LDA $12,Z").kind,
+            AsmErrorKind::InvalidOperand("$12,Z".to_string())
+        );
+        assert_eq!(
+            error("NOP\nBNE $1083").kind,
+            AsmErrorKind::BranchOutOfRange(0x1083)
+        );
+        assert_eq!(
+            error("BNE $1081\nBNE $0f83").kind,
+            AsmErrorKind::BranchOutOfRange(0x0f83)
+        );
+        assert_eq!(
+            error("BNE $1081\nBNE $0f84\nBNE").kind,
+            AsmErrorKind::UnsupportedAddressingMode("BNE".to_string(), AddressingMode::Implied)
+        );
+    }
+
+    #[test]
+    fn round_trip_with_disassembler() {
+        let source = [
+            "LDA #$0A",
+            "STA $D020",
+            "LDY $FB",
+            "LDA ($FB),Y",
+            "STA $0400,X",
+            "INX",
+            "BNE $C000",
+            "JMP ($0314)",
+        ];
+        let code = assemble(&source.join("\n"), 0xc000).unwrap();
+        let mut mem = Ram::zeroed(0xffff);
+        mem.copy_from_slice(0xc000, &code);
+        let lines = disassemble(&mem, 0xc000..0xc000 + code.len() as u16);
+        let disassembled: Vec<String> = lines
+            .iter()
+            .map(|line| {
+                format!("{} {}", line.mnemonic, line.operand)
+                    .trim()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(disassembled, source);
+    }
+}
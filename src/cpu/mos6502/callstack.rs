@@ -0,0 +1,114 @@
+//! MOS 6502 subroutine call tracking
+
+use super::InterruptKind;
+use crate::addr::Address;
+use std::fmt;
+
+/// How a call frame was entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// Subroutine call (JSR instruction)
+    Subroutine,
+    /// Interrupt sequence (including BRK)
+    Interrupt(InterruptKind),
+}
+
+/// A subroutine call or interrupt that hasn't returned yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    /// Address where execution continues after returning
+    pub return_addr: u16,
+    /// Address of the called subroutine or interrupt handler
+    pub target: u16,
+    /// Stack pointer after pushing the return address (and status for interrupts)
+    pub sp: u8,
+    /// How the frame was entered
+    pub kind: CallKind,
+}
+
+impl fmt::Display for CallFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            CallKind::Subroutine => "JSR",
+            CallKind::Interrupt(InterruptKind::Nmi) => "NMI",
+            CallKind::Interrupt(InterruptKind::Irq) => "IRQ",
+            CallKind::Interrupt(InterruptKind::Brk) => "BRK",
+        };
+        write!(
+            f,
+            "{} {}  return {}  SP:{:02X}",
+            kind,
+            self.target.display(),
+            self.return_addr.display(),
+            self.sp,
+        )
+    }
+}
+
+/// Stack of active call frames. Disabled by default.
+///
+/// Programs often manipulate the stack manually (e.g. dropping a return address to leave a
+/// routine early, or pushing an address and using RTS as a computed jump), so frames are kept
+/// in sync with the stack pointer: frames whose stack contents were discarded are dropped, and
+/// returns that don't match the innermost frame don't pop anything.
+#[derive(Debug, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+    enabled: bool,
+}
+
+impl CallStack {
+    /// Enable or disable tracking. Disabling discards all frames.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.frames.clear();
+        }
+    }
+
+    /// Record an entered call frame
+    pub fn enter(&mut self, frame: CallFrame) {
+        if self.enabled {
+            self.discard_below(frame.sp.wrapping_add(1));
+            self.frames.push(frame);
+        }
+    }
+
+    /// Record a return (RTS or RTI) with the given stack pointer before popping the return
+    /// address
+    pub fn leave(&mut self, sp: u8) {
+        if self.enabled {
+            self.discard_below(sp);
+            if self.frames.last().is_some_and(|frame| frame.sp == sp) {
+                self.frames.pop();
+            }
+        }
+    }
+
+    /// Discard all frames since the stack was reset
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Returns the active frames, outermost first
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    /// Drop frames whose stack contents are below the given stack pointer, i.e. have already
+    /// been discarded from the stack
+    fn discard_below(&mut self, sp: u8) {
+        while self.frames.last().is_some_and(|frame| frame.sp < sp) {
+            self.frames.pop();
+        }
+    }
+}
+
+impl fmt::Display for CallStack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for frame in self.frames.iter().rev() {
+            writeln!(f, "{}", frame)?;
+        }
+        Ok(())
+    }
+}
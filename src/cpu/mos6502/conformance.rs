@@ -0,0 +1,225 @@
+//! MOS 6502 ALU conformance tests
+//!
+//! Runs the real instruction implementations against a tiny, obviously-correct reference model
+//! for every combination of accumulator (or index register), operand and carry. Only binary mode
+//! is covered since decimal mode isn't supported yet.
+
+use super::{Instruction, Mos6502, Operand, StatusFlags};
+use crate::mem::{Addressable, Ram};
+use std::ops::RangeInclusive;
+
+/// Zero page address used for operations that need a memory operand
+const ZP: u8 = 0x80;
+
+/// Register that an operation reads its first input from and writes its result to
+#[derive(Clone, Copy)]
+enum Reg {
+    AC,
+    X,
+    Y,
+    Mem,
+}
+
+/// Result of an ALU operation: value of the target register and status flags
+type Outcome = (u8, StatusFlags);
+
+/// Reference model of an ALU operation: takes input, operand and status, returns the outcome
+type Model = fn(u8, u8, StatusFlags) -> Outcome;
+
+/// Set ZERO_FLAG and NEGATIVE_FLAG based on the given value
+fn zn(mut sr: StatusFlags, value: u8) -> StatusFlags {
+    sr.set(StatusFlags::ZERO_FLAG, value == 0);
+    sr.set(StatusFlags::NEGATIVE_FLAG, value & 0x80 != 0);
+    sr
+}
+
+fn adc(a: u8, m: u8, mut sr: StatusFlags) -> Outcome {
+    let c = sr.contains(StatusFlags::CARRY_FLAG) as u16;
+    let sum = a as u16 + m as u16 + c;
+    let r = sum as u8;
+    sr.set(StatusFlags::CARRY_FLAG, sum > 0xff);
+    // Overflow if both inputs have the same sign and the result has a different sign
+    sr.set(StatusFlags::OVERFLOW_FLAG, (a ^ r) & (m ^ r) & 0x80 != 0);
+    (r, zn(sr, r))
+}
+
+fn sbc(a: u8, m: u8, sr: StatusFlags) -> Outcome {
+    // Subtraction is addition of the one's complement in binary mode
+    adc(a, !m, sr)
+}
+
+fn cmp(a: u8, m: u8, mut sr: StatusFlags) -> Outcome {
+    sr.set(StatusFlags::CARRY_FLAG, a >= m);
+    (a, zn(sr, a.wrapping_sub(m)))
+}
+
+fn and(a: u8, m: u8, sr: StatusFlags) -> Outcome {
+    (a & m, zn(sr, a & m))
+}
+
+fn ora(a: u8, m: u8, sr: StatusFlags) -> Outcome {
+    (a | m, zn(sr, a | m))
+}
+
+fn eor(a: u8, m: u8, sr: StatusFlags) -> Outcome {
+    (a ^ m, zn(sr, a ^ m))
+}
+
+fn bit(a: u8, m: u8, mut sr: StatusFlags) -> Outcome {
+    sr.set(StatusFlags::ZERO_FLAG, a & m == 0);
+    sr.set(StatusFlags::NEGATIVE_FLAG, m & 0x80 != 0);
+    sr.set(StatusFlags::OVERFLOW_FLAG, m & 0x40 != 0);
+    (a, sr)
+}
+
+fn asl(a: u8, _m: u8, mut sr: StatusFlags) -> Outcome {
+    sr.set(StatusFlags::CARRY_FLAG, a & 0x80 != 0);
+    (a << 1, zn(sr, a << 1))
+}
+
+fn lsr(a: u8, _m: u8, mut sr: StatusFlags) -> Outcome {
+    sr.set(StatusFlags::CARRY_FLAG, a & 0x01 != 0);
+    (a >> 1, zn(sr, a >> 1))
+}
+
+fn rol(a: u8, _m: u8, mut sr: StatusFlags) -> Outcome {
+    let r = (a << 1) | sr.contains(StatusFlags::CARRY_FLAG) as u8;
+    sr.set(StatusFlags::CARRY_FLAG, a & 0x80 != 0);
+    (r, zn(sr, r))
+}
+
+fn ror(a: u8, _m: u8, mut sr: StatusFlags) -> Outcome {
+    let r = (a >> 1) | ((sr.contains(StatusFlags::CARRY_FLAG) as u8) << 7);
+    sr.set(StatusFlags::CARRY_FLAG, a & 0x01 != 0);
+    (r, zn(sr, r))
+}
+
+fn inc(a: u8, _m: u8, sr: StatusFlags) -> Outcome {
+    (a.wrapping_add(1), zn(sr, a.wrapping_add(1)))
+}
+
+fn dec(a: u8, _m: u8, sr: StatusFlags) -> Outcome {
+    (a.wrapping_sub(1), zn(sr, a.wrapping_sub(1)))
+}
+
+/// Run an instruction against the reference model for all inputs, the given operands and
+/// initial carry and overflow flags. Panics with the first mismatch found.
+fn check(
+    instruction: Instruction,
+    reg: Reg,
+    operands: RangeInclusive<u8>,
+    operand: impl Fn(u8) -> Operand,
+    model: Model,
+) {
+    let mut cpu = Mos6502::new(Ram::with_capacity(0x01ff));
+    for input in 0..=255_u8 {
+        for m in operands.clone() {
+            for flags in 0..4 {
+                let mut sr = StatusFlags::UNUSED_ALWAYS_ON_FLAG;
+                sr.set(StatusFlags::CARRY_FLAG, flags & 1 != 0);
+                sr.set(StatusFlags::OVERFLOW_FLAG, flags & 2 != 0);
+                cpu.sr = sr;
+                cpu.ac = 0;
+                cpu.x = 0;
+                cpu.y = 0;
+                match reg {
+                    Reg::AC => cpu.ac = input,
+                    Reg::X => cpu.x = input,
+                    Reg::Y => cpu.y = input,
+                    Reg::Mem => cpu.mem.set(ZP as u16, input),
+                }
+                instruction.execute(&mut cpu, &operand(m));
+                let actual = match reg {
+                    Reg::AC => cpu.ac,
+                    Reg::X => cpu.x,
+                    Reg::Y => cpu.y,
+                    Reg::Mem => cpu.mem.get(ZP as u16),
+                };
+                let (expected, expected_sr) = model(input, m, sr);
+                assert!(
+                    actual == expected && cpu.sr == expected_sr,
+                    "{} with input #${:02X}, operand #${:02X}, NV-BDIZC:{:08b}: \
+                     expected #${:02X} NV-BDIZC:{:08b}, got #${:02X} NV-BDIZC:{:08b}",
+                    instruction,
+                    input,
+                    m,
+                    sr.bits(),
+                    expected,
+                    expected_sr.bits(),
+                    actual,
+                    cpu.sr.bits(),
+                );
+            }
+        }
+    }
+}
+
+/// Check an instruction that combines a register with an immediate operand
+fn check_binary(instruction: Instruction, reg: Reg, model: Model) {
+    check(instruction, reg, 0..=255, Operand::Immediate, model);
+}
+
+/// Check an instruction that only modifies a single register or memory location
+fn check_unary(instruction: Instruction, reg: Reg, model: Model) {
+    let operand = |_| match reg {
+        Reg::AC => Operand::Accumulator,
+        Reg::X | Reg::Y => Operand::Implied,
+        Reg::Mem => Operand::ZeroPage(ZP),
+    };
+    check(instruction, reg, 0..=0, operand, model);
+}
+
+#[test]
+fn adc_binary() {
+    check_binary(Instruction::ADC, Reg::AC, adc);
+}
+
+#[test]
+fn sbc_binary() {
+    check_binary(Instruction::SBC, Reg::AC, sbc);
+}
+
+#[test]
+fn compare() {
+    check_binary(Instruction::CMP, Reg::AC, cmp);
+    check_binary(Instruction::CPX, Reg::X, cmp);
+    check_binary(Instruction::CPY, Reg::Y, cmp);
+}
+
+#[test]
+fn logical() {
+    check_binary(Instruction::AND, Reg::AC, and);
+    check_binary(Instruction::ORA, Reg::AC, ora);
+    check_binary(Instruction::EOR, Reg::AC, eor);
+}
+
+#[test]
+fn bit_test() {
+    check_binary(Instruction::BIT, Reg::AC, bit);
+}
+
+#[test]
+fn shifts_on_accumulator() {
+    check_unary(Instruction::ASL, Reg::AC, asl);
+    check_unary(Instruction::LSR, Reg::AC, lsr);
+    check_unary(Instruction::ROL, Reg::AC, rol);
+    check_unary(Instruction::ROR, Reg::AC, ror);
+}
+
+#[test]
+fn shifts_on_memory() {
+    check_unary(Instruction::ASL, Reg::Mem, asl);
+    check_unary(Instruction::LSR, Reg::Mem, lsr);
+    check_unary(Instruction::ROL, Reg::Mem, rol);
+    check_unary(Instruction::ROR, Reg::Mem, ror);
+}
+
+#[test]
+fn increments_and_decrements() {
+    check_unary(Instruction::INC, Reg::Mem, inc);
+    check_unary(Instruction::DEC, Reg::Mem, dec);
+    check_unary(Instruction::INX, Reg::X, inc);
+    check_unary(Instruction::INY, Reg::Y, inc);
+    check_unary(Instruction::DEX, Reg::X, dec);
+    check_unary(Instruction::DEY, Reg::Y, dec);
+}
@@ -0,0 +1,248 @@
+//! MOS 6502 disassembler
+
+use super::{Instruction, Operand};
+use crate::addr::Address;
+use crate::mem::Addressable;
+use std::fmt;
+use std::ops::Range;
+
+/// Maximum length of an instruction in bytes
+const MAX_INSTRUCTION_LEN: usize = 3;
+
+/// A single line of a disassembly listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledLine {
+    /// Address of the first byte
+    pub addr: u16,
+    /// Raw bytes of the instruction
+    pub bytes: Vec<u8>,
+    /// Mnemonic of the instruction (`.byte` for data that isn't a valid instruction)
+    pub mnemonic: String,
+    /// Formatted operand (empty for implied operands). Branch targets are shown as absolute
+    /// addresses.
+    pub operand: String,
+}
+
+impl DisassembledLine {
+    /// Disassemble the instruction at the given address. At most `limit` bytes are read; if the
+    /// instruction is illegal or longer than that, a single data byte is emitted instead.
+    fn decode<M: Addressable>(mem: &M, addr: u16, limit: usize) -> DisassembledLine {
+        let window = Window {
+            mem,
+            start: addr,
+            len: limit,
+        };
+        match Instruction::decode(&window, addr) {
+            Some((instruction, operand, len, _)) if len as usize <= limit => {
+                let bytes = (0..len as u16)
+                    .map(|i| mem.get(addr.wrapping_add(i)))
+                    .collect();
+                let operand = match operand {
+                    Operand::Relative(offset) => addr
+                        .wrapping_add(len as u16)
+                        .offset(offset as i16)
+                        .display()
+                        .to_string(),
+                    operand => operand.to_string(),
+                };
+                DisassembledLine {
+                    addr,
+                    bytes,
+                    mnemonic: instruction.to_string(),
+                    operand,
+                }
+            }
+            _ => {
+                let byte = mem.get(addr);
+                DisassembledLine {
+                    addr,
+                    bytes: vec![byte],
+                    mnemonic: ".byte".to_string(),
+                    operand: format!("${:02X}", byte),
+                }
+            }
+        }
+    }
+
+    /// Returns the number of bytes this line covers
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns true if this line doesn't cover any bytes (which never happens for lines created
+    /// by the disassembler)
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+impl fmt::Display for DisassembledLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        write!(
+            f,
+            "{:04X}  {:<8}  {}",
+            self.addr,
+            bytes.join(" "),
+            self.mnemonic
+        )?;
+        if !self.operand.is_empty() {
+            write!(f, " {}", self.operand)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read-only view of memory that hides everything outside of the given number of bytes from the
+/// start address. Used to prevent decoding from reading beyond the end of the disassembled range.
+struct Window<'a, M> {
+    mem: &'a M,
+    start: u16,
+    len: usize,
+}
+
+impl<M: Addressable> Addressable for Window<'_, M> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        let addr = addr.to_u16();
+        if (addr.wrapping_sub(self.start) as usize) < self.len {
+            self.mem.get(addr)
+        } else {
+            0
+        }
+    }
+
+    fn set<A: Address>(&mut self, _addr: A, _data: u8) {
+        unreachable!("disassembler: Write during decoding");
+    }
+}
+
+/// Disassemble all instructions in the given address range. Illegal opcodes and instructions
+/// that would extend beyond the end of the range are emitted as `.byte` lines, so every byte of
+/// the range is covered by exactly one line.
+pub fn disassemble<M: Addressable>(mem: &M, range: Range<u16>) -> Vec<DisassembledLine> {
+    let mut lines = Vec::new();
+    let mut addr = range.start;
+    while addr < range.end {
+        let line = DisassembledLine::decode(mem, addr, (range.end - addr) as usize);
+        addr += line.len() as u16;
+        lines.push(line);
+    }
+    lines
+}
+
+/// Disassemble all instructions from the start address up to (but not including) the end address
+/// and return a monitor-style listing with one line per instruction, like
+/// `$1000  A9 42     LDA #$42`. See `disassemble` for how illegal opcodes and the end of the
+/// range are handled.
+pub fn disassemble_range<M: Addressable>(mem: &M, start: u16, end: u16) -> String {
+    disassemble(mem, start..end)
+        .iter()
+        .map(|line| format!("${}\n", line))
+        .collect()
+}
+
+/// Disassemble the given number of instructions starting at the given address. Illegal opcodes
+/// are emitted as `.byte` lines (and count as an instruction). Wraps around at the end of the
+/// address space.
+pub fn disassemble_count<M: Addressable>(
+    mem: &M,
+    addr: u16,
+    count: usize,
+) -> Vec<DisassembledLine> {
+    let mut lines = Vec::with_capacity(count);
+    let mut addr = addr;
+    for _ in 0..count {
+        let line = DisassembledLine::decode(mem, addr, MAX_INSTRUCTION_LEN);
+        addr = addr.wrapping_add(line.len() as u16);
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Ram;
+
+    fn listing(lines: &[DisassembledLine]) -> String {
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        lines.join("\n")
+    }
+
+    fn memory() -> Ram {
+        let mut mem = Ram::zeroed(0xffff);
+        mem.copy_from_slice(
+            0xc000,
+            &[
+                0xa9, 0x0a, // LDA #$0A
+                0x8d, 0x20, 0xd0, // STA $D020
+                0x02, // illegal
+                0xe8, // INX
+                0xd0, 0xf7, // BNE $C000
+                0x4c, 0x00, 0xc0, // JMP $C000
+            ],
+        );
+        mem
+    }
+
+    #[test]
+    fn mixed_sequence() {
+        let mem = memory();
+        assert_eq!(
+            listing(&disassemble(&mem, 0xc000..0xc00c)),
+            "C000  A9 0A     LDA #$0A\n\
+             C002  8D 20 D0  STA $D020\n\
+             C005  02        .byte $02\n\
+             C006  E8        INX\n\
+             C007  D0 F7     BNE $C000\n\
+             C009  4C 00 C0  JMP $C000"
+        );
+    }
+
+    #[test]
+    fn instruction_straddling_end_of_range() {
+        let mem = memory();
+        let lines = disassemble(&mem, 0xc000..0xc004);
+        assert_eq!(
+            listing(&lines),
+            "C000  A9 0A     LDA #$0A\n\
+             C002  8D        .byte $8D\n\
+             C003  20        .byte $20"
+        );
+        assert_eq!(lines.iter().map(DisassembledLine::len).sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn listing_of_range() {
+        let mut mem = Ram::zeroed(0xffff);
+        mem.copy_from_slice(
+            0x1000,
+            &[
+                0xa9, 0x42, // LDA #$42
+                0x9d, 0x00, 0x04, // STA $0400,X
+                0xff, // illegal
+                0x6c, 0xfc, 0xff, // JMP ($FFFC)
+            ],
+        );
+        assert_eq!(
+            disassemble_range(&mem, 0x1000, 0x1008),
+            "$1000  A9 42     LDA #$42\n\
+             $1002  9D 00 04  STA $0400,X\n\
+             $1005  FF        .byte $FF\n\
+             $1006  6C        .byte $6C\n\
+             $1007  FC        .byte $FC\n"
+        );
+    }
+
+    #[test]
+    fn count_instructions() {
+        let mem = memory();
+        let lines = disassemble_count(&mem, 0xc002, 3);
+        assert_eq!(
+            listing(&lines),
+            "C002  8D 20 D0  STA $D020\n\
+             C005  02        .byte $02\n\
+             C006  E8        INX"
+        );
+    }
+}
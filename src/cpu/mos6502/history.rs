@@ -0,0 +1,51 @@
+//! MOS 6502 instruction history
+
+use super::TraceLine;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Ring buffer of the last executed instructions with the registers after executing them.
+/// Disabled (capacity 0) by default.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<TraceLine>,
+    capacity: usize,
+}
+
+impl History {
+    /// Returns true if instructions should be recorded
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Set the number of instructions to keep. A capacity of 0 disables recording.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+        self.entries.shrink_to(capacity);
+    }
+
+    /// Record an executed instruction, dropping the oldest one if the buffer is full
+    pub fn push(&mut self, entry: TraceLine) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the recorded instructions, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &TraceLine> {
+        self.entries.iter()
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
@@ -1,87 +1,417 @@
 //! MOS 6502 Instruction set
 
-use super::{Mos6502, Operand, StatusFlags, IRQ_VECTOR};
-use crate::addr::Address;
+use super::{
+    AddressingMode, CallFrame, CallKind, InterruptKind, Mos6502, Operand, ResolvedOperand,
+    StatusFlags, IRQ_VECTOR,
+};
+use crate::addr::{Address, Integer};
 use crate::mem::Addressable;
 use log::debug;
-use std::fmt;
+use std::{fmt, mem};
 
 /// Processor instructions
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(clippy::upper_case_acronyms)]
 pub enum Instruction {
     // Load/store operations
+    /// Load accumulator
     LDA,
+    /// Load X register
     LDX,
+    /// Load Y register
     LDY,
+    /// Store accumulator
     STA,
+    /// Store X register
     STX,
+    /// Store Y register
     STY,
     // Register transfers
+    /// Transfer accumulator to X
     TAX,
+    /// Transfer accumulator to Y
     TAY,
+    /// Transfer X to accumulator
     TXA,
+    /// Transfer Y to accumulator
     TYA,
     // Stack operations
+    /// Transfer stack pointer to X
     TSX,
+    /// Transfer X to stack pointer
     TXS,
+    /// Push accumulator on stack
     PHA,
+    /// Push processor status (SR) on stack
     PHP,
+    /// Pull accumulator from stack
     PLA,
+    /// Pull processor status (SR) from stack
     PLP,
     // Logical
+    /// Logical AND
     AND,
+    /// Logical exclusive OR
     EOR,
+    /// Logical inclusive OR
     ORA,
+    /// Bit test
     BIT,
     // Arithmetic
+    /// Add with carry
     ADC,
+    /// Subtract with carry
     SBC,
+    /// Compare (with accumulator)
     CMP,
+    /// Compare with X register
     CPX,
+    /// Compare with Y register
     CPY,
     // Increments & decrements
+    /// Increment a memory location
     INC,
+    /// Increment X register
     INX,
+    /// Increment Y register
     INY,
+    /// Decrement a memory location
     DEC,
+    /// Decrement X register
     DEX,
+    /// Decrement Y register
     DEY,
     // Shifts
+    /// Arithmetic shift left
     ASL,
+    /// Logical shift right
     LSR,
+    /// Rotate left
     ROL,
+    /// Rotate right
     ROR,
     // Jump & calls
+    /// Jump to another location
     JMP,
+    /// Jump to a subroutine
     JSR,
+    /// Return from subroutine
     RTS,
     // Branches
+    /// Branch if carry flag clear
     BCC,
+    /// Branch if carry flag set
     BCS,
+    /// Branch if zero flag set
     BEQ,
+    /// Branch if negative flag set
     BMI,
+    /// Branch if zero flag clear
     BNE,
+    /// Branch if negative flag clear
     BPL,
+    /// Branch if overflow flag clear
     BVC,
+    /// Branch if overflow flag set
     BVS,
     // Status flag changes
+    /// Clear carry flag
     CLC,
+    /// Clear decimal mode flag
     CLD,
+    /// Clear interrupt disable flag
     CLI,
+    /// Clear overflow flag
     CLV,
+    /// Set carry flag
     SEC,
+    /// Set decimal mode flag
     SED,
+    /// Set interrupt disable flag
     SEI,
     // System functions
+    /// Force an interrupt
     BRK,
+    /// No operation
     NOP,
+    /// Return from interrupt
     RTI,
 }
 
+/// Static information about an opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    /// Instruction the opcode executes
+    pub instruction: Instruction,
+    /// Addressing mode of the operand
+    pub mode: AddressingMode,
+    /// Length of the instruction in bytes (including the opcode)
+    pub len: u8,
+    /// Number of cycles (without penalties for page crossing and taken branches)
+    pub cycles: usize,
+}
+
+const fn op(instruction: Instruction, mode: AddressingMode, cycles: usize) -> Option<OpcodeInfo> {
+    Some(OpcodeInfo {
+        instruction,
+        mode,
+        len: mode.len(),
+        cycles,
+    })
+}
+
+/// Information about every opcode, indexed by opcode. Illegal opcodes are `None`. This table is
+/// used for decoding instructions, so it always matches what the CPU executes.
+#[rustfmt::skip]
+pub static OPCODE_TABLE: [Option<OpcodeInfo>; 256] = {
+    let mut t = [None; 256];
+    t[0x00] = op(Instruction::BRK, AddressingMode::Implied, 7);
+    t[0x01] = op(Instruction::ORA, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0x05] = op(Instruction::ORA, AddressingMode::ZeroPage, 3);
+    t[0x06] = op(Instruction::ASL, AddressingMode::ZeroPage, 5);
+    t[0x08] = op(Instruction::PHP, AddressingMode::Implied, 3);
+    t[0x09] = op(Instruction::ORA, AddressingMode::Immediate, 2);
+    t[0x0a] = op(Instruction::ASL, AddressingMode::Accumulator, 2);
+    t[0x0d] = op(Instruction::ORA, AddressingMode::Absolute, 4);
+    t[0x0e] = op(Instruction::ASL, AddressingMode::Absolute, 6);
+    t[0x10] = op(Instruction::BPL, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0x11] = op(Instruction::ORA, AddressingMode::ZeroPageIndirectIndexedWithY, 5); // +1 cycle if page crossed
+    t[0x15] = op(Instruction::ORA, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0x16] = op(Instruction::ASL, AddressingMode::ZeroPageIndexedWithX, 6);
+    t[0x18] = op(Instruction::CLC, AddressingMode::Implied, 2);
+    t[0x19] = op(Instruction::ORA, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0x1d] = op(Instruction::ORA, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0x1e] = op(Instruction::ASL, AddressingMode::AbsoluteIndexedWithX, 7);
+    t[0x20] = op(Instruction::JSR, AddressingMode::Absolute, 6);
+    t[0x21] = op(Instruction::AND, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0x24] = op(Instruction::BIT, AddressingMode::ZeroPage, 3);
+    t[0x25] = op(Instruction::AND, AddressingMode::ZeroPage, 3);
+    t[0x26] = op(Instruction::ROL, AddressingMode::ZeroPage, 5);
+    t[0x28] = op(Instruction::PLP, AddressingMode::Implied, 4);
+    t[0x29] = op(Instruction::AND, AddressingMode::Immediate, 2);
+    t[0x2a] = op(Instruction::ROL, AddressingMode::Accumulator, 2);
+    t[0x2c] = op(Instruction::BIT, AddressingMode::Absolute, 4);
+    t[0x2d] = op(Instruction::AND, AddressingMode::Absolute, 4);
+    t[0x2e] = op(Instruction::ROL, AddressingMode::Absolute, 6);
+    t[0x30] = op(Instruction::BMI, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0x31] = op(Instruction::AND, AddressingMode::ZeroPageIndirectIndexedWithY, 5); // +1 cycle if page crossed
+    t[0x35] = op(Instruction::AND, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0x36] = op(Instruction::ROL, AddressingMode::ZeroPageIndexedWithX, 6);
+    t[0x38] = op(Instruction::SEC, AddressingMode::Implied, 2);
+    t[0x39] = op(Instruction::AND, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0x3d] = op(Instruction::AND, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0x3e] = op(Instruction::ROL, AddressingMode::AbsoluteIndexedWithX, 7);
+    t[0x40] = op(Instruction::RTI, AddressingMode::Implied, 6);
+    t[0x41] = op(Instruction::EOR, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0x45] = op(Instruction::EOR, AddressingMode::ZeroPage, 3);
+    t[0x46] = op(Instruction::LSR, AddressingMode::ZeroPage, 5);
+    t[0x48] = op(Instruction::PHA, AddressingMode::Implied, 3);
+    t[0x49] = op(Instruction::EOR, AddressingMode::Immediate, 2);
+    t[0x4a] = op(Instruction::LSR, AddressingMode::Accumulator, 2);
+    t[0x4c] = op(Instruction::JMP, AddressingMode::Absolute, 3);
+    t[0x4d] = op(Instruction::EOR, AddressingMode::Absolute, 4);
+    t[0x4e] = op(Instruction::LSR, AddressingMode::Absolute, 6);
+    t[0x50] = op(Instruction::BVC, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0x51] = op(Instruction::EOR, AddressingMode::ZeroPageIndirectIndexedWithY, 5); // +1 cycle if page crossed
+    t[0x55] = op(Instruction::EOR, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0x56] = op(Instruction::LSR, AddressingMode::ZeroPageIndexedWithX, 6);
+    t[0x58] = op(Instruction::CLI, AddressingMode::Implied, 2);
+    t[0x59] = op(Instruction::EOR, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0x5d] = op(Instruction::EOR, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0x5e] = op(Instruction::LSR, AddressingMode::AbsoluteIndexedWithX, 7);
+    t[0x60] = op(Instruction::RTS, AddressingMode::Implied, 6);
+    t[0x61] = op(Instruction::ADC, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0x65] = op(Instruction::ADC, AddressingMode::ZeroPage, 3);
+    t[0x66] = op(Instruction::ROR, AddressingMode::ZeroPage, 5);
+    t[0x68] = op(Instruction::PLA, AddressingMode::Implied, 4);
+    t[0x69] = op(Instruction::ADC, AddressingMode::Immediate, 2);
+    t[0x6a] = op(Instruction::ROR, AddressingMode::Accumulator, 2);
+    t[0x6c] = op(Instruction::JMP, AddressingMode::Indirect, 5);
+    t[0x6d] = op(Instruction::ADC, AddressingMode::Absolute, 4);
+    t[0x6e] = op(Instruction::ROR, AddressingMode::Absolute, 6);
+    t[0x70] = op(Instruction::BVS, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0x71] = op(Instruction::ADC, AddressingMode::ZeroPageIndirectIndexedWithY, 5); // +1 cycle if page crossed
+    t[0x75] = op(Instruction::ADC, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0x76] = op(Instruction::ROR, AddressingMode::ZeroPageIndexedWithX, 6);
+    t[0x78] = op(Instruction::SEI, AddressingMode::Implied, 2);
+    t[0x79] = op(Instruction::ADC, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0x7d] = op(Instruction::ADC, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0x7e] = op(Instruction::ROR, AddressingMode::AbsoluteIndexedWithX, 7);
+    t[0x81] = op(Instruction::STA, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0x84] = op(Instruction::STY, AddressingMode::ZeroPage, 3);
+    t[0x85] = op(Instruction::STA, AddressingMode::ZeroPage, 3);
+    t[0x86] = op(Instruction::STX, AddressingMode::ZeroPage, 3);
+    t[0x88] = op(Instruction::DEY, AddressingMode::Implied, 2);
+    t[0x8a] = op(Instruction::TXA, AddressingMode::Implied, 2);
+    t[0x8c] = op(Instruction::STY, AddressingMode::Absolute, 4);
+    t[0x8d] = op(Instruction::STA, AddressingMode::Absolute, 4);
+    t[0x8e] = op(Instruction::STX, AddressingMode::Absolute, 4);
+    t[0x90] = op(Instruction::BCC, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0x91] = op(Instruction::STA, AddressingMode::ZeroPageIndirectIndexedWithY, 6);
+    t[0x94] = op(Instruction::STY, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0x95] = op(Instruction::STA, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0x96] = op(Instruction::STX, AddressingMode::ZeroPageIndexedWithY, 4);
+    t[0x98] = op(Instruction::TYA, AddressingMode::Implied, 2);
+    t[0x99] = op(Instruction::STA, AddressingMode::AbsoluteIndexedWithY, 5);
+    t[0x9a] = op(Instruction::TXS, AddressingMode::Implied, 2);
+    t[0x9d] = op(Instruction::STA, AddressingMode::AbsoluteIndexedWithX, 5);
+    t[0xa0] = op(Instruction::LDY, AddressingMode::Immediate, 2);
+    t[0xa1] = op(Instruction::LDA, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0xa2] = op(Instruction::LDX, AddressingMode::Immediate, 2);
+    t[0xa4] = op(Instruction::LDY, AddressingMode::ZeroPage, 3);
+    t[0xa5] = op(Instruction::LDA, AddressingMode::ZeroPage, 3);
+    t[0xa6] = op(Instruction::LDX, AddressingMode::ZeroPage, 3);
+    t[0xa8] = op(Instruction::TAY, AddressingMode::Implied, 2);
+    t[0xa9] = op(Instruction::LDA, AddressingMode::Immediate, 2);
+    t[0xaa] = op(Instruction::TAX, AddressingMode::Implied, 2);
+    t[0xac] = op(Instruction::LDY, AddressingMode::Absolute, 4);
+    t[0xad] = op(Instruction::LDA, AddressingMode::Absolute, 4);
+    t[0xae] = op(Instruction::LDX, AddressingMode::Absolute, 4);
+    t[0xb0] = op(Instruction::BCS, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0xb1] = op(Instruction::LDA, AddressingMode::ZeroPageIndirectIndexedWithY, 5); // +1 cycle if page crossed
+    t[0xb4] = op(Instruction::LDY, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0xb5] = op(Instruction::LDA, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0xb6] = op(Instruction::LDX, AddressingMode::ZeroPageIndexedWithY, 4);
+    t[0xb8] = op(Instruction::CLV, AddressingMode::Implied, 2);
+    t[0xb9] = op(Instruction::LDA, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0xba] = op(Instruction::TSX, AddressingMode::Implied, 2);
+    t[0xbc] = op(Instruction::LDY, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0xbd] = op(Instruction::LDA, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0xbe] = op(Instruction::LDX, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0xc0] = op(Instruction::CPY, AddressingMode::Immediate, 2);
+    t[0xc1] = op(Instruction::CMP, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0xc4] = op(Instruction::CPY, AddressingMode::ZeroPage, 3);
+    t[0xc5] = op(Instruction::CMP, AddressingMode::ZeroPage, 3);
+    t[0xc6] = op(Instruction::DEC, AddressingMode::ZeroPage, 5);
+    t[0xc8] = op(Instruction::INY, AddressingMode::Implied, 2);
+    t[0xc9] = op(Instruction::CMP, AddressingMode::Immediate, 2);
+    t[0xca] = op(Instruction::DEX, AddressingMode::Implied, 2);
+    t[0xcc] = op(Instruction::CPY, AddressingMode::Absolute, 4);
+    t[0xcd] = op(Instruction::CMP, AddressingMode::Absolute, 4);
+    t[0xce] = op(Instruction::DEC, AddressingMode::Absolute, 6);
+    t[0xd0] = op(Instruction::BNE, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0xd1] = op(Instruction::CMP, AddressingMode::ZeroPageIndirectIndexedWithY, 5); // +1 cycle if page crossed
+    t[0xd5] = op(Instruction::CMP, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0xd6] = op(Instruction::DEC, AddressingMode::ZeroPageIndexedWithX, 6);
+    t[0xd8] = op(Instruction::CLD, AddressingMode::Implied, 2);
+    t[0xd9] = op(Instruction::CMP, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0xdd] = op(Instruction::CMP, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0xde] = op(Instruction::DEC, AddressingMode::AbsoluteIndexedWithX, 7);
+    t[0xe0] = op(Instruction::CPX, AddressingMode::Immediate, 2);
+    t[0xe1] = op(Instruction::SBC, AddressingMode::ZeroPageIndexedWithXIndirect, 6);
+    t[0xe4] = op(Instruction::CPX, AddressingMode::ZeroPage, 3);
+    t[0xe5] = op(Instruction::SBC, AddressingMode::ZeroPage, 3);
+    t[0xe6] = op(Instruction::INC, AddressingMode::ZeroPage, 5);
+    t[0xe8] = op(Instruction::INX, AddressingMode::Implied, 2);
+    t[0xe9] = op(Instruction::SBC, AddressingMode::Immediate, 2);
+    t[0xea] = op(Instruction::NOP, AddressingMode::Implied, 2);
+    t[0xec] = op(Instruction::CPX, AddressingMode::Absolute, 4);
+    t[0xed] = op(Instruction::SBC, AddressingMode::Absolute, 4);
+    t[0xee] = op(Instruction::INC, AddressingMode::Absolute, 6);
+    t[0xf0] = op(Instruction::BEQ, AddressingMode::Relative, 2); // +1 cycle if branched, +2 if page crossed
+    t[0xf1] = op(Instruction::SBC, AddressingMode::ZeroPageIndirectIndexedWithY, 5); // +1 cycle if page crossed
+    t[0xf5] = op(Instruction::SBC, AddressingMode::ZeroPageIndexedWithX, 4);
+    t[0xf6] = op(Instruction::INC, AddressingMode::ZeroPageIndexedWithX, 6);
+    t[0xf8] = op(Instruction::SED, AddressingMode::Implied, 2);
+    t[0xf9] = op(Instruction::SBC, AddressingMode::AbsoluteIndexedWithY, 4); // +1 cycle if page crossed
+    t[0xfd] = op(Instruction::SBC, AddressingMode::AbsoluteIndexedWithX, 4); // +1 cycle if page crossed
+    t[0xfe] = op(Instruction::INC, AddressingMode::AbsoluteIndexedWithX, 7);
+    t
+};
+
+/// Get the memory contents at the given PC and advance the given PC
+fn fetch<M: Addressable, const N: usize, T: Integer<N>>(mem: &M, pc: &mut u16) -> T {
+    let value = mem.get_le(*pc);
+    *pc = pc.wrapping_add(mem::size_of::<T>() as u16);
+    value
+}
+
+/// Error returned when decoding an opcode that isn't a valid instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    /// Address of the opcode
+    pub pc: u16,
+    /// The opcode byte
+    pub opcode: u8,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Illegal opcode #${:02X} at {}",
+            self.opcode,
+            self.pc.display()
+        )
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Parse the instruction with the given opcode, fetching operands at the given PC and advancing
+/// the given PC. Returns number of cycles, instruction and operand
+pub(super) fn decode_opcode<M: Addressable>(
+    mem: &M,
+    opcode: u8,
+    pc: &mut u16,
+) -> Option<(usize, Instruction, Operand)> {
+    let info = OPCODE_TABLE[opcode as usize]?;
+    let mut bytes = [0; 2];
+    for byte in bytes.iter_mut().take(info.len as usize - 1) {
+        *byte = fetch(mem, pc);
+    }
+    let operand = Operand::new(info.mode, bytes);
+    Some((info.cycles, info.instruction, operand))
+}
+
 impl Instruction {
+    /// Decode the instruction at the given address without executing it. Returns instruction,
+    /// operand, length of the instruction in bytes and number of cycles (without penalties for
+    /// page crossing and taken branches).
+    pub fn decode<M: Addressable>(mem: &M, addr: u16) -> Option<(Instruction, Operand, u8, usize)> {
+        let mut pc = addr;
+        let opcode: u8 = fetch(mem, &mut pc);
+        let (cycles, instruction, operand) = decode_opcode(mem, opcode, &mut pc)?;
+        Some((instruction, operand, pc.wrapping_sub(addr) as u8, cycles))
+    }
+
+    /// Returns true if the instruction is a branch whose condition is met by the given status
+    /// flags
+    pub(super) fn branches(&self, sr: StatusFlags) -> bool {
+        match *self {
+            // branch if carry flag clear
+            Instruction::BCC => !sr.contains(StatusFlags::CARRY_FLAG),
+            // branch if carry flag set
+            Instruction::BCS => sr.contains(StatusFlags::CARRY_FLAG),
+            // branch if zero flag set
+            Instruction::BEQ => sr.contains(StatusFlags::ZERO_FLAG),
+            // branch if negative flag set
+            Instruction::BMI => sr.contains(StatusFlags::NEGATIVE_FLAG),
+            // branch if zero flag clear
+            Instruction::BNE => !sr.contains(StatusFlags::ZERO_FLAG),
+            // branch if negative flag clear
+            Instruction::BPL => !sr.contains(StatusFlags::NEGATIVE_FLAG),
+            // branch if overflow flag clear
+            Instruction::BVC => !sr.contains(StatusFlags::OVERFLOW_FLAG),
+            // branch if overflow flag set
+            Instruction::BVS => sr.contains(StatusFlags::OVERFLOW_FLAG),
+            _ => false,
+        }
+    }
+
     /// Execute an instruction using the given environment
     pub fn execute<M: Addressable>(&self, cpu: &mut Mos6502<M>, operand: &Operand) {
+        // Resolve the effective address once, so that read-modify-write instructions don't
+        // read indirect pointers twice
+        self.execute_resolved(cpu, operand.resolve(cpu));
+    }
+
+    /// Execute an instruction with an operand whose effective address is already resolved
+    pub(super) fn execute_resolved<M: Addressable>(
+        &self,
+        cpu: &mut Mos6502<M>,
+        operand: ResolvedOperand,
+    ) {
         match *self {
             // Load/store operations
             Instruction::LDA => {
@@ -338,69 +668,41 @@ impl Instruction {
             // Jump & calls
             Instruction::JMP => {
                 // jump to another location
-                cpu.pc = operand.addr(cpu);
+                cpu.pc = operand.addr();
             }
             Instruction::JSR => {
                 // jump to a subroutine
                 // Push the address of the last byte of this instruction to the
                 // stack instead of the address of the next instruction.
                 let pc = cpu.pc;
-                cpu.push(pc - 1);
-                cpu.pc = operand.addr(cpu);
+                cpu.push(pc.wrapping_sub(1));
+                cpu.pc = operand.addr();
+                cpu.calls.enter(CallFrame {
+                    return_addr: pc,
+                    target: cpu.pc,
+                    sp: cpu.sp,
+                    kind: CallKind::Subroutine,
+                });
             }
             Instruction::RTS => {
                 // return from subroutine
-                cpu.pc = cpu.pop();
+                cpu.calls.leave(cpu.sp);
+                let pc: u16 = cpu.pop();
                 // Need to advance the PC by 1 to step to the next instruction
-                cpu.pc += 1;
+                cpu.pc = pc.wrapping_add(1);
             }
             // Branches
-            Instruction::BCC => {
-                // branch if carry flag clear
-                if !cpu.sr.contains(StatusFlags::CARRY_FLAG) {
-                    cpu.pc = operand.addr(cpu);
-                }
-            }
-            Instruction::BCS => {
-                // branch if carry flag set
-                if cpu.sr.contains(StatusFlags::CARRY_FLAG) {
-                    cpu.pc = operand.addr(cpu);
-                }
-            }
-            Instruction::BEQ => {
-                // branch if zero flag set
-                if cpu.sr.contains(StatusFlags::ZERO_FLAG) {
-                    cpu.pc = operand.addr(cpu);
-                }
-            }
-            Instruction::BMI => {
-                // branch if negative flag set
-                if cpu.sr.contains(StatusFlags::NEGATIVE_FLAG) {
-                    cpu.pc = operand.addr(cpu);
-                }
-            }
-            Instruction::BNE => {
-                // branch if zero flag clear
-                if !cpu.sr.contains(StatusFlags::ZERO_FLAG) {
-                    cpu.pc = operand.addr(cpu);
-                }
-            }
-            Instruction::BPL => {
-                // branch if negative flag clear
-                if !cpu.sr.contains(StatusFlags::NEGATIVE_FLAG) {
-                    cpu.pc = operand.addr(cpu);
-                }
-            }
-            Instruction::BVC => {
-                // branch if overflow flag clear
-                if !cpu.sr.contains(StatusFlags::OVERFLOW_FLAG) {
-                    cpu.pc = operand.addr(cpu);
-                }
-            }
-            Instruction::BVS => {
-                // branch if overflow flag set
-                if cpu.sr.contains(StatusFlags::OVERFLOW_FLAG) {
-                    cpu.pc = operand.addr(cpu);
+            Instruction::BCC
+            | Instruction::BCS
+            | Instruction::BEQ
+            | Instruction::BMI
+            | Instruction::BNE
+            | Instruction::BPL
+            | Instruction::BVC
+            | Instruction::BVS => {
+                // branch if the condition of the instruction is met
+                if self.branches(cpu.sr) {
+                    cpu.pc = operand.addr();
                 }
             }
             // Status flag changes
@@ -441,7 +743,8 @@ impl Instruction {
                 // instruction to the stack. The next byte after BRK is
                 // skipped. It can be used to pass information to the
                 // interrupt handler.
-                cpu.push(cpu.pc + 1);
+                let return_addr = cpu.pc.wrapping_add(1);
+                cpu.push(return_addr);
                 cpu.push(cpu.sr.bits());
                 cpu.sr.insert(StatusFlags::INTERRUPT_DISABLE_FLAG);
                 cpu.pc = cpu.mem.get_le(IRQ_VECTOR);
@@ -450,12 +753,14 @@ impl Instruction {
                     IRQ_VECTOR.display(),
                     cpu.pc.display()
                 );
+                cpu.notify_interrupt(InterruptKind::Brk, return_addr);
             }
             Instruction::NOP => {
                 // no operation
             }
             Instruction::RTI => {
                 // return from interrupt [all]
+                cpu.calls.leave(cpu.sp);
                 cpu.sr = StatusFlags::from_bits_retain(cpu.pop());
                 cpu.pc = cpu.pop();
                 // Unlike RTS, do not advance the PC since it already points to
@@ -467,7 +772,7 @@ impl Instruction {
 
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(match *self {
+        f.pad(match *self {
             Instruction::LDA => "LDA",
             Instruction::LDX => "LDX",
             Instruction::LDY => "LDY",
@@ -527,3 +832,114 @@ impl fmt::Display for Instruction {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::{Access, AccessKind, Logged, Ram};
+
+    #[test]
+    fn decode_without_cpu() {
+        let mut mem = Ram::zeroed(0xffff);
+        mem.setn(0x1000, [0xa9, 0x12]); // A9 12: LDA #$12
+        mem.setn(0x1002, [0x9d, 0x00, 0x04]); // 9D 00 04: STA $0400,X
+        mem.set(0x1005, 0xe8); // E8: INX
+        mem.setn(0x1006, [0xd0, 0xf8]); // D0 F8: BNE -8
+        mem.setn(0x1008, [0x6c, 0xfc, 0xff]); // 6C FC FF: JMP ($FFFC)
+        mem.set(0x100b, 0x02); // 02: illegal
+        assert_eq!(
+            Instruction::decode(&mem, 0x1000),
+            Some((Instruction::LDA, Operand::Immediate(0x12), 2, 2))
+        );
+        assert_eq!(
+            Instruction::decode(&mem, 0x1002),
+            Some((
+                Instruction::STA,
+                Operand::AbsoluteIndexedWithX(0x0400),
+                3,
+                5
+            ))
+        );
+        assert_eq!(
+            Instruction::decode(&mem, 0x1005),
+            Some((Instruction::INX, Operand::Implied, 1, 2))
+        );
+        assert_eq!(
+            Instruction::decode(&mem, 0x1006),
+            Some((Instruction::BNE, Operand::Relative(-8), 2, 2))
+        );
+        assert_eq!(
+            Instruction::decode(&mem, 0x1008),
+            Some((Instruction::JMP, Operand::Indirect(0xfffc), 3, 5))
+        );
+        assert_eq!(Instruction::decode(&mem, 0x100b), None);
+    }
+
+    #[test]
+    fn decode_matches_cpu() {
+        let mut mem = Ram::zeroed(0xffff);
+        mem.setn(0x1000, [0xa9, 0x12, 0x9d, 0x00, 0x04, 0xe8, 0xd0, 0xf8]);
+        let mut cpu = Mos6502::new(mem);
+        cpu.set_pc(0x1000);
+        for _ in 0..4 {
+            let pc = cpu.pc();
+            let (instruction, operand, len, cycles) =
+                Instruction::decode(cpu.memory(), pc).unwrap();
+            assert_eq!(cpu.next_instruction(), Ok((cycles, instruction, operand)));
+            assert_eq!(cpu.pc(), pc + len as u16);
+        }
+    }
+
+    #[test]
+    fn read_modify_write_resolves_address_once() {
+        let mut cpu = Mos6502::new(Logged::new(Ram::zeroed(0xffff)));
+        cpu.mem.set_le(0x0080, 0x0200_u16);
+        cpu.mem.set(0x0205, 0x41);
+        cpu.mem.clear_log();
+        cpu.y = 0x05;
+        Instruction::INC.execute(&mut cpu, &Operand::ZeroPageIndirectIndexedWithY(0x80));
+        let read = |addr, data| Access {
+            kind: AccessKind::Read,
+            addr,
+            data,
+        };
+        assert_eq!(
+            *cpu.mem.log(),
+            [
+                read(0x0080, 0x00),
+                read(0x0081, 0x02),
+                read(0x0205, 0x41),
+                Access {
+                    kind: AccessKind::Write,
+                    addr: 0x0205,
+                    data: 0x42,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn opcode_table_matches_decoding() {
+        let mut mem = Ram::zeroed(0xffff);
+        let mut legal = 0;
+        for opcode in 0..=255_u8 {
+            mem.setn(0x1000, [opcode, 0x34, 0x12]);
+            let decoded = Instruction::decode(&mem, 0x1000);
+            let info = match OPCODE_TABLE[opcode as usize] {
+                Some(info) => info,
+                None => {
+                    assert_eq!(decoded, None, "opcode #${:02X}", opcode);
+                    continue;
+                }
+            };
+            let (instruction, operand, len, cycles) = decoded.unwrap();
+            assert_eq!(instruction, info.instruction, "opcode #${:02X}", opcode);
+            assert_eq!(operand.mode(), info.mode, "opcode #${:02X}", opcode);
+            assert_eq!(len, info.len, "opcode #${:02X}", opcode);
+            assert_eq!(operand.len(), info.len, "opcode #${:02X}", opcode);
+            assert_eq!(cycles, info.cycles, "opcode #${:02X}", opcode);
+            legal += 1;
+        }
+        assert_eq!(legal, 151);
+    }
+}
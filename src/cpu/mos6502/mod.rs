@@ -12,18 +12,37 @@
 //!            http://visual6502.org/wiki/index.php?title=6502TestPrograms
 //!            http://forum.6502.org/viewtopic.php?f=2&t=2241
 
+mod asm;
+mod callstack;
+mod disassembler;
+mod history;
 mod instruction;
 mod operand;
+mod trace;
 
-use super::Cpu;
+#[cfg(test)]
+mod conformance;
+
+use self::callstack::CallStack;
+use self::history::History;
+use super::{Cpu, IrqLine};
 use crate::addr::{Address, Integer, Masked};
 use crate::mem::Addressable;
 use bitflags::bitflags;
-use log::{debug, trace};
-use std::mem;
+use log::warn;
+#[cfg(not(feature = "tracing"))]
+use log::{debug, log_enabled, trace, Level};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::{fmt, mem};
 
-pub use self::instruction::Instruction;
-pub use self::operand::Operand;
+pub use self::asm::{assemble, AsmError, AsmErrorKind};
+pub use self::callstack::{CallFrame, CallKind};
+pub use self::disassembler::{disassemble, disassemble_count, disassemble_range, DisassembledLine};
+use self::instruction::decode_opcode;
+pub use self::instruction::{DecodeError, Instruction, OpcodeInfo, OPCODE_TABLE};
+pub use self::operand::{AddressingMode, Operand, ResolvedOperand};
+pub use self::trace::{run_and_compare, TraceFormatter, TraceLine};
 
 /// Hard-coded address where to look for the address to jump to on nonmaskable interrupt
 pub const NMI_VECTOR: u16 = 0xfffa;
@@ -32,38 +51,233 @@ pub const RESET_VECTOR: u16 = 0xfffc;
 /// Hard-coded address where to look for the address to jump to on interrupt
 pub const IRQ_VECTOR: u16 = 0xfffe;
 
+/// Opcodes of subroutine calls and returns, used by the debugger primitives
+const JSR_OPCODE: u8 = 0x20;
+const RTS_OPCODE: u8 = 0x60;
+const RTI_OPCODE: u8 = 0x40;
+
+/// A handler that is invoked before executing the instruction at a trapped address
+pub type TrapHandler<M> = Box<dyn FnMut(&mut Mos6502<M>)>;
+
+/// A handler that is invoked whenever an interrupt sequence is taken, with the kind of
+/// interrupt and the address of the interrupt handler
+pub type InterruptHandler = Box<dyn FnMut(InterruptKind, u16)>;
+
+/// Kind of an interrupt sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptKind {
+    /// Nonmaskable interrupt
+    Nmi,
+    /// Interrupt request
+    Irq,
+    /// Software interrupt (BRK instruction)
+    Brk,
+}
+
+/// Interrupt sequence that is processed instead of the next instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interrupt {
+    Reset,
+    Nmi,
+    Irq,
+}
+
+/// An instruction that is executed cycle by cycle by `tick`. Its current cycle is derived from
+/// the remaining cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InFlight {
+    pc: u16,                           // address of the opcode
+    opcode: u8,                        // fetched opcode
+    info: OpcodeInfo,                  // information about the opcode
+    cycles: usize,                     // number of cycles (including penalties known so far)
+    bytes: [u8; 2],                    // operand bytes fetched so far
+    resolved: Option<ResolvedOperand>, // operand resolved before the last cycle
+}
+
 /// The MOS6502 processor
-#[derive(Debug)]
 pub struct Mos6502<M> {
-    pc: u16,         // Program Counter
-    ac: u8,          // Accumulator
-    x: u8,           // X register
-    y: u8,           // Y register
-    sr: StatusFlags, // Status Register
-    sp: u8,          // Stack Pointer
-    mem: M,          // main memory
-    reset: bool,     // RESET line
-    nmi: bool,       // NMI line
-    irq: bool,       // IRQ line
+    pc: u16,                                     // Program Counter
+    ac: u8,                                      // Accumulator
+    x: u8,                                       // X register
+    y: u8,                                       // Y register
+    sr: StatusFlags,                             // Status Register
+    sp: u8,                                      // Stack Pointer
+    mem: M,                                      // main memory
+    reset: bool,                                 // RESET line
+    nmi: bool,                                   // NMI latch (edge seen, not processed yet)
+    irq: bool,                                   // IRQ line
+    halted: bool,                                // jammed by a JAM opcode
+    irq_line: Option<IrqLine>,                   // shared IRQ line
+    nmi_line: Option<IrqLine>,                   // shared NMI line
+    nmi_level: bool,                             // NMI input level set by assert_nmi
+    nmi_asserted: bool,                          // last sampled level of NMI inputs
+    traps: HashMap<u16, TrapHandler<M>>,         // trapped addresses
+    on_interrupt: Option<InterruptHandler>,      // invoked when an interrupt is taken
+    breakpoints: HashSet<u16>,                   // addresses to stop execution at
+    history: History,                            // last executed instructions
+    calls: CallStack,                            // active subroutine calls
+    profiling: bool,                             // whether to count executed instructions
+    profile: HashMap<Instruction, ProfileEntry>, // executed instruction counts
+    coverage: Option<Vec<bool>>,                 // executed instruction addresses
+    trace_writer: Option<Box<dyn Write>>,        // sink for trace lines
+    pending_cycles: usize,                       // remaining cycles of current instruction
+    in_flight: Option<InFlight>,                 // instruction executed cycle by cycle
+    cycles: u64,                                 // total number of cycles simulated
+    last_step: (u16, Executed),                  // start PC and kind of the last step
 }
 
 bitflags! {
     /// The MOS6502 status flags
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     pub struct StatusFlags: u8 {
-        const CARRY_FLAG             = 1 << 0;  // C
-        const ZERO_FLAG              = 1 << 1;  // Z
-        const INTERRUPT_DISABLE_FLAG = 1 << 2;  // I
-        const DECIMAL_FLAG           = 1 << 3;  // D
-        const BREAK_FLAG             = 1 << 4;  // B
-        const UNUSED_ALWAYS_ON_FLAG  = 1 << 5;  // -
-        const OVERFLOW_FLAG          = 1 << 6;  // V
-        const NEGATIVE_FLAG          = 1 << 7;  // N
+        /// Carry flag (C)
+        const CARRY_FLAG             = 1 << 0;
+        /// Zero flag (Z)
+        const ZERO_FLAG              = 1 << 1;
+        /// Interrupt disable flag (I)
+        const INTERRUPT_DISABLE_FLAG = 1 << 2;
+        /// Decimal mode flag (D)
+        const DECIMAL_FLAG           = 1 << 3;
+        /// Break flag (B)
+        const BREAK_FLAG             = 1 << 4;
+        /// Unused flag, always on (-)
+        const UNUSED_ALWAYS_ON_FLAG  = 1 << 5;
+        /// Overflow flag (V)
+        const OVERFLOW_FLAG          = 1 << 6;
+        /// Negative flag (N)
+        const NEGATIVE_FLAG          = 1 << 7;
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for StatusFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.bits())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StatusFlags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(StatusFlags::from_bits_retain)
+    }
+}
+
+impl fmt::Display for StatusFlags {
+    /// Format flags like `nv-bdIzC`, with set flags in uppercase (the unused flag is always shown
+    /// as `-`)
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, letter) in "nv-bdizc".chars().enumerate() {
+            if letter != '-' && self.bits() & (0x80 >> i) != 0 {
+                write!(f, "{}", letter.to_ascii_uppercase())?;
+            } else {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Result of running the processor with a cycle budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunResult {
+    /// The stop condition was met after the given number of cycles
+    Stopped(u64),
+    /// A breakpoint was hit after the given number of cycles
+    Breakpoint(u64),
+    /// The cycle budget was exhausted after the given number of cycles
+    BudgetExhausted(u64),
+}
+
+impl RunResult {
+    /// Returns the number of cycles that were simulated
+    pub fn cycles(&self) -> u64 {
+        match *self {
+            RunResult::Stopped(cycles)
+            | RunResult::Breakpoint(cycles)
+            | RunResult::BudgetExhausted(cycles) => cycles,
+        }
     }
 }
 
+/// What the processor did in a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Executed {
+    /// The given instruction was executed with the given operand (including BRK)
+    Instruction(Instruction, Operand),
+    /// An NMI or IRQ sequence was processed
+    Interrupt(InterruptKind),
+    /// A RESET sequence was processed
+    Reset,
+    /// The processor is jammed and did nothing
+    Jammed,
+}
+
+/// Result of a traced step, see `Mos6502::step_traced`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepResult {
+    /// Number of cycles that were simulated
+    pub cycles: usize,
+    /// Program counter before the step
+    pub pc: u16,
+    /// Program counter after the step
+    pub next_pc: u16,
+    /// What the processor did
+    pub executed: Executed,
+}
+
+/// Execution statistics of an instruction, collected if profiling is enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProfileEntry {
+    /// Number of times the instruction was executed
+    pub count: u64,
+    /// Number of cycles spent executing the instruction
+    pub cycles: u64,
+}
+
+/// Complete state of a MOS6502 processor (without memory), e.g. for save states
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    /// Program Counter
+    pub pc: u16,
+    /// Accumulator
+    pub ac: u8,
+    /// X register
+    pub x: u8,
+    /// Y register
+    pub y: u8,
+    /// Status Register
+    pub sr: StatusFlags,
+    /// Stack Pointer
+    pub sp: u8,
+    /// RESET line
+    pub reset: bool,
+    /// NMI line
+    pub nmi: bool,
+    /// IRQ line
+    pub irq: bool,
+    /// Jammed by a JAM opcode (until RESET)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub halted: bool,
+    /// Remaining cycles of the current instruction
+    pub pending_cycles: usize,
+    /// Address of the instruction in flight if the state was taken in the middle of an
+    /// instruction (interrupt sequences are processed at once and have no address here)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub instruction_pc: Option<u16>,
+    /// Number of cycles of the instruction in flight that are done already
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub instruction_cycles_done: usize,
+    /// Total number of cycles simulated
+    pub cycles: u64,
+}
+
 impl<M: Addressable> Mos6502<M> {
-    /// Create a new MOS6502 processor
+    /// Create a new MOS6502 processor with a pending RESET. On real hardware, registers contain
+    /// random values after power-on, so programs must not rely on them. Here, all registers
+    /// (including SP) are zero and SR only has the unused flag set, which together with
+    /// deterministically initialized memory (e.g. `Ram::zeroed`) makes every run bit-identical.
     pub fn new(mem: M) -> Mos6502<M> {
         Mos6502 {
             pc: 0x0000,
@@ -76,175 +290,257 @@ impl<M: Addressable> Mos6502<M> {
             reset: true,
             nmi: false,
             irq: false,
+            halted: false,
+            irq_line: None,
+            nmi_line: None,
+            nmi_level: false,
+            nmi_asserted: false,
+            traps: HashMap::new(),
+            on_interrupt: None,
+            breakpoints: HashSet::new(),
+            history: History::default(),
+            calls: CallStack::default(),
+            profiling: false,
+            profile: HashMap::new(),
+            coverage: None,
+            trace_writer: None,
+            pending_cycles: 0,
+            in_flight: None,
+            cycles: 0,
+            last_step: (0x0000, Executed::Jammed),
+        }
+    }
+
+    /// Create a new MOS6502 processor with the given state, e.g. restored from a save state
+    /// together with its memory
+    pub fn from_state(state: &CpuState, mem: M) -> Mos6502<M> {
+        let mut cpu = Mos6502::new(mem);
+        cpu.load_state(state);
+        cpu
+    }
+
+    /// Returns the processor state together with the memory, e.g. to store a save state
+    pub fn to_state(&self) -> (CpuState, &M) {
+        (self.save_state(), &self.mem)
+    }
+
+    /// Returns a reference to the memory the processor is connected to
+    pub fn memory(&self) -> &M {
+        &self.mem
+    }
+
+    /// Returns a mutable reference to the memory the processor is connected to
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.mem
+    }
+
+    /// Consume the processor and return the memory it was connected to
+    pub fn into_inner(self) -> M {
+        self.mem
+    }
+
+    /// Consume the processor and return its state together with the memory it was connected to
+    pub fn into_parts(self) -> (CpuState, M) {
+        (self.save_state(), self.mem)
+    }
+
+    /// Returns the program counter (PC)
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Set the program counter (PC)
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Returns the accumulator (A)
+    pub fn a(&self) -> u8 {
+        self.ac
+    }
+
+    /// Set the accumulator (A)
+    pub fn set_a(&mut self, ac: u8) {
+        self.ac = ac;
+    }
+
+    /// Returns the X register
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    /// Set the X register
+    pub fn set_x(&mut self, x: u8) {
+        self.x = x;
+    }
+
+    /// Returns the Y register
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    /// Set the Y register
+    pub fn set_y(&mut self, y: u8) {
+        self.y = y;
+    }
+
+    /// Returns the stack pointer (SP)
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// Set the stack pointer (SP)
+    pub fn set_sp(&mut self, sp: u8) {
+        self.sp = sp;
+    }
+
+    /// Returns the absolute address the stack pointer points to ($0100+SP), i.e. the address
+    /// the next push writes to
+    pub fn stack_addr(&self) -> u16 {
+        0x0100 | self.sp as u16
+    }
+
+    /// Returns the absolute address of the value on top of the stack, i.e. the address the
+    /// next pop reads from. Like the stack pointer, it wraps around within the stack page.
+    pub fn stack_top(&self) -> u16 {
+        0x0100 | self.sp.wrapping_add(1) as u16
+    }
+
+    /// Returns the status register (SR)
+    pub fn status(&self) -> StatusFlags {
+        self.sr
+    }
+
+    /// Set the status register (SR). The unused flag is always kept on.
+    pub fn set_status(&mut self, sr: StatusFlags) {
+        self.sr = sr | StatusFlags::UNUSED_ALWAYS_ON_FLAG;
+    }
+
+    /// Returns the total number of cycles simulated since creation (or since the counter was
+    /// last reset)
+    pub fn total_cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Reset the total cycle counter to 0. Note that the counter is not reset by a CPU reset.
+    pub fn reset_cycles(&mut self) {
+        self.cycles = 0;
+    }
+
+    /// Returns the complete processor state (without memory)
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            pc: self.pc,
+            ac: self.ac,
+            x: self.x,
+            y: self.y,
+            sr: self.sr,
+            sp: self.sp,
+            reset: self.reset,
+            nmi: self.nmi,
+            irq: self.irq,
+            halted: self.halted,
+            pending_cycles: self.pending_cycles,
+            instruction_pc: self.in_flight.map(|in_flight| in_flight.pc),
+            instruction_cycles_done: self
+                .in_flight
+                .map_or(0, |in_flight| in_flight.cycles - self.pending_cycles),
+            cycles: self.cycles,
+        }
+    }
+
+    /// Restore the processor state from the given state (memory is left untouched)
+    pub fn load_state(&mut self, state: &CpuState) {
+        self.pc = state.pc;
+        self.ac = state.ac;
+        self.x = state.x;
+        self.y = state.y;
+        self.sr = state.sr;
+        self.sp = state.sp;
+        self.reset = state.reset;
+        self.nmi = state.nmi;
+        self.irq = state.irq;
+        self.halted = state.halted;
+        self.pending_cycles = state.pending_cycles;
+        self.cycles = state.cycles;
+        self.in_flight = state
+            .instruction_pc
+            .filter(|_| state.pending_cycles > 0)
+            .and_then(|pc| self.restore_in_flight(pc, state.instruction_cycles_done));
+    }
+
+    /// Rebuild the instruction in flight at the given address, which did the given number of
+    /// cycles already, from memory. The operand bytes it fetched are read again and so is the
+    /// value a read-modify-write instruction already read (it was written back unmodified
+    /// before the final write).
+    fn restore_in_flight(&self, pc: u16, done: usize) -> Option<InFlight> {
+        let opcode: u8 = self.mem.get(pc);
+        let info = OPCODE_TABLE[opcode as usize]?;
+        let mut in_flight = InFlight {
+            pc,
+            opcode,
+            info,
+            cycles: done + self.pending_cycles,
+            bytes: [0; 2],
+            resolved: None,
+        };
+        let fetched = done.min(info.len as usize).saturating_sub(1);
+        for (i, byte) in in_flight.bytes.iter_mut().take(fetched).enumerate() {
+            *byte = self.mem.get(pc.wrapping_add(1 + i as u16));
         }
+        let operand = Operand::new(info.mode, in_flight.bytes);
+        if is_read_modify_write(&info) && done + 2 >= info.cycles {
+            let addr = operand.addr(self);
+            in_flight.resolved = Some(ResolvedOperand::Latched(addr, self.mem.get(addr)));
+        } else if has_page_crossing_penalty(&info) && done + 1 >= info.cycles {
+            in_flight.resolved = Some(ResolvedOperand::Address(operand.addr(self)));
+        }
+        Some(in_flight)
+    }
+
+    /// Get the memory contents at the given PC and advance the given PC
+    fn fetch<const N: usize, T: Integer<N>>(&self, pc: &mut u16) -> T {
+        let value = self.mem.get_le(*pc);
+        *pc = pc.wrapping_add(mem::size_of::<T>() as u16);
+        value
     }
 
     /// Get the memory contents at the current PC and advance the PC
+    #[cfg(test)]
     fn next<const N: usize, T: Integer<N>>(&mut self) -> T {
-        let value = self.mem.get_le(self.pc);
-        self.pc += mem::size_of::<T>() as u16;
+        let mut pc = self.pc;
+        let value = self.fetch(&mut pc);
+        self.pc = pc;
         value
     }
 
-    /// Parse next instruction and advance PC. Returns number of cycles, instruction and operand
-    #[rustfmt::skip]
-    fn next_instruction(&mut self) -> Option<(usize, Instruction, Operand)> {
-        let opcode: u8 = self.next();
-        Some(match opcode {
-            0x00 => (7, Instruction::BRK, Operand::Implied),
-            0x01 => (6, Instruction::ORA, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0x05 => (3, Instruction::ORA, Operand::ZeroPage(self.next())),
-            0x06 => (5, Instruction::ASL, Operand::ZeroPage(self.next())),
-            0x08 => (3, Instruction::PHP, Operand::Implied),
-            0x09 => (2, Instruction::ORA, Operand::Immediate(self.next())),
-            0x0a => (2, Instruction::ASL, Operand::Accumulator),
-            0x0d => (4, Instruction::ORA, Operand::Absolute(self.next())),
-            0x0e => (6, Instruction::ASL, Operand::Absolute(self.next())),
-            0x10 => (2, Instruction::BPL, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0x11 => (5, Instruction::ORA, Operand::ZeroPageIndirectIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x15 => (4, Instruction::ORA, Operand::ZeroPageIndexedWithX(self.next())),
-            0x16 => (6, Instruction::ASL, Operand::ZeroPageIndexedWithX(self.next())),
-            0x18 => (2, Instruction::CLC, Operand::Implied),
-            0x19 => (4, Instruction::ORA, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x1d => (4, Instruction::ORA, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0x1e => (7, Instruction::ASL, Operand::AbsoluteIndexedWithX(self.next())),
-            0x20 => (6, Instruction::JSR, Operand::Absolute(self.next())),
-            0x21 => (6, Instruction::AND, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0x24 => (3, Instruction::BIT, Operand::ZeroPage(self.next())),
-            0x25 => (3, Instruction::AND, Operand::ZeroPage(self.next())),
-            0x26 => (5, Instruction::ROL, Operand::ZeroPage(self.next())),
-            0x28 => (4, Instruction::PLP, Operand::Implied),
-            0x29 => (2, Instruction::AND, Operand::Immediate(self.next())),
-            0x2a => (2, Instruction::ROL, Operand::Accumulator),
-            0x2c => (4, Instruction::BIT, Operand::Absolute(self.next())),
-            0x2d => (4, Instruction::AND, Operand::Absolute(self.next())),
-            0x2e => (6, Instruction::ROL, Operand::Absolute(self.next())),
-            0x30 => (2, Instruction::BMI, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0x31 => (5, Instruction::AND, Operand::ZeroPageIndirectIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x35 => (4, Instruction::AND, Operand::ZeroPageIndexedWithX(self.next())),
-            0x36 => (6, Instruction::ROL, Operand::ZeroPageIndexedWithX(self.next())),
-            0x38 => (2, Instruction::SEC, Operand::Implied),
-            0x39 => (4, Instruction::AND, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x3d => (4, Instruction::AND, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0x3e => (7, Instruction::ROL, Operand::AbsoluteIndexedWithX(self.next())),
-            0x40 => (6, Instruction::RTI, Operand::Implied),
-            0x41 => (6, Instruction::EOR, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0x45 => (3, Instruction::EOR, Operand::ZeroPage(self.next())),
-            0x46 => (5, Instruction::LSR, Operand::ZeroPage(self.next())),
-            0x48 => (3, Instruction::PHA, Operand::Implied),
-            0x49 => (2, Instruction::EOR, Operand::Immediate(self.next())),
-            0x4a => (2, Instruction::LSR, Operand::Accumulator),
-            0x4c => (3, Instruction::JMP, Operand::Absolute(self.next())),
-            0x4d => (4, Instruction::EOR, Operand::Absolute(self.next())),
-            0x4e => (6, Instruction::LSR, Operand::Absolute(self.next())),
-            0x50 => (2, Instruction::BVC, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0x51 => (5, Instruction::EOR, Operand::ZeroPageIndirectIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x55 => (4, Instruction::EOR, Operand::ZeroPageIndexedWithX(self.next())),
-            0x56 => (6, Instruction::LSR, Operand::ZeroPageIndexedWithX(self.next())),
-            0x58 => (2, Instruction::CLI, Operand::Implied),
-            0x59 => (4, Instruction::EOR, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x5d => (4, Instruction::EOR, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0x5e => (7, Instruction::LSR, Operand::AbsoluteIndexedWithX(self.next())),
-            0x60 => (6, Instruction::RTS, Operand::Implied),
-            0x61 => (6, Instruction::ADC, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0x65 => (3, Instruction::ADC, Operand::ZeroPage(self.next())),
-            0x66 => (5, Instruction::ROR, Operand::ZeroPage(self.next())),
-            0x68 => (4, Instruction::PLA, Operand::Implied),
-            0x69 => (2, Instruction::ADC, Operand::Immediate(self.next())),
-            0x6a => (2, Instruction::ROR, Operand::Accumulator),
-            0x6c => (5, Instruction::JMP, Operand::Indirect(self.next())),
-            0x6d => (4, Instruction::ADC, Operand::Absolute(self.next())),
-            0x6e => (6, Instruction::ROR, Operand::Absolute(self.next())),
-            0x70 => (2, Instruction::BVS, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0x71 => (5, Instruction::ADC, Operand::ZeroPageIndirectIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x75 => (4, Instruction::ADC, Operand::ZeroPageIndexedWithX(self.next())),
-            0x76 => (6, Instruction::ROR, Operand::ZeroPageIndexedWithX(self.next())),
-            0x78 => (2, Instruction::SEI, Operand::Implied),
-            0x79 => (4, Instruction::ADC, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0x7d => (4, Instruction::ADC, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0x7e => (7, Instruction::ROR, Operand::AbsoluteIndexedWithX(self.next())),
-            0x81 => (6, Instruction::STA, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0x84 => (3, Instruction::STY, Operand::ZeroPage(self.next())),
-            0x85 => (3, Instruction::STA, Operand::ZeroPage(self.next())),
-            0x86 => (3, Instruction::STX, Operand::ZeroPage(self.next())),
-            0x88 => (2, Instruction::DEY, Operand::Implied),
-            0x8a => (2, Instruction::TXA, Operand::Implied),
-            0x8c => (4, Instruction::STY, Operand::Absolute(self.next())),
-            0x8d => (4, Instruction::STA, Operand::Absolute(self.next())),
-            0x8e => (4, Instruction::STX, Operand::Absolute(self.next())),
-            0x90 => (2, Instruction::BCC, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0x91 => (6, Instruction::STA, Operand::ZeroPageIndirectIndexedWithY(self.next())),
-            0x94 => (4, Instruction::STY, Operand::ZeroPageIndexedWithX(self.next())),
-            0x95 => (4, Instruction::STA, Operand::ZeroPageIndexedWithX(self.next())),
-            0x96 => (4, Instruction::STX, Operand::ZeroPageIndexedWithY(self.next())),
-            0x98 => (2, Instruction::TYA, Operand::Implied),
-            0x99 => (5, Instruction::STA, Operand::AbsoluteIndexedWithY(self.next())),
-            0x9a => (2, Instruction::TXS, Operand::Implied),
-            0x9d => (5, Instruction::STA, Operand::AbsoluteIndexedWithX(self.next())),
-            0xa0 => (2, Instruction::LDY, Operand::Immediate(self.next())),
-            0xa1 => (6, Instruction::LDA, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0xa2 => (2, Instruction::LDX, Operand::Immediate(self.next())),
-            0xa4 => (3, Instruction::LDY, Operand::ZeroPage(self.next())),
-            0xa5 => (3, Instruction::LDA, Operand::ZeroPage(self.next())),
-            0xa6 => (3, Instruction::LDX, Operand::ZeroPage(self.next())),
-            0xa8 => (2, Instruction::TAY, Operand::Implied),
-            0xa9 => (2, Instruction::LDA, Operand::Immediate(self.next())),
-            0xaa => (2, Instruction::TAX, Operand::Implied),
-            0xac => (4, Instruction::LDY, Operand::Absolute(self.next())),
-            0xad => (4, Instruction::LDA, Operand::Absolute(self.next())),
-            0xae => (4, Instruction::LDX, Operand::Absolute(self.next())),
-            0xb0 => (2, Instruction::BCS, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0xb1 => (5, Instruction::LDA, Operand::ZeroPageIndirectIndexedWithY(self.next())), // +1 cycle if page crossed
-            0xb4 => (4, Instruction::LDY, Operand::ZeroPageIndexedWithX(self.next())),
-            0xb5 => (4, Instruction::LDA, Operand::ZeroPageIndexedWithX(self.next())),
-            0xb6 => (4, Instruction::LDX, Operand::ZeroPageIndexedWithY(self.next())),
-            0xb8 => (2, Instruction::CLV, Operand::Implied),
-            0xb9 => (4, Instruction::LDA, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0xba => (2, Instruction::TSX, Operand::Implied),
-            0xbc => (4, Instruction::LDY, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0xbd => (4, Instruction::LDA, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0xbe => (4, Instruction::LDX, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0xc0 => (2, Instruction::CPY, Operand::Immediate(self.next())),
-            0xc1 => (6, Instruction::CMP, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0xc4 => (3, Instruction::CPY, Operand::ZeroPage(self.next())),
-            0xc5 => (3, Instruction::CMP, Operand::ZeroPage(self.next())),
-            0xc6 => (5, Instruction::DEC, Operand::ZeroPage(self.next())),
-            0xc8 => (2, Instruction::INY, Operand::Implied),
-            0xc9 => (2, Instruction::CMP, Operand::Immediate(self.next())),
-            0xca => (2, Instruction::DEX, Operand::Implied),
-            0xcc => (4, Instruction::CPY, Operand::Absolute(self.next())),
-            0xcd => (4, Instruction::CMP, Operand::Absolute(self.next())),
-            0xce => (6, Instruction::DEC, Operand::Absolute(self.next())),
-            0xd0 => (2, Instruction::BNE, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0xd1 => (5, Instruction::CMP, Operand::ZeroPageIndirectIndexedWithY(self.next())), // +1 cycle if page crossed
-            0xd5 => (4, Instruction::CMP, Operand::ZeroPageIndexedWithX(self.next())),
-            0xd6 => (6, Instruction::DEC, Operand::ZeroPageIndexedWithX(self.next())),
-            0xd8 => (2, Instruction::CLD, Operand::Implied),
-            0xd9 => (4, Instruction::CMP, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0xdd => (4, Instruction::CMP, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0xde => (7, Instruction::DEC, Operand::AbsoluteIndexedWithX(self.next())),
-            0xe0 => (2, Instruction::CPX, Operand::Immediate(self.next())),
-            0xe1 => (6, Instruction::SBC, Operand::ZeroPageIndexedWithXIndirect(self.next())),
-            0xe4 => (3, Instruction::CPX, Operand::ZeroPage(self.next())),
-            0xe5 => (3, Instruction::SBC, Operand::ZeroPage(self.next())),
-            0xe6 => (5, Instruction::INC, Operand::ZeroPage(self.next())),
-            0xe8 => (2, Instruction::INX, Operand::Implied),
-            0xe9 => (2, Instruction::SBC, Operand::Immediate(self.next())),
-            0xea => (2, Instruction::NOP, Operand::Implied),
-            0xec => (4, Instruction::CPX, Operand::Absolute(self.next())),
-            0xed => (4, Instruction::SBC, Operand::Absolute(self.next())),
-            0xee => (6, Instruction::INC, Operand::Absolute(self.next())),
-            0xf0 => (2, Instruction::BEQ, Operand::Relative(self.next())), // +1 cycle if branched, +2 if page crossed
-            0xf1 => (5, Instruction::SBC, Operand::ZeroPageIndirectIndexedWithY(self.next())), // +1 cycle if page crossed
-            0xf5 => (4, Instruction::SBC, Operand::ZeroPageIndexedWithX(self.next())),
-            0xf6 => (6, Instruction::INC, Operand::ZeroPageIndexedWithX(self.next())),
-            0xf8 => (2, Instruction::SED, Operand::Implied),
-            0xf9 => (4, Instruction::SBC, Operand::AbsoluteIndexedWithY(self.next())), // +1 cycle if page crossed
-            0xfd => (4, Instruction::SBC, Operand::AbsoluteIndexedWithX(self.next())), // +1 cycle if page crossed
-            0xfe => (7, Instruction::INC, Operand::AbsoluteIndexedWithX(self.next())),
-            // Illegal opcode
-            _ => return None,
-        })
+    /// Parse next instruction and advance PC. Returns number of cycles, instruction and operand.
+    /// On an illegal opcode, the PC is left pointing at it.
+    #[cfg(test)]
+    fn next_instruction(&mut self) -> Result<(usize, Instruction, Operand), DecodeError> {
+        let mut pc = self.pc;
+        let result = self.decode(&mut pc);
+        self.pc = pc;
+        result
+    }
+
+    /// Parse next instruction without advancing PC. Returns number of cycles, instruction and
+    /// operand, just like the next step would execute it.
+    pub fn peek_instruction(&self) -> Result<(usize, Instruction, Operand), DecodeError> {
+        let mut pc = self.pc;
+        self.decode(&mut pc)
+    }
+
+    /// Parse the instruction at the given PC and advance the given PC. Returns number of cycles,
+    /// instruction and operand. On an illegal opcode, the given PC is left untouched and no
+    /// operand bytes are read.
+    fn decode(&self, pc: &mut u16) -> Result<(usize, Instruction, Operand), DecodeError> {
+        let mut next_pc = *pc;
+        let opcode: u8 = self.fetch(&mut next_pc);
+        let decoded = decode_opcode(&self.mem, opcode, &mut next_pc)
+            .ok_or(DecodeError { pc: *pc, opcode })?;
+        *pc = next_pc;
+        Ok(decoded)
     }
 
     /// Set ZERO_FLAG and NEGATIVE_FLAG based on the given value
@@ -260,191 +556,1034 @@ impl<M: Addressable> Mos6502<M> {
         // initialized to #$FF by the reset code. As the stack grows, SP decreases
         // down to #$00 (i.e. stack full). Stack access never leaves the stack page!
         self.sp = self.sp.wrapping_sub(mem::size_of::<T>() as u8);
-        let addr = Masked(0x0100, 0xff00).offset(self.sp as i16 + 1);
-        self.mem.set_le(addr, value);
+        self.mem.set_le(Masked(self.stack_top(), 0xff00), value);
     }
 
     /// Pop a value from the stack
     fn pop<const N: usize, T: Integer<N>>(&mut self) -> T {
         // See push() for details
-        let addr = Masked(0x0100, 0xff00).offset(self.sp as i16 + 1);
+        let addr = Masked(self.stack_top(), 0xff00);
         self.sp = self.sp.wrapping_add(mem::size_of::<T>() as u8);
         self.mem.get_le(addr)
     }
 
-    /// Interrupt the CPU (NMI)
-    pub fn nmi(&mut self) {
-        // Trigger the NMI line. The actual NMI processing is done in the next step().
-        self.nmi = true;
+    /// Push the given byte onto the stack, e.g. to set up a test scenario without executing
+    /// instructions
+    pub fn push_byte(&mut self, value: u8) {
+        self.push(value);
     }
 
-    /// Interrupt the CPU (IRQ)
-    pub fn irq(&mut self) {
-        // Trigger the IRQ line. The actual IRQ processing is done in the next step().
-        self.irq = true;
+    /// Push the given word onto the stack (high byte first, like JSR pushes the return address)
+    pub fn push_word(&mut self, value: u16) {
+        self.push(value);
     }
-}
 
-impl<M: Addressable> Cpu for Mos6502<M> {
-    /// Reset the CPU
-    fn reset(&mut self) {
-        // Trigger the RESET line. The actual RESET processing is done in the next step().
-        self.reset = true;
+    /// Pop a byte from the stack
+    pub fn pop_byte(&mut self) -> u8 {
+        self.pop()
     }
 
-    /// Do one step (execute the next instruction). Return the number of cycles
-    /// that were simulated.
-    fn step(&mut self) -> usize {
-        // Process RESET if line was triggered
+    /// Pop a word from the stack (low byte first, like RTS pops the return address)
+    pub fn pop_word(&mut self) -> u16 {
+        self.pop()
+    }
+
+    /// Trap the given address. Before the instruction at the given address is executed, the
+    /// given handler is invoked. The handler can emulate a routine in Rust and skip the real
+    /// instruction by changing the PC, e.g. using `return_from_subroutine`. Execution always
+    /// continues at the PC the handler leaves behind.
+    pub fn add_trap<F: FnMut(&mut Mos6502<M>) + 'static>(&mut self, addr: u16, handler: F) {
+        self.traps.insert(addr, Box::new(handler));
+    }
+
+    /// Remove the trap of the given address
+    pub fn remove_trap(&mut self, addr: u16) {
+        self.traps.remove(&addr);
+    }
+
+    /// Set a handler that is invoked whenever an NMI, IRQ or BRK sequence is taken. It is
+    /// invoked after PC and SR were pushed, before the first instruction of the interrupt
+    /// handler is executed.
+    pub fn set_interrupt_callback<F: FnMut(InterruptKind, u16) + 'static>(&mut self, handler: F) {
+        self.on_interrupt = Some(Box::new(handler));
+    }
+
+    /// Remove the interrupt handler
+    pub fn remove_interrupt_callback(&mut self) {
+        self.on_interrupt = None;
+    }
+
+    /// Notify the interrupt handler and call tracking that an interrupt sequence jumped to the
+    /// current PC
+    fn notify_interrupt(&mut self, kind: InterruptKind, return_addr: u16) {
+        self.calls.enter(CallFrame {
+            return_addr,
+            target: self.pc,
+            sp: self.sp,
+            kind: CallKind::Interrupt(kind),
+        });
+        if let Some(ref mut handler) = self.on_interrupt {
+            handler(kind, self.pc);
+        }
+    }
+
+    /// Set the number of executed instructions to keep in the history, which is helpful for
+    /// post-mortem diagnostics. A capacity of 0 (the default) disables the history.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history.set_capacity(capacity);
+    }
+
+    /// Returns the last executed instructions (oldest first) with the registers after
+    /// executing them if the history is enabled
+    pub fn history(&self) -> impl Iterator<Item = &TraceLine> {
+        self.history.iter()
+    }
+
+    /// Enable or disable tracking of subroutine calls and interrupts, which helps to find out
+    /// how execution got somewhere. Disabling discards all tracked frames.
+    pub fn set_call_tracking(&mut self, enabled: bool) {
+        self.calls.set_enabled(enabled);
+    }
+
+    /// Returns the subroutine calls and interrupts that haven't returned yet (outermost first)
+    /// if call tracking is enabled
+    pub fn call_stack(&self) -> &[CallFrame] {
+        self.calls.frames()
+    }
+
+    /// Enable or disable counting of executed instructions. Counts collected so far are kept
+    /// when profiling is disabled.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiling = enabled;
+    }
+
+    /// Returns the number of executions and spent cycles per instruction, collected while
+    /// profiling was enabled
+    pub fn profile(&self) -> &HashMap<Instruction, ProfileEntry> {
+        &self.profile
+    }
+
+    /// Discard all collected profiling counts
+    pub fn clear_profile(&mut self) {
+        self.profile.clear();
+    }
+
+    /// Enable or disable recording of executed instruction addresses. Enabling starts with an
+    /// empty coverage map, disabling discards it.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(|| vec![false; 0x10000]);
+    }
+
+    /// Returns the coverage map if coverage recording is enabled. It has one entry per address
+    /// of the address space, which is true if an instruction starting at that address was
+    /// executed.
+    pub fn coverage(&self) -> Option<&[bool]> {
+        self.coverage.as_deref()
+    }
+
+    /// Add a breakpoint. Execution stops before executing the instruction at the given address.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Remove the breakpoint at the given address
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Remove all breakpoints
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Returns the addresses of all breakpoints (in no particular order), e.g. for saving them
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Return from the current subroutine like an RTS instruction does. Useful for trap
+    /// handlers that replace a whole routine.
+    pub fn return_from_subroutine(&mut self) {
+        Instruction::RTS.execute(self, &Operand::Implied);
+    }
+
+    /// Do one clock cycle. Returns true if the current instruction (or interrupt sequence)
+    /// completed with this cycle. After as many ticks as a step() would take, the CPU is in the
+    /// exact same state as after the step().
+    ///
+    /// Instructions access memory in the cycle they do on real hardware: the opcode and each
+    /// operand byte are fetched in their own cycle, read-modify-write instructions read their
+    /// operand, write it back unmodified and write the result in their last three cycles, and
+    /// all other instructions access their operand in their last cycle. Interrupt sequences
+    /// are processed at once in their first cycle.
+    pub fn tick(&mut self) -> bool {
+        match self.in_flight {
+            Some(in_flight) => self.instruction_cycle(in_flight),
+            None if self.pending_cycles == 0 => self.begin_step(),
+            // Remaining cycles of an interrupt sequence
+            None => (),
+        }
+        self.pending_cycles -= 1;
+        self.cycles += 1;
+        self.pending_cycles == 0
+    }
+
+    /// Connect the given shared IRQ line. It is sampled before every instruction and an IRQ is
+    /// triggered as long as it is asserted (level-sensitive).
+    pub fn connect_irq(&mut self, line: IrqLine) {
+        self.irq_line = Some(line);
+    }
+
+    /// Connect the given shared NMI line. It is sampled before every instruction and an NMI is
+    /// triggered whenever it becomes asserted (edge-sensitive).
+    pub fn connect_nmi(&mut self, line: IrqLine) {
+        self.nmi_line = Some(line);
+        self.nmi_asserted = self.nmi_input();
+    }
+
+    /// Pull the NMI input low. Like on a real 6502, the NMI is edge-sensitive: an NMI is
+    /// latched if the input wasn't asserted before and processed once before the next
+    /// instruction, no matter how long the input is held. Use `deassert_nmi` to release it.
+    pub fn assert_nmi(&mut self) {
+        if !self.nmi_asserted {
+            self.nmi = true;
+        }
+        self.nmi_level = true;
+        self.nmi_asserted = true;
+    }
+
+    /// Release the NMI input, so the next `assert_nmi` triggers another NMI
+    pub fn deassert_nmi(&mut self) {
+        self.nmi_level = false;
+        self.nmi_asserted = self.nmi_input();
+    }
+
+    /// Returns the current level of the NMI inputs (true if any pulls the NMI low)
+    fn nmi_input(&self) -> bool {
+        self.nmi_level || self.nmi_line.as_ref().is_some_and(IrqLine::is_asserted)
+    }
+
+    /// Update the interrupt state from the connected shared interrupt lines
+    fn sample_interrupt_lines(&mut self) {
+        if let Some(ref line) = self.irq_line {
+            self.irq = line.is_asserted();
+        }
+        let asserted = self.nmi_input();
+        if asserted && !self.nmi_asserted {
+            self.nmi = true;
+        }
+        self.nmi_asserted = asserted;
+    }
+
+    /// Returns true if an NMI is pending, i.e. it was triggered (by `nmi` or an edge on the
+    /// shared NMI line) but not processed yet
+    pub fn nmi_pending(&self) -> bool {
+        self.nmi || (self.nmi_input() && !self.nmi_asserted)
+    }
+
+    /// Returns true if an IRQ is requested (by `irq` or the shared IRQ line). It is only
+    /// processed if interrupts aren't masked.
+    pub fn irq_pending(&self) -> bool {
+        self.irq || self.irq_line.as_ref().is_some_and(IrqLine::is_asserted)
+    }
+
+    /// Returns true if IRQs are masked by the interrupt disable flag
+    pub fn irq_masked(&self) -> bool {
+        self.sr.contains(StatusFlags::INTERRUPT_DISABLE_FLAG)
+    }
+
+    /// Returns true if the current instruction (or interrupt sequence) is completed, i.e. the
+    /// next tick starts a new one
+    pub fn is_between_instructions(&self) -> bool {
+        self.pending_cycles == 0
+    }
+
+    /// Returns the interrupt sequence the next step processes. If several interrupts are
+    /// pending at an instruction boundary, the one with the highest priority wins
+    /// (RESET > NMI > IRQ) and the others stay pending.
+    ///
+    /// On a real 6502, an NMI that occurs during the first cycles of an IRQ (or BRK) sequence
+    /// hijacks it: PC and SR are pushed for the IRQ, but the NMI vector is fetched, so the NMI
+    /// handler runs and the IRQ is only seen again at a later boundary. Since interrupts are
+    /// only sampled and processed at instruction boundaries here, an NMI and an IRQ that both
+    /// become pending during the same instruction are seen together at the next boundary, which
+    /// has the same outcome: the NMI sequence is taken and the IRQ stays pending (it is
+    /// level-sensitive) until the following boundary.
+    fn next_interrupt(&self) -> Option<Interrupt> {
         if self.reset {
-            // A RESET jumps to the vector at RESET_VECTOR and sets INTERRUPT_DISABLE_FLAG.
-            // Note that all other states and registers are unspecified and might contain
-            // random values, so they need to be initialized by the reset routine.
-            // See also http://6502.org/tutorials/interrupts.html
-            self.sr.insert(StatusFlags::INTERRUPT_DISABLE_FLAG);
-            self.pc = self.mem.get_le(RESET_VECTOR);
-            self.reset = false;
-            self.nmi = false;
-            self.irq = false;
-            debug!(
-                "mos6502: RESET - Jumping to ({}) -> {}",
-                RESET_VECTOR.display(),
-                self.pc.display()
-            );
-            return 6;
-        }
-        // Process NMI if line was triggered
-        if self.nmi {
-            // An NMI pushes PC and SR to the stack and jumps to the vector at NMI_VECTOR.
-            // It does NOT set the INTERRUPT_DISABLE_FLAG. Unlike JSR, it pushes the address
-            // of the next instruction to the stack.
-            // See also http://6502.org/tutorials/interrupts.html
-            self.push(self.pc);
-            self.push(self.sr.bits());
-            self.pc = self.mem.get_le(NMI_VECTOR);
-            self.nmi = false;
-            debug!(
-                "mos6502: NMI - Jumping to ({}) -> {}",
-                NMI_VECTOR.display(),
-                self.pc.display()
-            );
-            return 7;
-        }
-        // Process IRQ if line was triggered and interrupts are enabled
-        if self.irq && !self.sr.contains(StatusFlags::INTERRUPT_DISABLE_FLAG) {
-            // An IRQ pushes PC and SR to the stack, jumps to the vector at IRQ_VECTOR and
-            // sets the INTERRUPT_DISABLE_FLAG. Unlike JSR, it pushes the address of the next
-            // instruction to the stack. This also emulates the BRK bug where a BRK instruction
-            // is ignored if an IRQ occurs simultaneously.
-            // The BRK instruction does the same, but sets BREAK_FLAG (before pushing SR).
-            // See also http://6502.org/tutorials/interrupts.html
-            self.sr.remove(StatusFlags::BREAK_FLAG);
-            if self.mem.get(self.pc) == 0x00 {
-                // Simulate BRK bug
-                self.pc += 1;
+            Some(Interrupt::Reset)
+        } else if self.halted {
+            // A jammed CPU doesn't react to NMI or IRQ
+            None
+        } else if self.nmi {
+            Some(Interrupt::Nmi)
+        } else if self.irq && !self.irq_masked() {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+
+    /// Start the next interrupt sequence or instruction. An interrupt sequence is processed at
+    /// once, of an instruction only the opcode is fetched.
+    fn begin_step(&mut self) {
+        self.sample_interrupt_lines();
+        let pc = self.pc;
+        let (cycles, executed) = match self.next_interrupt() {
+            Some(Interrupt::Reset) => (self.process_reset(), Executed::Reset),
+            Some(Interrupt::Nmi) => (self.process_nmi(), Executed::Interrupt(InterruptKind::Nmi)),
+            Some(Interrupt::Irq) => (self.process_irq(), Executed::Interrupt(InterruptKind::Irq)),
+            None => match self.fetch_opcode() {
+                Some(in_flight) => {
+                    self.pending_cycles = in_flight.info.cycles;
+                    self.in_flight = Some(in_flight);
+                    return;
+                }
+                None => (1, Executed::Jammed),
+            },
+        };
+        self.pending_cycles = cycles;
+        self.last_step = (pc, executed);
+    }
+
+    /// Process a RESET sequence. Returns the number of cycles it takes.
+    fn process_reset(&mut self) -> usize {
+        // A RESET jumps to the vector at RESET_VECTOR and sets INTERRUPT_DISABLE_FLAG.
+        // Note that all other states and registers are unspecified and might contain
+        // random values, so they need to be initialized by the reset routine.
+        // See also http://6502.org/tutorials/interrupts.html
+        self.sr.insert(StatusFlags::INTERRUPT_DISABLE_FLAG);
+        self.pc = self.mem.get_le(RESET_VECTOR);
+        self.reset = false;
+        self.nmi = false;
+        self.irq = false;
+        self.halted = false;
+        self.calls.clear();
+        self.log_interrupt("RESET", RESET_VECTOR);
+        6
+    }
+
+    /// Process an NMI sequence. Returns the number of cycles it takes.
+    fn process_nmi(&mut self) -> usize {
+        // An NMI pushes PC and SR to the stack and jumps to the vector at NMI_VECTOR.
+        // It does NOT set the INTERRUPT_DISABLE_FLAG. Unlike JSR, it pushes the address
+        // of the next instruction to the stack.
+        // See also http://6502.org/tutorials/interrupts.html
+        let return_addr = self.pc;
+        self.push(return_addr);
+        self.push(self.sr.bits());
+        self.pc = self.mem.get_le(NMI_VECTOR);
+        self.nmi = false;
+        self.log_interrupt("NMI", NMI_VECTOR);
+        self.notify_interrupt(InterruptKind::Nmi, return_addr);
+        7
+    }
+
+    /// Process an IRQ sequence. Returns the number of cycles it takes.
+    fn process_irq(&mut self) -> usize {
+        // An IRQ pushes PC and SR to the stack, jumps to the vector at IRQ_VECTOR and
+        // sets the INTERRUPT_DISABLE_FLAG. Unlike JSR, it pushes the address of the next
+        // instruction to the stack. This also emulates the BRK bug where a BRK instruction
+        // is ignored if an IRQ occurs simultaneously.
+        // The BRK instruction does the same, but sets BREAK_FLAG (before pushing SR).
+        // See also http://6502.org/tutorials/interrupts.html
+        self.sr.remove(StatusFlags::BREAK_FLAG);
+        if self.mem.get(self.pc) == 0x00 {
+            // Simulate BRK bug
+            self.pc = self.pc.wrapping_add(1);
+        }
+        let return_addr = self.pc;
+        self.push(return_addr);
+        self.push(self.sr.bits());
+        self.sr.insert(StatusFlags::INTERRUPT_DISABLE_FLAG);
+        self.pc = self.mem.get_le(IRQ_VECTOR);
+        // FIXME: The real 6502 IRQ line is level-sensitive, not edge-sensitive!
+        // FIXME: I.e. it does not stop jumping to the IRQ_VECTOR after one run,
+        // FIXME: but after the hardware drops the IRQ line (which the interrupt
+        // FIXME: code usually causes, but not necessary needs to cause).
+        self.irq = false;
+        self.log_interrupt("IRQ", IRQ_VECTOR);
+        self.notify_interrupt(InterruptKind::Irq, return_addr);
+        7
+    }
+
+    /// Fetch the opcode at the PC, which is the first cycle of an instruction. Returns the
+    /// instruction in flight or None if the CPU is (or got) jammed.
+    fn fetch_opcode(&mut self) -> Option<InFlight> {
+        // A jammed CPU doesn't do anything until RESET
+        if self.halted {
+            return None;
+        }
+        // Invoke trap handler if the next instruction is trapped
+        if !self.traps.is_empty() {
+            if let Some(mut handler) = self.traps.remove(&self.pc) {
+                let pc = self.pc;
+                handler(self);
+                self.traps.insert(pc, handler);
             }
-            self.push(self.pc);
-            self.push(self.sr.bits());
-            self.sr.insert(StatusFlags::INTERRUPT_DISABLE_FLAG);
-            self.pc = self.mem.get_le(IRQ_VECTOR);
-            // FIXME: The real 6502 IRQ line is level-sensitive, not edge-sensitive!
-            // FIXME: I.e. it does not stop jumping to the IRQ_VECTOR after one run,
-            // FIXME: but after the hardware drops the IRQ line (which the interrupt
-            // FIXME: code usually causes, but not necessary needs to cause).
-            self.irq = false;
-            debug!(
-                "mos6502: IRQ - Jumping to ({}) -> {}",
-                IRQ_VECTOR.display(),
-                self.pc.display()
-            );
-            return 7;
         }
-        // Read and parse next opcode
-        let old_pc = self.pc;
-        match self.next_instruction() {
+        // Read and look up next opcode
+        let pc = self.pc;
+        let opcode: u8 = self.mem.get(pc);
+        match OPCODE_TABLE[opcode as usize] {
             // Got valid opcode
-            Some((cycles, instruction, operand)) => {
-                let new_pc = self.pc;
-                instruction.execute(self, &operand);
-                // FIXME: formatting doesn't work!?
-                trace!("mos6502: {}  {:8}  {:3} {:15}  -[{}]-> AC:{:02X} X:{:02X} Y:{:02X} SR:{:02X} SP:{:02X} NV-BDIZC:{:08b}",
-                    old_pc.display(), self.mem.hexdump(old_pc..new_pc), instruction, operand,
-                    cycles, self.ac, self.x, self.y, self.sr.bits(), self.sp, self.sr.bits());
-                cycles
+            Some(info) => {
+                self.pc = pc.wrapping_add(1);
+                Some(InFlight {
+                    pc,
+                    opcode,
+                    info,
+                    cycles: info.cycles,
+                    bytes: [0; 2],
+                    resolved: None,
+                })
+            }
+            // Got JAM opcode, which stops the CPU
+            None if is_jam(opcode) => {
+                warn!("mos6502: CPU jammed: {}", DecodeError { pc, opcode });
+                self.halted = true;
+                None
             }
             // Got illegal opcode
             None => {
+                let err = DecodeError { pc, opcode };
+                #[cfg(feature = "tracing")]
+                tracing::error!(pc = err.pc, opcode = err.opcode, "illegal opcode");
+                #[cfg(not(feature = "tracing"))]
                 trace!(
-                    "mos6502: {}  {:8}  ???",
-                    old_pc.display(),
-                    self.mem.hexdump(old_pc..old_pc + 2)
+                    "mos6502: {}  {:02X}        ???",
+                    err.pc.display(),
+                    err.opcode
                 );
                 panic!(
-                    "mos6502: Illegal opcode #${:02X} at {}",
-                    self.mem.get(old_pc),
-                    old_pc.display()
+                    "mos6502: {}\n{}\n{}\nCall stack:\n{}",
+                    err, self, self.history, self.calls
                 );
             }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::mem::test::TestMemory;
-    use crate::mem::{Ram, Rom};
+    /// Do the next cycle of the given instruction in flight: fetch an operand byte, resolve
+    /// the operand of an indexed read, do the read or the unmodified write of a
+    /// read-modify-write instruction, or execute the instruction in its last cycle. Penalty
+    /// cycles of taken branches and indexed reads that cross a page are added as soon as the
+    /// real CPU knows about them.
+    fn instruction_cycle(&mut self, mut in_flight: InFlight) {
+        let info = in_flight.info;
+        let cycle = in_flight.cycles - self.pending_cycles + 1;
+        let mut penalty = 0;
+        if cycle <= info.len as usize {
+            in_flight.bytes[cycle - 2] = self.mem.get(self.pc);
+            self.pc = self.pc.wrapping_add(1);
+            if cycle == info.len as usize && info.instruction.branches(self.sr) {
+                // A taken branch takes one more cycle, and another one if it crosses a page
+                let target = Operand::new(info.mode, in_flight.bytes).addr(self);
+                penalty = if target.same_page(self.pc) { 1 } else { 2 };
+            }
+        }
+        if has_page_crossing_penalty(&info) && cycle + 1 == info.cycles {
+            // An indexed read takes one more cycle if indexing crosses a page
+            let operand = Operand::new(info.mode, in_flight.bytes);
+            let addr = operand.addr(self);
+            let index = match info.mode {
+                AddressingMode::AbsoluteIndexedWithX => self.x,
+                _ => self.y,
+            };
+            if !addr.same_page(addr.wrapping_sub(index as u16)) {
+                penalty = 1;
+            }
+            in_flight.resolved = Some(ResolvedOperand::Address(addr));
+        }
+        if is_read_modify_write(&info) {
+            if cycle + 2 == info.cycles {
+                let addr = Operand::new(info.mode, in_flight.bytes).addr(self);
+                in_flight.resolved = Some(ResolvedOperand::Latched(addr, self.mem.get(addr)));
+            } else if let (true, Some(ResolvedOperand::Latched(addr, value))) =
+                (cycle + 1 == info.cycles, in_flight.resolved)
+            {
+                self.mem.set(addr, value);
+            }
+        }
+        in_flight.cycles += penalty;
+        self.pending_cycles += penalty;
+        if cycle == in_flight.cycles {
+            self.in_flight = None;
+            self.finish_instruction(in_flight);
+        } else {
+            self.in_flight = Some(in_flight);
+        }
+    }
 
-    #[test]
-    fn smoke() {
-        let mut cpu = Mos6502::new(TestMemory);
-        cpu.reset();
-        cpu.nmi();
-        cpu.irq();
-        cpu.step();
+    /// Execute the given instruction in flight, whose opcode and operand bytes were fetched,
+    /// and record it
+    fn finish_instruction(&mut self, in_flight: InFlight) {
+        let InFlight {
+            pc: old_pc,
+            opcode,
+            info,
+            cycles,
+            bytes,
+            resolved,
+        } = in_flight;
+        let instruction = info.instruction;
+        let operand = Operand::new(info.mode, bytes);
+        {
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("instruction", pc = old_pc).entered();
+            let resolved = resolved.unwrap_or_else(|| operand.resolve(self));
+            instruction.execute_resolved(self, resolved);
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage[old_pc as usize] = true;
+        }
+        if self.profiling {
+            let entry = self.profile.entry(instruction).or_default();
+            entry.count += 1;
+            entry.cycles += cycles as u64;
+        }
+        let bytes = [opcode, bytes[0], bytes[1]];
+        let line = TraceLine::new(
+            self,
+            old_pc,
+            &bytes[..info.len as usize],
+            instruction,
+            operand,
+            cycles,
+        );
+        if self.history.is_enabled() {
+            self.history.push(line);
+        }
+        self.log_instruction(&line);
+        if self.trace_writer.is_some() {
+            self.write_trace(&line);
+        }
+        self.last_step = (old_pc, Executed::Instruction(instruction, operand));
     }
 
-    #[test]
-    fn initial_state() {
-        let cpu = Mos6502::new(TestMemory);
-        assert_eq!(cpu.pc, 0x0000);
-        assert_eq!(cpu.sr, StatusFlags::UNUSED_ALWAYS_ON_FLAG);
-        assert!(cpu.reset);
+    /// Do one step like `step`, but also return what was executed, which makes it easy to
+    /// check what just ran without installing a hook
+    pub fn step_traced(&mut self) -> StepResult {
+        let cycles = Cpu::step(self);
+        let (pc, executed) = self.last_step;
+        StepResult {
+            cycles,
+            pc,
+            next_pc: self.pc,
+            executed,
+        }
     }
 
-    #[test]
-    fn fetch_memory_contents_and_advance_pc() {
-        let mut cpu = Mos6502::new(TestMemory);
-        cpu.pc = 0x0012;
-        let value: u8 = cpu.next();
-        assert_eq!(value, 0x12);
-        let value: u8 = cpu.next();
-        assert_eq!(value, 0x13);
-        let value: u16 = cpu.next();
-        assert_eq!(value, 0x1514);
-        let value: u16 = cpu.next();
-        assert_eq!(value, 0x1716);
+    /// Returns true if the CPU is jammed by a JAM opcode. Only a RESET recovers from that.
+    pub fn is_halted(&self) -> bool {
+        self.halted && !self.reset
     }
 
-    #[test]
-    fn fetch_instruction_and_advance_pc() {
-        let mut cpu = Mos6502::new(TestMemory);
-        cpu.pc = 0x00ad; // AD AE AF: LDA $AFAE
-        let (cycles, instruction, operand) = cpu.next_instruction().unwrap();
-        assert_eq!(cycles, 4);
-        assert_eq!(instruction, Instruction::LDA);
-        assert_eq!(operand, Operand::Absolute(0xafae));
+    /// Returns true if the next step executes the instruction at a breakpoint (i.e. the PC is at
+    /// a breakpoint and no interrupt is going to be serviced first)
+    pub fn at_breakpoint(&self) -> bool {
+        !self.breakpoints.is_empty()
+            && self.breakpoints.contains(&self.pc)
+            && !self.interrupt_pending()
     }
 
-    #[test]
-    fn status_flags() {
-        let mut cpu = Mos6502::new(TestMemory);
-        cpu.sr = StatusFlags::ZERO_FLAG
-            | StatusFlags::DECIMAL_FLAG
-            | StatusFlags::UNUSED_ALWAYS_ON_FLAG
-            | StatusFlags::NEGATIVE_FLAG;
-        cpu.sr.set(StatusFlags::CARRY_FLAG, true);
-        cpu.sr.set(StatusFlags::ZERO_FLAG, false);
+    /// Returns true if the next step is going to process an interrupt instead of executing the
+    /// instruction at the PC
+    fn interrupt_pending(&self) -> bool {
+        self.reset || self.nmi_pending() || (self.irq_pending() && !self.irq_masked())
+    }
+
+    /// Log that an interrupt sequence jumped through the given vector to the current PC
+    fn log_interrupt(&self, interrupt: &str, vector: u16) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(interrupt, vector, pc = self.pc, "interrupt");
+        #[cfg(not(feature = "tracing"))]
+        debug!(
+            "mos6502: {} - Jumping to ({}) -> {}",
+            interrupt,
+            vector.display(),
+            self.pc.display()
+        );
+    }
+
+    /// Log the given executed instruction. With the `tracing` feature, an event with the fields
+    /// of the trace line is emitted, otherwise the trace line is logged.
+    fn log_instruction(&self, line: &TraceLine) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            pc = line.pc,
+            opcode = line.bytes()[0],
+            mnemonic = %line.instruction,
+            operand = %line.operand,
+            cycles = line.cycles,
+            a = line.ac,
+            x = line.x,
+            y = line.y,
+            sr = line.sr.bits(),
+            sp = line.sp,
+            "instruction"
+        );
+        #[cfg(not(feature = "tracing"))]
+        if log_enabled!(Level::Trace) {
+            trace!("mos6502: {}", line);
+        }
+    }
+
+    /// Write the given trace line to the trace writer. If writing fails, a warning is logged
+    /// and the trace writer is disabled.
+    fn write_trace(&mut self, line: &TraceLine) {
+        if let Some(mut writer) = self.trace_writer.take() {
+            match writeln!(writer, "{}", line) {
+                Ok(()) => self.trace_writer = Some(writer),
+                Err(err) => warn!(
+                    "mos6502: Unable to write trace, disabling trace writer: {}",
+                    err
+                ),
+            }
+        }
+    }
+
+    /// Set a writer that every executed instruction is traced to (in addition to the trace log
+    /// level), or disable it with `None`. Returns the previous writer.
+    pub fn set_trace_writer(&mut self, writer: Option<Box<dyn Write>>) -> Option<Box<dyn Write>> {
+        mem::replace(&mut self.trace_writer, writer)
+    }
+
+    /// Flush the trace writer
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match self.trace_writer.as_mut() {
+            Some(writer) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Step until the given predicate returns true (checked after every instruction) or at
+    /// least the given number of cycles were simulated
+    pub fn run_until<F>(&mut self, max_cycles: u64, mut pred: F) -> RunResult
+    where
+        F: FnMut(&Mos6502<M>) -> bool,
+    {
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            cycles += self.step() as u64;
+            if pred(self) {
+                return RunResult::Stopped(cycles);
+            }
+        }
+        RunResult::BudgetExhausted(cycles)
+    }
+
+    /// Step over the next instruction. If it is a JSR, run until the subroutine returned to the
+    /// following instruction at the same stack depth (recursive calls and interrupts in between
+    /// don't stop), otherwise just step. Stops after at least the given number of cycles were
+    /// simulated if the subroutine didn't return by then.
+    pub fn step_over(&mut self, max_cycles: u64) -> RunResult {
+        if self.interrupt_pending() || self.mem.get(self.pc) != JSR_OPCODE {
+            return RunResult::Stopped(self.step() as u64);
+        }
+        let (return_addr, sp) = (self.pc.wrapping_add(3), self.sp);
+        self.run_until(max_cycles, |cpu| cpu.pc == return_addr && cpu.sp == sp)
+    }
+
+    /// Run until the current subroutine (or interrupt handler) returned, i.e. until an RTS (or
+    /// RTI) unwinds the stack above the current stack pointer. Returns of nested subroutines and
+    /// interrupts in between don't stop. Stops after at least the given number of cycles were
+    /// simulated if the subroutine didn't return by then.
+    pub fn step_out(&mut self, max_cycles: u64) -> RunResult {
+        let sp = self.sp;
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            let returning = !self.interrupt_pending()
+                && matches!(self.mem.get(self.pc), RTS_OPCODE | RTI_OPCODE);
+            cycles += self.step() as u64;
+            // The stack wraps around, so compare the distance the stack was unwound
+            if returning && (self.sp.wrapping_sub(sp) as i8) > 0 {
+                return RunResult::Stopped(cycles);
+            }
+        }
+        RunResult::BudgetExhausted(cycles)
+    }
+
+    /// Step until the next step would execute the instruction at a breakpoint (see
+    /// `at_breakpoint`) or at least the given number of cycles were simulated. If the CPU is at
+    /// a breakpoint already, it stops immediately, so use step() to continue from a breakpoint.
+    pub fn run_until_break(&mut self, max_cycles: u64) -> RunResult {
+        let mut cycles = 0;
+        while cycles < max_cycles {
+            if self.at_breakpoint() {
+                return RunResult::Breakpoint(cycles);
+            }
+            cycles += self.step() as u64;
+        }
+        RunResult::BudgetExhausted(cycles)
+    }
+}
+
+impl<M: fmt::Debug> fmt::Debug for Mos6502<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Mos6502")
+            .field("pc", &self.pc)
+            .field("ac", &self.ac)
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("sr", &self.sr)
+            .field("sp", &self.sp)
+            .field("mem", &self.mem)
+            .field("reset", &self.reset)
+            .field("nmi", &self.nmi)
+            .field("irq", &self.irq)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M: Addressable + Default> Default for Mos6502<M> {
+    fn default() -> Mos6502<M> {
+        Mos6502::new(M::default())
+    }
+}
+
+impl<M> fmt::Display for Mos6502<M> {
+    /// Format a one-line register dump in monitor style, like
+    /// `PC=$1234 A=$0A X=$00 Y=$FF SP=$F7 P=$25 [nv-bdIzC]` (flags in uppercase are set). The
+    /// alternate form (`{:#}`) shows the flags bitwise instead, like
+    /// `PC:1234 A:0A X:00 Y:FF SP:F7 NV-BDIZC:00100101`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if f.alternate() {
+            return write!(
+                f,
+                "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X} NV-BDIZC:{:08b}",
+                self.pc,
+                self.ac,
+                self.x,
+                self.y,
+                self.sp,
+                self.sr.bits()
+            );
+        }
+        write!(
+            f,
+            "PC=${:04X} A=${:02X} X=${:02X} Y=${:02X} SP=${:02X} P=${:02X} [{}]",
+            self.pc,
+            self.ac,
+            self.x,
+            self.y,
+            self.sp,
+            self.sr.bits(),
+            self.sr
+        )
+    }
+}
+
+impl<M: Addressable> Cpu for Mos6502<M> {
+    /// Reset the CPU
+    fn reset(&mut self) {
+        // Trigger the RESET line. The actual RESET processing is done in the next step().
+        self.reset = true;
+    }
+
+    /// Do one step (execute the next instruction). Return the number of cycles
+    /// that were simulated.
+    fn step(&mut self) -> usize {
+        let mut cycles = 1;
+        while !self.tick() {
+            cycles += 1;
+        }
+        cycles
+    }
+
+    /// Interrupt the CPU (NMI)
+    fn nmi(&mut self) {
+        // Pulse the NMI input, which latches an NMI. The actual NMI processing is done in the
+        // next step().
+        self.nmi = true;
+    }
+
+    /// Interrupt the CPU (IRQ)
+    fn irq(&mut self) {
+        // Trigger the IRQ line. The actual IRQ processing is done in the next step().
+        self.irq = true;
+    }
+
+    /// Returns the program counter (PC)
+    fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Returns true if the CPU is jammed
+    fn is_halted(&self) -> bool {
+        Mos6502::is_halted(self)
+    }
+
+    /// Returns true if the next instruction is at a breakpoint
+    fn at_breakpoint(&self) -> bool {
+        Mos6502::at_breakpoint(self)
+    }
+}
+
+/// Returns true if the given opcode is a read-modify-write instruction on memory, which reads
+/// its operand, writes it back unmodified and writes the result in its last three cycles
+fn is_read_modify_write(info: &OpcodeInfo) -> bool {
+    matches!(
+        info.instruction,
+        Instruction::ASL
+            | Instruction::LSR
+            | Instruction::ROL
+            | Instruction::ROR
+            | Instruction::INC
+            | Instruction::DEC
+    ) && info.mode != AddressingMode::Accumulator
+}
+
+/// Returns true if the given opcode is an indexed read, which takes one more cycle if indexing
+/// crosses a page
+fn has_page_crossing_penalty(info: &OpcodeInfo) -> bool {
+    matches!(
+        info.instruction,
+        Instruction::LDA
+            | Instruction::LDX
+            | Instruction::LDY
+            | Instruction::AND
+            | Instruction::EOR
+            | Instruction::ORA
+            | Instruction::ADC
+            | Instruction::SBC
+            | Instruction::CMP
+    ) && matches!(
+        info.mode,
+        AddressingMode::AbsoluteIndexedWithX
+            | AddressingMode::AbsoluteIndexedWithY
+            | AddressingMode::ZeroPageIndirectIndexedWithY
+    )
+}
+
+/// Returns true if the given opcode is one of the illegal opcodes that jam the CPU
+fn is_jam(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2 | 0xf2
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{RunOutcome, StopReason};
+    use crate::mem::test::TestMemory;
+    use crate::mem::{Access, AccessKind, Logged, Ram, RamInit};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Step the CPU the given number of times and return the execution log
+    fn trace<M: Addressable>(cpu: &mut Mos6502<M>, steps: usize) -> Vec<String> {
+        let formatter = TraceFormatter::new();
+        (0..steps)
+            .map(|_| {
+                let line = formatter.format(cpu);
+                cpu.step();
+                line
+            })
+            .collect()
+    }
+
+    /// Run the CPU until the PC stops changing (i.e. a jump or branch to itself, which is how
+    /// test ROMs signal their result) or the cycle budget is exhausted. Returns the trap address.
+    fn run_until_trap<M: Addressable>(cpu: &mut Mos6502<M>, max_cycles: u64) -> Option<u16> {
+        let mut last_pc = cpu.pc;
+        let result = cpu.run_until(max_cycles, |cpu| {
+            let trapped = cpu.pc == last_pc;
+            last_pc = cpu.pc;
+            trapped
+        });
+        match result {
+            RunResult::Stopped(_) => Some(cpu.pc),
+            RunResult::Breakpoint(_) | RunResult::BudgetExhausted(_) => None,
+        }
+    }
+
+    #[test]
+    fn smoke() {
+        let mut cpu = Mos6502::new(TestMemory);
+        cpu.reset();
+        cpu.nmi();
+        cpu.irq();
+        cpu.step();
+    }
+
+    #[test]
+    fn initial_state() {
+        let cpu = Mos6502::new(TestMemory);
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.sr, StatusFlags::UNUSED_ALWAYS_ON_FLAG);
+        assert!(cpu.reset);
+    }
+
+    #[test]
+    fn register_access() {
+        let mut cpu = Mos6502::new(TestMemory);
+        cpu.set_pc(0x1234);
+        cpu.set_a(0x12);
+        cpu.set_x(0x34);
+        cpu.set_y(0x56);
+        cpu.set_sp(0xfd);
+        cpu.set_status(StatusFlags::CARRY_FLAG);
+        assert_eq!(cpu.pc(), 0x1234);
+        assert_eq!(
+            (cpu.a(), cpu.x(), cpu.y(), cpu.sp()),
+            (0x12, 0x34, 0x56, 0xfd)
+        );
+        assert_eq!(
+            cpu.status(),
+            StatusFlags::CARRY_FLAG | StatusFlags::UNUSED_ALWAYS_ON_FLAG
+        );
+    }
+
+    #[test]
+    fn memory_access() {
+        let mut cpu = Mos6502::new(Ram::with_capacity(0x03ff));
+        cpu.memory_mut().set(0x0123, 0x55);
+        assert_eq!(cpu.memory().get(0x0123), 0x55);
+        let mem = cpu.into_inner();
+        assert_eq!(mem.get(0x0123), 0x55);
+    }
+
+    #[test]
+    fn default() {
+        let cpu: Mos6502<Ram> = Default::default();
+        assert_eq!(cpu.save_state(), Mos6502::new(Ram::new()).save_state());
+        assert_eq!(cpu.mem.capacity(), 0x10000);
+    }
+
+    #[test]
+    fn fetch_memory_contents_and_advance_pc() {
+        let mut cpu = Mos6502::new(TestMemory);
+        cpu.pc = 0x0012;
+        let value: u8 = cpu.next();
+        assert_eq!(value, 0x12);
+        let value: u8 = cpu.next();
+        assert_eq!(value, 0x13);
+        let value: u16 = cpu.next();
+        assert_eq!(value, 0x1514);
+        let value: u16 = cpu.next();
+        assert_eq!(value, 0x1716);
+    }
+
+    #[test]
+    fn fetch_wraps_around_end_of_memory() {
+        let mut cpu = Mos6502::new(TestMemory);
+        cpu.pc = 0xffff;
+        let value: u16 = cpu.next();
+        assert_eq!(value, 0x00fe);
+        assert_eq!(cpu.pc, 0x0001);
+    }
+
+    #[test]
+    fn instructions_wrap_around_end_of_memory() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0xfffe, [0xad, 0x34]); // AD 34 12: LDA $1234
+        cpu.mem.set(0x0000, 0x12);
+        cpu.mem.set(0x1234, 0x42);
+        cpu.pc = 0xfffe;
+        cpu.reset = false;
+        cpu.step();
+        assert_eq!(cpu.ac, 0x42);
+        assert_eq!(cpu.pc, 0x0001);
+        cpu.mem.set(0xffff, 0x4c); // 4C 00 20: JMP $2000
+        cpu.mem.setn(0x0000, [0x00, 0x20]);
+        cpu.pc = 0xffff;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x2000);
+    }
+
+    #[test]
+    fn subroutine_return_wraps_around_end_of_memory() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0xfffd, [0x20, 0x00, 0x30]); // 20 00 30: JSR $3000
+        cpu.mem.set(0x3000, 0x60); // 60: RTS
+        cpu.pc = 0xfffd;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x3000);
+        let return_addr: u16 = cpu.mem.get_le(0x01fe_u16);
+        assert_eq!(return_addr, 0xffff);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.sp, 0xff);
+    }
+
+    #[test]
+    fn brk_wraps_around_end_of_memory() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        // The low byte of the IRQ vector at $FFFE doubles as BRK opcode
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.pc = 0xfffe;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x2000);
+        let return_addr: u16 = cpu.mem.get_le(0x01fe_u16);
+        assert_eq!(return_addr, 0x0000);
+    }
+
+    #[test]
+    fn fetch_instruction_and_advance_pc() {
+        let mut cpu = Mos6502::new(TestMemory);
+        cpu.pc = 0x00ad; // AD AE AF: LDA $AFAE
+        let (cycles, instruction, operand) = cpu.next_instruction().unwrap();
+        assert_eq!(cycles, 4);
+        assert_eq!(instruction, Instruction::LDA);
+        assert_eq!(operand, Operand::Absolute(0xafae));
+    }
+
+    #[test]
+    fn illegal_opcode_leaves_pc() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0x0fff));
+        cpu.mem.setn(0x0800, [0xff, 0xa9]);
+        cpu.pc = 0x0800;
+        let err = DecodeError {
+            pc: 0x0800,
+            opcode: 0xff,
+        };
+        assert_eq!(cpu.peek_instruction(), Err(err));
+        assert_eq!(cpu.next_instruction(), Err(err));
+        assert_eq!(cpu.pc, 0x0800);
+        assert_eq!(err.to_string(), "Illegal opcode #$FF at $0800");
+        // Operand bytes beyond the end of memory aren't read
+        cpu.mem.set(0x0fff, 0x02);
+        cpu.pc = 0x0fff;
+        let err = DecodeError {
+            pc: 0x0fff,
+            opcode: 0x02,
+        };
+        assert_eq!(cpu.next_instruction(), Err(err));
+        assert_eq!(cpu.pc, 0x0fff);
+        cpu.reset = false;
+        cpu.step();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.pc, 0x0fff);
+    }
+
+    #[test]
+    fn peek_instruction_does_not_advance_pc() {
+        let mut cpu = Mos6502::new(TestMemory);
+        cpu.pc = 0x00ad; // AD AE AF: LDA $AFAE
+        let first = cpu.peek_instruction().unwrap();
+        let second = cpu.peek_instruction().unwrap();
+        assert_eq!(first, (4, Instruction::LDA, Operand::Absolute(0xafae)));
+        assert_eq!(first, second);
+        assert_eq!(cpu.pc, 0x00ad);
+        assert_eq!(cpu.next_instruction().unwrap(), first);
+        assert_eq!(cpu.pc, 0x00b0);
+    }
+
+    #[test]
+    fn status_flags() {
+        let mut cpu = Mos6502::new(TestMemory);
+        cpu.sr = StatusFlags::ZERO_FLAG
+            | StatusFlags::DECIMAL_FLAG
+            | StatusFlags::UNUSED_ALWAYS_ON_FLAG
+            | StatusFlags::NEGATIVE_FLAG;
+        cpu.sr.set(StatusFlags::CARRY_FLAG, true);
+        cpu.sr.set(StatusFlags::ZERO_FLAG, false);
         cpu.sr.set(StatusFlags::OVERFLOW_FLAG, true);
         cpu.sr.set(StatusFlags::NEGATIVE_FLAG, false);
         assert_eq!(
@@ -489,6 +1628,46 @@ mod tests {
         assert_eq!(cpu.sp, 0xff);
     }
 
+    #[test]
+    fn stack_push_pop_public() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.set_sp(0xff);
+        cpu.push_byte(0x12);
+        cpu.push_word(0x3456);
+        assert_eq!(cpu.sp(), 0xfc);
+        assert_eq!(cpu.mem.get(0x01ff), 0x12);
+        assert_eq!(cpu.mem.get(0x01fe), 0x34);
+        assert_eq!(cpu.mem.get(0x01fd), 0x56);
+        assert_eq!(cpu.pop_word(), 0x3456);
+        assert_eq!(cpu.pop_byte(), 0x12);
+        assert_eq!(cpu.sp(), 0xff);
+        // A pushed return address is used by RTS
+        cpu.mem.set(0x1000, 0x60); // 60: RTS
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.push_word(0x2002);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x2003);
+    }
+
+    #[test]
+    fn stack_addresses() {
+        let mut cpu = Mos6502::new(Ram::with_capacity(0x01ff));
+        cpu.sp = 0xff;
+        assert_eq!(cpu.stack_addr(), 0x01ff);
+        assert_eq!(cpu.stack_top(), 0x0100);
+        cpu.push(0x12_u8);
+        assert_eq!(cpu.stack_addr(), 0x01fe);
+        assert_eq!(cpu.stack_top(), 0x01ff);
+        assert_eq!(cpu.mem.get(cpu.stack_top()), 0x12);
+        cpu.push(0x3456_u16);
+        assert_eq!(cpu.stack_addr(), 0x01fc);
+        assert_eq!(cpu.mem.get(cpu.stack_top()), 0x56);
+        cpu.sp = 0x00;
+        assert_eq!(cpu.stack_addr(), 0x0100);
+        assert_eq!(cpu.stack_top(), 0x0101);
+    }
+
     #[test]
     fn stack_overflow() {
         let mut cpu = Mos6502::new(Ram::with_capacity(0x01ff));
@@ -592,29 +1771,1270 @@ mod tests {
     }
 
     #[test]
-    fn ruud_baltissen_core_instruction_rom() {
-        // Test all instructions using Ruud Baltissen's test ROM from his VHDL 6502 core.
-        // See also http://visual6502.org/wiki/index.php?title=6502TestPrograms
-        let mut cpu = Mos6502::new(Ram::with_capacity(0xffff));
-        for addr in 0x0000..0xe000 {
-            cpu.mem.set(addr, 0x00);
-        }
-        let rom = Rom::new("test/ttl6502_v10.rom");
-        cpu.mem.copy(0xe000, &rom, 0x0000, rom.capacity());
-        cpu.reset();
-        for _ in 0..3000 {
+    fn nmi_is_edge_triggered() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.fill(0x1000, 0x10, 0xea); // EA: NOP
+        cpu.mem.set(0x3000, 0x40); // 40: RTI
+        cpu.mem.set_le(NMI_VECTOR, 0x3000_u16);
+        cpu.pc = 0x1000;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        let nmis = Rc::new(RefCell::new(0));
+        let count = nmis.clone();
+        cpu.set_interrupt_callback(move |_, _| *count.borrow_mut() += 1);
+        // Two edges cause two NMIs, even if the input is released before the next step
+        cpu.assert_nmi();
+        cpu.deassert_nmi();
+        cpu.step();
+        cpu.step(); // RTI
+        cpu.assert_nmi();
+        cpu.step();
+        cpu.step(); // RTI
+        assert_eq!(*nmis.borrow(), 2);
+        // A held level causes only one NMI
+        cpu.assert_nmi();
+        assert!(!cpu.nmi_pending());
+        cpu.deassert_nmi();
+        cpu.assert_nmi();
+        assert!(cpu.nmi_pending());
+        for _ in 0..4 {
             cpu.step();
-            // TODO: This skips decimal mode tests for now
-            if cpu.pc == 0xf5b6 {
-                cpu.pc = 0xf5e6;
-            }
         }
-        let status = cpu.mem.get(0x0003);
-        assert!(
-            status == 0xfe,
-            "stopped at {} with status #${:02X}",
-            cpu.pc.display(),
-            status,
+        cpu.assert_nmi();
+        cpu.step();
+        assert_eq!(*nmis.borrow(), 3);
+        assert_eq!(cpu.pc, 0x1003);
+        // Pulses always trigger an NMI
+        cpu.nmi();
+        cpu.step();
+        assert_eq!(*nmis.borrow(), 4);
+    }
+
+    #[test]
+    fn interrupt_priority() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0xea, 0xea]); // EA EA: NOP NOP
+        cpu.mem.set(0x3000, 0xea); // EA: NOP
+        cpu.mem.set_le(NMI_VECTOR, 0x3000_u16);
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.pc = 0x1000;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        let kinds = Rc::new(RefCell::new(Vec::new()));
+        let log = kinds.clone();
+        cpu.set_interrupt_callback(move |kind, _| log.borrow_mut().push(kind));
+        // With both lines asserted, the NMI is taken first and the IRQ stays pending
+        let line = IrqLine::new();
+        cpu.connect_irq(line.clone());
+        line.set(0, true);
+        cpu.assert_nmi();
+        assert_eq!(cpu.step(), 7);
+        assert_eq!(cpu.pc, 0x3000);
+        assert!(!cpu.nmi_pending());
+        assert!(cpu.irq_pending());
+        // The IRQ is taken at the next boundary, before the first instruction of the handler
+        assert_eq!(cpu.step(), 7);
+        assert_eq!(cpu.pc, 0x2000);
+        let return_addr: u16 = cpu.mem.get_le(0x01fb_u16);
+        assert_eq!(return_addr, 0x3000);
+        assert_eq!(*kinds.borrow(), [InterruptKind::Nmi, InterruptKind::Irq]);
+        // RESET wins over both
+        cpu.deassert_nmi();
+        cpu.sr.remove(StatusFlags::INTERRUPT_DISABLE_FLAG);
+        cpu.assert_nmi();
+        cpu.reset = true;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0000);
+        assert!(!cpu.nmi_pending());
+        assert_eq!(kinds.borrow().len(), 2);
+    }
+
+    #[test]
+    fn irq_skips_brk_at_end_of_memory() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        // The high byte of the IRQ vector at $FFFF doubles as BRK opcode
+        cpu.mem.set_le(IRQ_VECTOR, 0x0020_u16);
+        cpu.pc = 0xffff;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        cpu.irq();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x0020);
+        let return_addr: u16 = cpu.mem.get_le(0x01fe_u16);
+        assert_eq!(return_addr, 0x0000);
+    }
+
+    #[test]
+    fn pending_interrupts() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0xea, 0xea]); // EA EA: NOP NOP
+        cpu.mem.set_le(NMI_VECTOR, 0x3000_u16);
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.pc = 0x1000;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        cpu.sr.insert(StatusFlags::INTERRUPT_DISABLE_FLAG);
+        assert!(!cpu.nmi_pending());
+        assert!(!cpu.irq_pending());
+        assert!(cpu.irq_masked());
+        cpu.irq();
+        assert!(cpu.irq_pending());
+        cpu.step();
+        assert!(cpu.irq_pending());
+        assert_eq!(cpu.pc, 0x1001);
+        cpu.nmi();
+        assert!(cpu.nmi_pending());
+        cpu.step();
+        assert!(!cpu.nmi_pending());
+        assert_eq!(cpu.pc, 0x3000);
+        cpu.sr.remove(StatusFlags::INTERRUPT_DISABLE_FLAG);
+        assert!(!cpu.irq_masked());
+        cpu.step();
+        assert!(!cpu.irq_pending());
+        assert!(cpu.irq_masked());
+        assert_eq!(cpu.pc, 0x2000);
+        // Shared lines
+        let line = IrqLine::new();
+        cpu.connect_nmi(line.clone());
+        line.set(0, true);
+        assert!(cpu.nmi_pending());
+        cpu.step();
+        assert!(!cpu.nmi_pending());
+        let line = IrqLine::new();
+        cpu.connect_irq(line.clone());
+        line.set(0, true);
+        assert!(cpu.irq_pending());
+        line.set(0, false);
+        assert!(!cpu.irq_pending());
+    }
+
+    #[test]
+    fn interrupt_callback() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.pc = 0x1000;
+        cpu.sp = 0xff;
+        cpu.mem.set(0x1000, 0x00); // 00: BRK
+        cpu.mem.set(0x2000, 0x40); // 40: RTI
+        cpu.mem.set(0x3000, 0x40); // 40: RTI
+        cpu.mem.set_le(NMI_VECTOR, 0x3000_u16);
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.reset = false;
+        let interrupts = Rc::new(RefCell::new(Vec::new()));
+        let log = interrupts.clone();
+        cpu.set_interrupt_callback(move |kind, addr| log.borrow_mut().push((kind, addr)));
+        cpu.step(); // BRK
+        cpu.step(); // RTI
+        assert_eq!(cpu.pc, 0x1002);
+        cpu.nmi();
+        cpu.step();
+        cpu.step(); // RTI
+        cpu.irq();
+        cpu.step();
+        assert_eq!(cpu.sp, 0xfc);
+        assert_eq!(
+            *interrupts.borrow(),
+            [
+                (InterruptKind::Brk, 0x2000),
+                (InterruptKind::Nmi, 0x3000),
+                (InterruptKind::Irq, 0x2000),
+            ]
+        );
+        cpu.remove_interrupt_callback();
+        cpu.step(); // RTI
+        cpu.nmi();
+        cpu.step();
+        assert_eq!(interrupts.borrow().len(), 3);
+    }
+
+    #[test]
+    fn ticks_complete_instructions() {
+        let mut cpu = Mos6502::new(Ram::with_capacity(0xffff));
+        cpu.pc = 0x1000;
+        cpu.mem.setn(0x1000, [0xee, 0x00, 0x02]); // EE 00 02: INC $0200
+        cpu.reset = false;
+        for _ in 0..5 {
+            assert!(!cpu.tick());
+        }
+        assert!(cpu.tick());
+        assert_eq!(cpu.pc, 0x1003);
+    }
+
+    #[test]
+    fn ticks_access_memory_in_their_cycle() {
+        let mut ram = Ram::zeroed(0xffff);
+        ram.setn(0x1000, [0xee, 0x00, 0x02]); // EE 00 02: INC $0200
+        ram.set(0x0200, 0x41);
+        let mut cpu = Mos6502::new(Logged::new(ram));
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        let accesses = [
+            (AccessKind::Read, 0x1000, 0xee),  // opcode
+            (AccessKind::Read, 0x1001, 0x00),  // address low byte
+            (AccessKind::Read, 0x1002, 0x02),  // address high byte
+            (AccessKind::Read, 0x0200, 0x41),  // read operand
+            (AccessKind::Write, 0x0200, 0x41), // write back unmodified
+            (AccessKind::Write, 0x0200, 0x42), // write result
+        ];
+        for (kind, addr, data) in accesses {
+            cpu.mem.clear_log();
+            cpu.tick();
+            assert_eq!(*cpu.mem.log(), [Access { kind, addr, data }]);
+        }
+        assert!(cpu.is_between_instructions());
+        assert_eq!(cpu.pc, 0x1003);
+    }
+
+    #[test]
+    fn ticks_fetch_operands_before_executing() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.pc = 0x1000;
+        cpu.mem.setn(0x1000, [0xad, 0x00, 0x02]); // AD 00 02: LDA $0200
+        cpu.mem.set(0x0200, 0x42);
+        cpu.reset = false;
+        cpu.tick();
+        assert_eq!((cpu.pc, cpu.ac), (0x1001, 0x00));
+        cpu.tick();
+        cpu.tick();
+        assert_eq!((cpu.pc, cpu.ac), (0x1003, 0x00));
+        // Memory changed by other devices before the last cycle is seen by the instruction
+        cpu.mem.set(0x0200, 0x43);
+        assert!(cpu.tick());
+        assert_eq!(cpu.ac, 0x43);
+    }
+
+    #[test]
+    fn save_state_in_the_middle_of_an_instruction() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.pc = 0x1000;
+        cpu.mem.setn(0x1000, [0xee, 0x00, 0x02]); // EE 00 02: INC $0200
+        cpu.mem.set(0x0200, 0x41);
+        cpu.reset = false;
+        for _ in 0..4 {
+            cpu.tick();
+        }
+        let state = cpu.save_state();
+        assert_eq!(state.instruction_pc, Some(0x1000));
+        assert_eq!(state.pending_cycles, 2);
+        let mut restored = Mos6502::new(Ram::zeroed(0xffff));
+        restored.mem.copy(0x0000, &cpu.mem, 0x0000, 0x10000);
+        restored.load_state(&state);
+        for cpu in [&mut cpu, &mut restored] {
+            assert!(!cpu.tick());
+            assert!(cpu.tick());
+            assert_eq!(cpu.pc, 0x1003);
+            assert_eq!(cpu.mem.get(0x0200), 0x42);
+        }
+        assert_eq!(restored.save_state(), cpu.save_state());
+    }
+
+    #[test]
+    fn page_crossing_and_branch_penalties() {
+        let cycles = |pc: u16, code: &[u8], setup: &dyn Fn(&mut Mos6502<Ram>)| {
+            let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+            cpu.reset = false;
+            cpu.pc = pc;
+            cpu.mem.copy_from_slice(pc, code);
+            cpu.mem.setn(0x0010, [0xff, 0x02]);
+            setup(&mut cpu);
+            cpu.step()
+        };
+        // LDA $0200,X and LDA $02FF,X
+        assert_eq!(cycles(0x1000, &[0xbd, 0x00, 0x02], &|cpu| cpu.x = 1), 4);
+        assert_eq!(cycles(0x1000, &[0xbd, 0xff, 0x02], &|cpu| cpu.x = 1), 5);
+        // LDA ($10),Y
+        assert_eq!(cycles(0x1000, &[0xb1, 0x10], &|cpu| cpu.y = 0), 5);
+        assert_eq!(cycles(0x1000, &[0xb1, 0x10], &|cpu| cpu.y = 1), 6);
+        // STA $02FF,X always takes the extra cycle
+        assert_eq!(cycles(0x1000, &[0x9d, 0xff, 0x02], &|cpu| cpu.x = 0), 5);
+        assert_eq!(cycles(0x1000, &[0x9d, 0xff, 0x02], &|cpu| cpu.x = 1), 5);
+        // BNE not taken, taken within the page and taken to the next page
+        let zero = |cpu: &mut Mos6502<Ram>| cpu.sr.insert(StatusFlags::ZERO_FLAG);
+        let nonzero = |cpu: &mut Mos6502<Ram>| cpu.sr.remove(StatusFlags::ZERO_FLAG);
+        assert_eq!(cycles(0x1000, &[0xd0, 0x20], &zero), 2);
+        assert_eq!(cycles(0x1000, &[0xd0, 0x20], &nonzero), 3);
+        assert_eq!(cycles(0x10f0, &[0xd0, 0x20], &nonzero), 4);
+        assert_eq!(cycles(0x1000, &[0xd0, 0xf0], &nonzero), 4);
+    }
+
+    #[test]
+    fn ticks_equal_steps() {
+        let program = assemble(
+            "
+            start:  LDA #$42
+                    STA $0200
+                    INC $0200
+                    LDX #$10
+                    JSR sub
+                    PHA
+                    JMP start
+            sub:    DEX
+                    RTS
+            ",
+            0x1000,
+        )
+        .unwrap();
+        let setup = || {
+            let mut mem = Ram::with_capacity(0xffff);
+            mem.fill(0x0000, 0x10000, 0x00);
+            mem.copy_from_slice(0x1000, &program);
+            mem.set_le(RESET_VECTOR, 0x1000_u16);
+            Mos6502::new(mem)
+        };
+        let mut stepped = setup();
+        let mut ticked = setup();
+        for _ in 0..20 {
+            let cycles = stepped.step();
+            for _ in 1..cycles {
+                assert!(!ticked.tick());
+            }
+            assert!(ticked.tick());
+            assert_eq!(
+                (ticked.pc, ticked.ac, ticked.x, ticked.y, ticked.sr, ticked.sp),
+                (stepped.pc, stepped.ac, stepped.x, stepped.y, stepped.sr, stepped.sp),
+            );
+        }
+        assert_eq!(ticked.mem.get(0x0200), 0x43);
+        assert!((0..=0xffff_u16).all(|addr| ticked.mem.get(addr) == stepped.mem.get(addr)));
+    }
+
+    #[test]
+    fn trapped_routine() {
+        let mut cpu = Mos6502::new(Ram::with_capacity(0xffff));
+        cpu.pc = 0x1000;
+        cpu.sp = 0xff;
+        cpu.ac = 0x41;
+        cpu.mem.setn(0x1000, [0x20, 0xd2, 0xff]); // 20 D2 FF: JSR $FFD2
+        cpu.mem.set(0x1003, 0xea); // EA: NOP
+        cpu.mem.set(0xffd2, 0x00); // 00: BRK (must not be executed)
+        cpu.reset = false;
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let trap_output = output.clone();
+        cpu.add_trap(0xffd2, move |cpu| {
+            trap_output.borrow_mut().push(cpu.ac);
+            cpu.return_from_subroutine();
+        });
+        cpu.step(); // JSR
+        assert_eq!(cpu.pc, 0xffd2);
+        assert!(output.borrow().is_empty());
+        cpu.step(); // Trap handler, then NOP
+        assert_eq!(*output.borrow(), [0x41]);
+        assert_eq!(cpu.pc, 0x1004);
+        assert_eq!(cpu.sp, 0xff);
+        cpu.remove_trap(0xffd2);
+        assert!(cpu.traps.is_empty());
+    }
+
+    #[test]
+    fn run_for_services_interrupts() {
+        let mut cpu = Mos6502::new(Ram::with_capacity(0xffff));
+        cpu.mem.fill(0x0000, 0x10000, 0xea); // EA: NOP
+        cpu.mem.set_le(NMI_VECTOR, 0x2000_u16);
+        cpu.pc = 0x1000;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        cpu.add_trap(0x1002, |cpu| cpu.nmi());
+        assert_eq!(cpu.run_for(3), 1); // NOP, NOP
+        assert_eq!(cpu.pc, 0x1002);
+        assert_eq!(cpu.run_for(10), 1); // NOP (trapped), NMI, NOP
+        assert_eq!(cpu.pc, 0x2001);
+        assert_eq!(cpu.sp, 0xfc);
+    }
+
+    #[test]
+    fn ruud_baltissen_core_instruction_rom() {
+        // Test all instructions using Ruud Baltissen's test ROM from his VHDL 6502 core.
+        // See also http://visual6502.org/wiki/index.php?title=6502TestPrograms
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem
+            .load_file(0xe000, "share/test/ttl6502_v10.rom")
+            .unwrap();
+        cpu.reset();
+        // TODO: This skips decimal mode tests for now
+        cpu.add_trap(0xf5b6, |cpu| cpu.set_pc(0xf5e6));
+        // The ROM loops at $F5EA when it's finished
+        let result = cpu.run_until(20_000, |cpu| cpu.pc() == 0xf5ea);
+        let status = cpu.memory().get(0x0003);
+        assert!(
+            matches!(result, RunResult::Stopped(_)) && status == 0xfe,
+            "stopped at {} after {} cycles with status #${:02X}",
+            cpu.pc().display(),
+            result.cycles(),
+            status,
+        );
+    }
+
+    #[test]
+    fn count_total_cycles() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.set_le(RESET_VECTOR, 0x1000_u16);
+        cpu.mem.set_le(NMI_VECTOR, 0x2000_u16);
+        cpu.mem.setn(0x1000, [0xa9, 0x12]); // A9 12: LDA #$12
+        cpu.mem.setn(0x1002, [0x8d, 0x00, 0x03]); // 8D 00 03: STA $0300
+        cpu.mem.setn(0x1005, [0xee, 0x00, 0x03]); // EE 00 03: INC $0300
+        cpu.mem.setn(0x2000, [0x20, 0x00, 0x30]); // 20 00 30: JSR $3000
+        cpu.mem.set(0x3000, 0x60); // 60: RTS
+        cpu.reset();
+        let mut sum = 0;
+        for _ in 0..4 {
+            sum += cpu.step(); // RESET, LDA, STA, INC
+        }
+        cpu.nmi();
+        for _ in 0..3 {
+            sum += cpu.step(); // NMI, JSR, RTS
+        }
+        assert_eq!(cpu.total_cycles(), sum as u64);
+        assert_eq!(cpu.total_cycles(), 6 + 2 + 4 + 6 + 7 + 6 + 6);
+        assert_eq!(cpu.save_state().cycles, cpu.total_cycles());
+        cpu.reset();
+        cpu.step();
+        assert_eq!(cpu.total_cycles(), sum as u64 + 6);
+        cpu.reset_cycles();
+        assert_eq!(cpu.total_cycles(), 0);
+    }
+
+    #[test]
+    fn format_trace_line() {
+        assert_eq!(format!("{:5}|", Instruction::LDA), "LDA  |");
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0xbd, 0x00, 0x02]); // BD 00 02: LDA $0200,X
+        cpu.mem.set(0x1003, 0xe8); // E8: INX
+        cpu.mem.set(0x0201, 0x82);
+        cpu.x = 0x01;
+        cpu.sp = 0xfd;
+        cpu.cycles = 1234;
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.set_history_capacity(2);
+        cpu.step();
+        cpu.step();
+        let lines: Vec<String> = cpu.history().map(|line| line.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "$1000  BD 00 02  LDA $0200,X          -[4]-> AC:82 X:01 Y:00 SR:A0 SP:FD NV-BDIZC:10100000 CYC:1234",
+                "$1003  E8        INX                  -[2]-> AC:82 X:02 Y:00 SR:20 SP:FD NV-BDIZC:00100000 CYC:1238",
+            ]
+        );
+    }
+
+    /// Trace writer that writes to a shared buffer
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Trace writer that always fails
+    struct BrokenWriter;
+
+    impl io::Write for BrokenWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace_to_writer() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0xa9, 0x12]); // A9 12: LDA #$12
+        cpu.mem.set(0x1002, 0xaa); // AA: TAX
+        cpu.mem.set(0x1003, 0xe8); // E8: INX
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        cpu.set_trace_writer(Some(Box::new(SharedBuffer(buffer.clone()))));
+        for _ in 0..3 {
+            cpu.step();
+        }
+        cpu.flush_trace().unwrap();
+        assert!(cpu.set_trace_writer(None).is_some());
+        cpu.step();
+        let trace = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let lines: Vec<&str> = trace.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "$1000  A9 12     LDA #$12             -[2]-> AC:12 X:00 Y:00 SR:20 SP:00 NV-BDIZC:00100000 CYC:0",
+                "$1002  AA        TAX                  -[2]-> AC:12 X:12 Y:00 SR:20 SP:00 NV-BDIZC:00100000 CYC:2",
+                "$1003  E8        INX                  -[2]-> AC:12 X:13 Y:00 SR:20 SP:00 NV-BDIZC:00100000 CYC:4",
+            ]
+        );
+    }
+
+    #[test]
+    fn broken_trace_writer_is_disabled() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.set_trace_writer(Some(Box::new(BrokenWriter)));
+        cpu.step();
+        assert!(cpu.trace_writer.is_none());
+        cpu.step();
+    }
+
+    /// Subscriber that captures the fields of all events
+    #[cfg(feature = "tracing")]
+    #[derive(Default)]
+    struct CapturingSubscriber {
+        events: std::sync::Arc<std::sync::Mutex<Vec<Vec<String>>>>,
+    }
+
+    #[cfg(feature = "tracing")]
+    impl tracing::Subscriber for CapturingSubscriber {
+        fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &tracing::span::Id, _values: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &tracing::span::Id, _follows: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            struct Visitor(Vec<String>);
+            impl tracing::field::Visit for Visitor {
+                fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+                    self.0.push(format!("{}={:?}", field.name(), value));
+                }
+            }
+            let mut visitor = Visitor(Vec::new());
+            event.record(&mut visitor);
+            self.events.lock().unwrap().push(visitor.0);
+        }
+
+        fn enter(&self, _span: &tracing::span::Id) {}
+
+        fn exit(&self, _span: &tracing::span::Id) {}
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn tracing_events() {
+        let subscriber = CapturingSubscriber::default();
+        let events = subscriber.events.clone();
+        tracing::subscriber::with_default(subscriber, || {
+            let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+            cpu.mem.set_le(RESET_VECTOR, 0x1000_u16);
+            cpu.mem.setn(0x1000, [0xa9, 0x12]); // A9 12: LDA #$12
+            cpu.step();
+            cpu.step();
+        });
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events[0],
+            [
+                "message=interrupt",
+                "interrupt=\"RESET\"",
+                "vector=65532",
+                "pc=4096"
+            ],
+        );
+        assert_eq!(
+            events[1],
+            [
+                "message=instruction",
+                "pc=4096",
+                "opcode=169",
+                "mnemonic=LDA",
+                "operand=#$12",
+                "cycles=2",
+                "a=18",
+                "x=0",
+                "y=0",
+                "sr=36",
+                "sp=0",
+            ],
+        );
+    }
+
+    #[test]
+    fn instruction_history() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0xa2, 0x10]); // A2 10: LDX #$10
+        cpu.mem.set(0x1002, 0xe8); // E8: INX
+        cpu.mem.set(0x1003, 0xe8); // E8: INX
+        cpu.mem.setn(0x1004, [0x4c, 0x02, 0x10]); // 4C 02 10: JMP $1002
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.step();
+        assert_eq!(cpu.history().count(), 0);
+        cpu.set_history_capacity(3);
+        cpu.step();
+        cpu.step();
+        let history: Vec<String> = cpu.history().map(|e| e.to_string()).collect();
+        assert_eq!(
+            history,
+            [
+                "$1002  E8        INX                  -[2]-> AC:00 X:11 Y:00 SR:20 SP:00 NV-BDIZC:00100000 CYC:2",
+                "$1003  E8        INX                  -[2]-> AC:00 X:12 Y:00 SR:20 SP:00 NV-BDIZC:00100000 CYC:4",
+            ]
+        );
+        cpu.step();
+        cpu.step();
+        let history: Vec<(u16, u8)> = cpu.history().map(|e| (e.pc, e.x)).collect();
+        assert_eq!(history, [(0x1003, 0x12), (0x1004, 0x12), (0x1002, 0x13)]);
+        let jmp = cpu.history().nth(1).unwrap();
+        assert_eq!(jmp.bytes(), [0x4c, 0x02, 0x10]);
+        assert_eq!(jmp.operand, Operand::Absolute(0x1002));
+    }
+
+    #[test]
+    fn history_has_registers_after_execution() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0xa9, 0x42]); // A9 42: LDA #$42
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.set_history_capacity(1);
+        cpu.step();
+        let lda = cpu.history().next().unwrap();
+        assert_eq!(lda.instruction, Instruction::LDA);
+        assert_eq!(lda.ac, 0x42);
+        assert_eq!(lda.sr, StatusFlags::UNUSED_ALWAYS_ON_FLAG);
+    }
+
+    /// Run a program that sums up the whole memory and return the execution log
+    fn memory_sum_log<M: Addressable>(mut cpu: Mos6502<M>) -> Vec<String> {
+        let program = assemble(
+            "loop: ADC $0100,X\nINX\nBNE loop\nINC $F002\nJMP loop",
+            0xf000,
+        )
+        .unwrap();
+        for (i, byte) in program.into_iter().enumerate() {
+            cpu.mem.set(0xf000 + i as u16, byte);
+        }
+        cpu.mem.set_le(RESET_VECTOR, 0xf000_u16);
+        let formatter = TraceFormatter::new();
+        (0..1000)
+            .map(|_| {
+                let line = formatter.format(&cpu);
+                cpu.step();
+                line
+            })
+            .collect()
+    }
+
+    #[test]
+    fn deterministic_runs() {
+        let cpu1 = Mos6502::new(Ram::zeroed(0xffff));
+        let cpu2 = Mos6502::new(Ram::zeroed(0xffff));
+        assert_eq!(cpu1.save_state(), cpu2.save_state());
+        assert_eq!((cpu1.pc, cpu1.ac, cpu1.x, cpu1.y, cpu1.sp), (0, 0, 0, 0, 0));
+        assert_eq!(cpu1.sr, StatusFlags::UNUSED_ALWAYS_ON_FLAG);
+        assert!(cpu1.reset);
+        assert_eq!(memory_sum_log(cpu1), memory_sum_log(cpu2));
+        // With random memory, runs differ (practically always)
+        let cpu1 = Mos6502::new(Ram::with_init(0xffff, RamInit::Random));
+        let cpu2 = Mos6502::new(Ram::with_init(0xffff, RamInit::Random));
+        assert_ne!(memory_sum_log(cpu1), memory_sum_log(cpu2));
+    }
+
+    #[test]
+    fn executed_address_coverage() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        let program = assemble(
+            "LDX #$02\nloop: DEX\nBNE loop\nBEQ done\nNOP\ndone: JMP done",
+            0x1000,
+        )
+        .unwrap();
+        cpu.mem.copy_from_slice(0x1000, &program);
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        assert_eq!(cpu.coverage(), None);
+        cpu.set_coverage(true);
+        for _ in 0..8 {
+            cpu.step();
+        }
+        let coverage = cpu.coverage().unwrap();
+        assert_eq!(coverage.len(), 0x10000);
+        let executed: Vec<usize> = (0..coverage.len()).filter(|&a| coverage[a]).collect();
+        assert_eq!(executed, [0x1000, 0x1002, 0x1003, 0x1005, 0x1008]);
+        cpu.set_coverage(false);
+        assert_eq!(cpu.coverage(), None);
+    }
+
+    #[test]
+    fn status_flags_display() {
+        let sr =
+            StatusFlags::CARRY_FLAG | StatusFlags::ZERO_FLAG | StatusFlags::UNUSED_ALWAYS_ON_FLAG;
+        assert_eq!(sr.to_string(), "nv-bdiZC");
+        assert_eq!(StatusFlags::empty().to_string(), "nv-bdizc");
+        assert_eq!(StatusFlags::all().to_string(), "NV-BDIZC");
+    }
+
+    #[test]
+    fn register_dump() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.pc = 0x1234;
+        cpu.ac = 0x0a;
+        cpu.y = 0xff;
+        cpu.sp = 0xf7;
+        cpu.sr = StatusFlags::UNUSED_ALWAYS_ON_FLAG
+            | StatusFlags::INTERRUPT_DISABLE_FLAG
+            | StatusFlags::CARRY_FLAG;
+        assert_eq!(
+            cpu.to_string(),
+            "PC=$1234 A=$0A X=$00 Y=$FF SP=$F7 P=$25 [nv-bdIzC]"
+        );
+        cpu.sr = StatusFlags::all();
+        assert_eq!(
+            cpu.to_string(),
+            "PC=$1234 A=$0A X=$00 Y=$FF SP=$F7 P=$FF [NV-BDIZC]"
+        );
+    }
+
+    #[test]
+    fn bitwise_register_dump() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.pc = 0x1234;
+        cpu.ac = 0x56;
+        cpu.x = 0x78;
+        cpu.y = 0x9a;
+        cpu.sp = 0xff;
+        cpu.sr = StatusFlags::UNUSED_ALWAYS_ON_FLAG | StatusFlags::INTERRUPT_DISABLE_FLAG;
+        assert_eq!(
+            format!("{:#}", cpu),
+            "PC:1234 A:56 X:78 Y:9A SP:FF NV-BDIZC:00100100"
+        );
+    }
+
+    #[test]
+    fn instruction_profile() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        let program = assemble("LDX #$0A\nloop: DEX\nBNE loop\nNOP", 0x1000).unwrap();
+        cpu.mem.copy_from_slice(0x1000, &program);
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.step();
+        assert!(cpu.profile().is_empty());
+        cpu.set_profiling(true);
+        while cpu.pc != 0x1005 {
+            cpu.step();
+        }
+        assert_eq!(cpu.profile()[&Instruction::DEX].count, 10);
+        assert_eq!(cpu.profile()[&Instruction::DEX].cycles, 20);
+        assert_eq!(cpu.profile()[&Instruction::BNE].count, 10);
+        assert!(!cpu.profile().contains_key(&Instruction::LDX));
+        cpu.set_profiling(false);
+        cpu.step();
+        assert!(!cpu.profile().contains_key(&Instruction::NOP));
+        cpu.clear_profile();
+        assert!(cpu.profile().is_empty());
+    }
+
+    #[test]
+    fn step_traced() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        let program = assemble("LDA #$42\nSTA $0200", 0x1000).unwrap();
+        cpu.mem.copy_from_slice(0x1000, &program);
+        cpu.mem.set(0x1005, 0x02); // 02: JAM
+        cpu.mem.set_le(RESET_VECTOR, 0x1000_u16);
+        cpu.mem.set_le(NMI_VECTOR, 0x3000_u16);
+        let step = cpu.step_traced();
+        assert_eq!(step.executed, Executed::Reset);
+        assert_eq!((step.cycles, step.next_pc), (6, 0x1000));
+        assert_eq!(
+            cpu.step_traced(),
+            StepResult {
+                cycles: 2,
+                pc: 0x1000,
+                next_pc: 0x1002,
+                executed: Executed::Instruction(Instruction::LDA, Operand::Immediate(0x42)),
+            }
+        );
+        cpu.nmi();
+        let step = cpu.step_traced();
+        assert_eq!(step.executed, Executed::Interrupt(InterruptKind::Nmi));
+        assert_eq!((step.pc, step.next_pc), (0x1002, 0x3000));
+        cpu.pc = 0x1002;
+        let step = cpu.step_traced();
+        assert_eq!(
+            step.executed,
+            Executed::Instruction(Instruction::STA, Operand::Absolute(0x0200))
+        );
+        assert_eq!(cpu.step_traced().executed, Executed::Jammed);
+        assert_eq!(cpu.step_traced().executed, Executed::Jammed);
+    }
+
+    fn call_tracking_cpu(src: &str) -> Mos6502<Ram> {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem
+            .copy_from_slice(0x1000, &assemble(src, 0x1000).unwrap());
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.mem.set(0x2000, 0x40); // 40: RTI
+        cpu.pc = 0x1000;
+        cpu.sp = 0xff;
+        cpu.reset = false;
+        cpu.set_call_tracking(true);
+        cpu
+    }
+
+    fn frame(return_addr: u16, target: u16, sp: u8, kind: CallKind) -> CallFrame {
+        CallFrame {
+            return_addr,
+            target,
+            sp,
+            kind,
+        }
+    }
+
+    #[test]
+    fn call_stack_nested_calls() {
+        let mut cpu = call_tracking_cpu(
+            "
+                    JSR outer
+                    NOP
+            outer:  JSR inner
+                    RTS
+            inner:  CLI
+                    NOP
+                    RTS
+            ",
+        );
+        cpu.step(); // JSR outer
+        cpu.step(); // JSR inner
+        cpu.step(); // CLI
+        assert_eq!(
+            cpu.call_stack(),
+            [
+                frame(0x1003, 0x1004, 0xfd, CallKind::Subroutine),
+                frame(0x1007, 0x1008, 0xfb, CallKind::Subroutine),
+            ]
+        );
+        // IRQ in the middle of the inner subroutine
+        cpu.irq();
+        cpu.step();
+        assert_eq!(cpu.call_stack().len(), 3);
+        assert_eq!(
+            cpu.call_stack()[2],
+            frame(
+                0x1009,
+                0x2000,
+                0xf8,
+                CallKind::Interrupt(InterruptKind::Irq)
+            )
+        );
+        cpu.step(); // RTI
+        assert_eq!(cpu.call_stack().len(), 2);
+        cpu.step(); // NOP
+        cpu.step(); // RTS
+        assert_eq!(
+            cpu.call_stack(),
+            [frame(0x1003, 0x1004, 0xfd, CallKind::Subroutine)]
+        );
+        cpu.step(); // RTS
+        assert_eq!(cpu.call_stack(), []);
+        assert_eq!(cpu.pc, 0x1003);
+        cpu.set_call_tracking(false);
+        cpu.pc = 0x1000;
+        cpu.step(); // JSR outer
+        assert_eq!(cpu.call_stack(), []);
+    }
+
+    #[test]
+    fn call_stack_manual_stack_manipulation() {
+        let mut cpu = call_tracking_cpu(
+            "
+                    JSR sub
+                    NOP
+            sub:    LDA #$10
+                    PHA
+                    LDA #$0B
+                    PHA
+                    RTS
+                    NOP
+            jump:   PLA
+                    PLA
+                    JSR other
+            other:  NOP
+            ",
+        );
+        cpu.step(); // JSR sub
+
+        // RTS used as computed jump doesn't return from the subroutine
+        for _ in 0..5 {
+            cpu.step();
+        }
+        assert_eq!(cpu.pc, 0x100c);
+        assert_eq!(
+            cpu.call_stack(),
+            [frame(0x1003, 0x1004, 0xfd, CallKind::Subroutine)]
+        );
+        // Dropping the return address leaves the subroutine
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!(
+            cpu.call_stack(),
+            [frame(0x1011, 0x1011, 0xfd, CallKind::Subroutine)]
+        );
+        // RTS without JSR neither pops nor records a frame
+        cpu.mem.setn(0x01f1, [0x1f, 0x10]);
+        cpu.pc = 0x100a;
+        cpu.sp = 0xf0;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1020);
+        assert_eq!(cpu.sp, 0xf2);
+        assert_eq!(
+            cpu.call_stack(),
+            [frame(0x1011, 0x1011, 0xfd, CallKind::Subroutine)]
+        );
+        // RTS without any frame doesn't underflow the call stack
+        cpu.set_call_tracking(false);
+        cpu.set_call_tracking(true);
+        cpu.pc = 0x100a;
+        cpu.sp = 0xf0;
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1020);
+        assert_eq!(cpu.call_stack(), []);
+    }
+
+    #[test]
+    fn step_over_subroutine() {
+        let mut cpu = call_tracking_cpu(
+            "
+                    JSR delay
+                    NOP
+            delay:  LDX #$10
+                    CLI
+            loop:   DEX
+                    BNE loop
+                    RTS
+            ",
+        );
+        cpu.sr.insert(StatusFlags::INTERRUPT_DISABLE_FLAG);
+        cpu.irq();
+        assert_eq!(cpu.step_over(1000), RunResult::Stopped(108));
+        assert_eq!(cpu.pc, 0x1003);
+        assert_eq!(cpu.sp, 0xff);
+        assert_eq!(cpu.x, 0x00);
+        assert_eq!(cpu.step_over(1000), RunResult::Stopped(2));
+        assert_eq!(cpu.pc, 0x1004);
+        cpu.pc = 0x1000;
+        assert_eq!(cpu.step_over(20), RunResult::BudgetExhausted(20));
+    }
+
+    #[test]
+    fn step_out_of_nested_subroutine() {
+        let mut cpu = call_tracking_cpu(
+            "
+                    JSR outer
+                    NOP
+            outer:  JSR inner
+                    NOP
+                    RTS
+            inner:  PHA
+                    JSR leaf
+                    PLA
+                    RTS
+            leaf:   RTS
+            ",
+        );
+        cpu.step(); // JSR outer
+        cpu.step(); // JSR inner
+        cpu.step(); // PHA
+        cpu.irq();
+        assert_eq!(cpu.step_out(1000), RunResult::Stopped(35));
+        assert_eq!(cpu.pc, 0x1007);
+        assert_eq!(cpu.sp, 0xfd);
+        assert_eq!(cpu.step_out(1000), RunResult::Stopped(8));
+        assert_eq!(cpu.pc, 0x1003);
+        assert_eq!(cpu.sp, 0xff);
+    }
+
+    #[test]
+    fn step_out_with_wrapped_stack() {
+        let mut cpu = call_tracking_cpu(
+            "
+                    JSR sub
+                    NOP
+            sub:    NOP
+                    RTS
+            ",
+        );
+        cpu.sp = 0x01;
+        cpu.step(); // JSR sub
+        assert_eq!(cpu.sp, 0xff);
+        assert_eq!(cpu.step_out(1000), RunResult::Stopped(8));
+        assert_eq!(cpu.pc, 0x1003);
+        assert_eq!(cpu.sp, 0x01);
+    }
+
+    #[test]
+    #[should_panic(expected = "Call stack:\nJSR $1003  return $1003  SP:FD\n")]
+    fn illegal_opcode_shows_call_stack() {
+        let mut cpu = call_tracking_cpu("JSR sub\nsub: .byte $FF");
+        cpu.step();
+        cpu.step();
+    }
+
+    #[test]
+    #[should_panic(expected = "$1000  EA        NOP")]
+    fn illegal_opcode_shows_history() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.set(0x1000, 0xea); // EA: NOP
+        cpu.mem.set(0x1001, 0xff); // FF: illegal
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.set_history_capacity(8);
+        cpu.step();
+        cpu.step();
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0xa2, 0x00]); // A2 00: LDX #$00
+        cpu.mem.set(0x1002, 0xe8); // E8: INX
+        cpu.mem.setn(0x1003, [0x4c, 0x02, 0x10]); // 4C 02 10: JMP $1002
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.add_breakpoint(0x1002);
+        assert_eq!(cpu.run_until_break(100), RunResult::Breakpoint(2));
+        assert_eq!(cpu.pc, 0x1002);
+        assert_eq!(cpu.x, 0x00);
+        assert_eq!(cpu.run_until_break(100), RunResult::Breakpoint(0));
+        cpu.step();
+        assert_eq!(cpu.x, 0x01);
+        assert_eq!(cpu.run_until_break(100), RunResult::Breakpoint(3));
+        assert_eq!(cpu.pc, 0x1002);
+        assert_eq!(cpu.x, 0x01);
+        cpu.remove_breakpoint(0x1002);
+        assert_eq!(cpu.run_until_break(10), RunResult::BudgetExhausted(10));
+        cpu.add_breakpoint(0x1003);
+        cpu.clear_breakpoints();
+        assert_eq!(cpu.run_until_break(5), RunResult::BudgetExhausted(5));
+    }
+
+    #[test]
+    fn breakpoint_with_pending_interrupt() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.set(0x1000, 0xea); // EA: NOP
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.mem.set(0x2000, 0x40); // 40: RTI
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.add_breakpoint(0x1000);
+        cpu.irq();
+        // The IRQ is processed before the instruction at the breakpoint
+        assert!(!cpu.at_breakpoint());
+        assert_eq!(cpu.run_until_break(100), RunResult::Breakpoint(13));
+        assert!(cpu.at_breakpoint());
+        assert_eq!(cpu.pc, 0x1000);
+        assert_eq!(cpu.sp, 0x00);
+    }
+
+    #[test]
+    fn multiple_breakpoints() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        let program = assemble(
+            "
+                    LDX #$03
+            loop:   DEX         ; $1002
+                    BNE loop
+                    NOP         ; $1005
+                    JMP $1000
+            ",
+            0x1000,
+        )
+        .unwrap();
+        cpu.mem.copy_from_slice(0x1000, &program);
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        cpu.add_breakpoint(0x1005);
+        cpu.add_breakpoint(0x1002);
+        let mut breakpoints: Vec<u16> = cpu.breakpoints().collect();
+        breakpoints.sort();
+        assert_eq!(breakpoints, [0x1002, 0x1005]);
+        assert!(matches!(cpu.run_until_break(100), RunResult::Breakpoint(_)));
+        assert_eq!(cpu.pc, 0x1002);
+        assert_eq!(cpu.x, 0x03);
+        cpu.remove_breakpoint(0x1002);
+        assert!(matches!(cpu.run_until_break(100), RunResult::Breakpoint(_)));
+        assert_eq!(cpu.pc, 0x1005);
+        assert_eq!(cpu.x, 0x00);
+    }
+
+    #[test]
+    fn shared_interrupt_lines() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.fill(0x1000, 0x10, 0xea); // EA: NOP
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.mem.set(0x2000, 0x40); // 40: RTI
+        cpu.mem.set_le(NMI_VECTOR, 0x3000_u16);
+        cpu.mem.set(0x3000, 0x40); // 40: RTI
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        let irq = IrqLine::new();
+        let nmi = IrqLine::new();
+        cpu.connect_irq(irq.clone());
+        cpu.connect_nmi(nmi.clone());
+        // Two sources assert the IRQ line, one releases it
+        irq.set(0, true);
+        irq.set(1, true);
+        irq.set(0, false);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x2000);
+        // IRQ is taken again after RTI while the line is asserted
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1000);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x2000);
+        irq.set(1, false);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1001);
+        // NMI is only triggered on the edge
+        nmi.set(0, true);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x3000);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1002);
+        // No new edge while another source keeps the line asserted
+        nmi.set(1, true);
+        nmi.set(0, false);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1003);
+        nmi.set(1, false);
+        cpu.step();
+        nmi.set(1, true);
+        cpu.step();
+        assert_eq!(cpu.pc, 0x3000);
+    }
+
+    #[test]
+    fn run_until_jammed() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        let program = assemble("LDX #$01\n.byte $02\nINX", 0x1000).unwrap();
+        cpu.mem.copy_from_slice(0x1000, &program);
+        cpu.mem.set_le(RESET_VECTOR, 0x1000_u16);
+        cpu.step(); // RESET
+        assert_eq!(
+            cpu.run(100),
+            RunOutcome {
+                cycles: 3,
+                reason: StopReason::Halted
+            }
+        );
+        assert_eq!(cpu.pc, 0x1002);
+        cpu.nmi();
+        assert_eq!(cpu.step(), 1);
+        assert!(cpu.is_halted());
+        cpu.reset();
+        assert!(!cpu.is_halted());
+        cpu.step();
+        assert_eq!(cpu.pc, 0x1000);
+    }
+
+    #[test]
+    fn run_with_budget_and_breakpoint() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem.setn(0x1000, [0x4c, 0x00, 0x10]); // 4C 00 10: JMP $1000
+        cpu.mem.set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.mem.set(0x2000, 0xea); // EA: NOP
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        assert_eq!(
+            cpu.run(10),
+            RunOutcome {
+                cycles: 12,
+                reason: StopReason::BudgetExhausted
+            }
+        );
+        cpu.add_breakpoint(0x1000);
+        assert_eq!(cpu.run(10).reason, StopReason::Breakpoint);
+        // A pending interrupt is serviced before stopping at a breakpoint
+        cpu.add_breakpoint(0x2000);
+        cpu.irq();
+        assert_eq!(
+            cpu.run(10),
+            RunOutcome {
+                cycles: 7,
+                reason: StopReason::Breakpoint
+            }
+        );
+        assert_eq!(cpu.pc, 0x2000);
+    }
+
+    #[test]
+    fn run_until_budget_exhausted() {
+        let mut cpu = Mos6502::new(Ram::with_capacity(0xffff));
+        cpu.mem.setn(0x1000, [0x4c, 0x00, 0x10]); // 4C 00 10: JMP $1000
+        cpu.pc = 0x1000;
+        cpu.reset = false;
+        let result = cpu.run_until(10, |cpu| cpu.pc() != 0x1000);
+        assert_eq!(result, RunResult::BudgetExhausted(12));
+        let result = cpu.run_until(10, |cpu| cpu.pc() == 0x1000);
+        assert_eq!(result, RunResult::Stopped(3));
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem
+            .load_file(0xe000, "share/test/ttl6502_v10.rom")
+            .unwrap();
+        cpu.reset();
+        for _ in 0..100 {
+            cpu.step();
+        }
+        let state = cpu.save_state();
+        let snapshot = cpu.mem.snapshot();
+        let first = trace(&mut cpu, 50);
+        cpu.load_state(&state);
+        cpu.mem.restore(&snapshot);
+        assert_eq!(cpu.save_state(), state);
+        let second = trace(&mut cpu, 50);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    #[ignore = "needs share/test/6502_functional_test.bin and decimal mode support"]
+    fn klaus_dormann_functional_test_rom() {
+        // Test all instructions using Klaus Dormann's 6502 functional test. The binary is a
+        // full 64k memory image with the test code starting at $0400. If all tests succeed,
+        // the test traps (jumps to itself) at SUCCESS_TRAP. Any other trap address means that
+        // the test found an error at that address.
+        // See also https://github.com/Klaus2m5/6502_65C02_functional_tests
+        const SUCCESS_TRAP: u16 = 0x3469;
+        let mut cpu = Mos6502::new(Ram::with_capacity(0xffff));
+        cpu.mem
+            .load_file(0x0000, "share/test/6502_functional_test.bin")
+            .unwrap();
+        cpu.reset = false;
+        cpu.pc = 0x0400;
+        let trap = run_until_trap(&mut cpu, 400_000_000);
+        assert!(
+            trap == Some(SUCCESS_TRAP),
+            "{} at {} with AC:{:02X} X:{:02X} Y:{:02X} SR:{:02X} SP:{:02X}\n{}",
+            if trap.is_some() {
+                "trapped"
+            } else {
+                "step budget exhausted"
+            },
+            cpu.pc.display(),
+            cpu.ac,
+            cpu.x,
+            cpu.y,
+            cpu.sr.bits(),
+            cpu.sp,
+            disassemble_count(&cpu.mem, cpu.pc, 8)
+                .iter()
+                .map(|line| format!("{}\n", line))
+                .collect::<String>(),
         );
     }
 }
@@ -3,10 +3,11 @@
 use super::Mos6502;
 use crate::addr::{Address, Masked};
 use crate::mem::Addressable;
+use crate::pad::pad;
 use std::fmt;
 
 /// Instruction operand with different addressing modes
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operand {
     /// OPC          Operand implied
     Implied,
@@ -36,7 +37,178 @@ pub enum Operand {
     ZeroPageIndirectIndexedWithY(u8),
 }
 
+/// An operand whose effective address has been resolved. Resolving reads indirect pointers,
+/// so resolving once per instruction makes sure that read-modify-write instructions access
+/// memory just like the real CPU does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolvedOperand {
+    /// Operand implied
+    Implied,
+    /// Operand is the given value
+    Immediate(u8),
+    /// Operand is AC
+    Accumulator,
+    /// Operand is the given effective address (or branch target)
+    Address(u16),
+    /// Operand is the given effective address, whose value was already read by the read cycle
+    /// of a read-modify-write instruction, so getting it doesn't access memory again
+    Latched(u16, u8),
+}
+
+impl ResolvedOperand {
+    /// Returns the address an operand targets to
+    pub fn addr(&self) -> u16 {
+        match *self {
+            ResolvedOperand::Address(addr) | ResolvedOperand::Latched(addr, _) => addr,
+            ref op => panic!("mos6502: {:?} operand does never target an address", op),
+        }
+    }
+
+    /// Returns the value an operand specifies
+    pub fn get<M: Addressable>(&self, cpu: &Mos6502<M>) -> u8 {
+        match *self {
+            ResolvedOperand::Implied => panic!("mos6502: Implied operand does never have a value"),
+            ResolvedOperand::Immediate(value) => value,
+            ResolvedOperand::Accumulator => cpu.ac,
+            ResolvedOperand::Address(addr) => cpu.mem.get(addr),
+            ResolvedOperand::Latched(_, value) => value,
+        }
+    }
+
+    /// Sets the value an operand specifies
+    pub fn set<M: Addressable>(&self, cpu: &mut Mos6502<M>, value: u8) {
+        match *self {
+            ResolvedOperand::Implied => panic!("mos6502: Implied operand does never set a value"),
+            ResolvedOperand::Immediate(..) => {
+                panic!("mos6502: Immediate operand does never set a value")
+            }
+            ResolvedOperand::Accumulator => cpu.ac = value,
+            ResolvedOperand::Address(addr) | ResolvedOperand::Latched(addr, _) => {
+                cpu.mem.set(addr, value)
+            }
+        }
+    }
+}
+
+/// Addressing mode of an operand, i.e. the kind of operand without its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    /// Operand implied
+    Implied,
+    /// Operand is an immediate value
+    Immediate,
+    /// Operand is AC
+    Accumulator,
+    /// Branch target relative to PC
+    Relative,
+    /// Absolute address
+    Absolute,
+    /// Absolute address incremented by X
+    AbsoluteIndexedWithX,
+    /// Absolute address incremented by Y
+    AbsoluteIndexedWithY,
+    /// Indirect absolute address
+    Indirect,
+    /// Zero page address
+    ZeroPage,
+    /// Zero page address incremented by X
+    ZeroPageIndexedWithX,
+    /// Zero page address incremented by Y
+    ZeroPageIndexedWithY,
+    /// Indirect zero page address incremented by X
+    ZeroPageIndexedWithXIndirect,
+    /// Indirect zero page address, incremented by Y afterwards
+    ZeroPageIndirectIndexedWithY,
+}
+
+impl AddressingMode {
+    /// Returns the length in bytes of an instruction using this addressing mode (including the
+    /// opcode)
+    #[allow(clippy::len_without_is_empty)]
+    pub const fn len(&self) -> u8 {
+        match *self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 1,
+            AddressingMode::Immediate
+            | AddressingMode::Relative
+            | AddressingMode::ZeroPage
+            | AddressingMode::ZeroPageIndexedWithX
+            | AddressingMode::ZeroPageIndexedWithY
+            | AddressingMode::ZeroPageIndexedWithXIndirect
+            | AddressingMode::ZeroPageIndirectIndexedWithY => 2,
+            AddressingMode::Absolute
+            | AddressingMode::AbsoluteIndexedWithX
+            | AddressingMode::AbsoluteIndexedWithY
+            | AddressingMode::Indirect => 3,
+        }
+    }
+}
+
 impl Operand {
+    /// Create an operand with the given addressing mode from the bytes following the opcode
+    /// (little endian). Bytes that the addressing mode doesn't use are ignored.
+    pub(super) fn new(mode: AddressingMode, bytes: [u8; 2]) -> Operand {
+        let byte = bytes[0];
+        let word = u16::from_le_bytes(bytes);
+        match mode {
+            AddressingMode::Implied => Operand::Implied,
+            AddressingMode::Immediate => Operand::Immediate(byte),
+            AddressingMode::Accumulator => Operand::Accumulator,
+            AddressingMode::Relative => Operand::Relative(byte as i8),
+            AddressingMode::Absolute => Operand::Absolute(word),
+            AddressingMode::AbsoluteIndexedWithX => Operand::AbsoluteIndexedWithX(word),
+            AddressingMode::AbsoluteIndexedWithY => Operand::AbsoluteIndexedWithY(word),
+            AddressingMode::Indirect => Operand::Indirect(word),
+            AddressingMode::ZeroPage => Operand::ZeroPage(byte),
+            AddressingMode::ZeroPageIndexedWithX => Operand::ZeroPageIndexedWithX(byte),
+            AddressingMode::ZeroPageIndexedWithY => Operand::ZeroPageIndexedWithY(byte),
+            AddressingMode::ZeroPageIndexedWithXIndirect => {
+                Operand::ZeroPageIndexedWithXIndirect(byte)
+            }
+            AddressingMode::ZeroPageIndirectIndexedWithY => {
+                Operand::ZeroPageIndirectIndexedWithY(byte)
+            }
+        }
+    }
+
+    /// Returns the addressing mode of the operand
+    pub fn mode(&self) -> AddressingMode {
+        match *self {
+            Operand::Implied => AddressingMode::Implied,
+            Operand::Immediate(..) => AddressingMode::Immediate,
+            Operand::Accumulator => AddressingMode::Accumulator,
+            Operand::Relative(..) => AddressingMode::Relative,
+            Operand::Absolute(..) => AddressingMode::Absolute,
+            Operand::AbsoluteIndexedWithX(..) => AddressingMode::AbsoluteIndexedWithX,
+            Operand::AbsoluteIndexedWithY(..) => AddressingMode::AbsoluteIndexedWithY,
+            Operand::Indirect(..) => AddressingMode::Indirect,
+            Operand::ZeroPage(..) => AddressingMode::ZeroPage,
+            Operand::ZeroPageIndexedWithX(..) => AddressingMode::ZeroPageIndexedWithX,
+            Operand::ZeroPageIndexedWithY(..) => AddressingMode::ZeroPageIndexedWithY,
+            Operand::ZeroPageIndexedWithXIndirect(..) => {
+                AddressingMode::ZeroPageIndexedWithXIndirect
+            }
+            Operand::ZeroPageIndirectIndexedWithY(..) => {
+                AddressingMode::ZeroPageIndirectIndexedWithY
+            }
+        }
+    }
+
+    /// Returns the length in bytes of an instruction using this operand (including the opcode)
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u8 {
+        self.mode().len()
+    }
+
+    /// Resolve the effective address of the operand, reading indirect pointers if needed
+    pub fn resolve<M: Addressable>(&self, cpu: &Mos6502<M>) -> ResolvedOperand {
+        match *self {
+            Operand::Implied => ResolvedOperand::Implied,
+            Operand::Immediate(value) => ResolvedOperand::Immediate(value),
+            Operand::Accumulator => ResolvedOperand::Accumulator,
+            ref op => ResolvedOperand::Address(op.addr(cpu)),
+        }
+    }
+
     /// Returns the address an operand targets to
     pub fn addr<M: Addressable>(&self, cpu: &Mos6502<M>) -> u16 {
         match *self {
@@ -94,22 +266,40 @@ impl Operand {
 
 impl fmt::Display for Operand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let str = match *self {
-            Operand::Implied => "".to_string(),
-            Operand::Immediate(value) => format!("#${:02X}", value),
-            Operand::Accumulator => "A".to_string(),
-            Operand::Relative(offset) => format!("{:+}", offset),
-            Operand::Absolute(addr) => format!("{}", addr.display()),
-            Operand::AbsoluteIndexedWithX(addr) => format!("{},X", addr.display()),
-            Operand::AbsoluteIndexedWithY(addr) => format!("{},Y", addr.display()),
-            Operand::Indirect(addr) => format!("({})", addr.display()),
-            Operand::ZeroPage(zp) => format!("${:02X}", zp),
-            Operand::ZeroPageIndexedWithX(zp) => format!("${:02X},X", zp),
-            Operand::ZeroPageIndexedWithY(zp) => format!("${:02X},Y", zp),
-            Operand::ZeroPageIndexedWithXIndirect(zp) => format!("(${:02X},X)", zp),
-            Operand::ZeroPageIndirectIndexedWithY(zp) => format!("(${:02X}),Y", zp),
+        let len = match *self {
+            Operand::Implied => 0,
+            Operand::Accumulator => 1,
+            Operand::ZeroPage(..) => 3,
+            Operand::Immediate(..) => 4,
+            Operand::Relative(offset) => match offset.unsigned_abs() {
+                0..=9 => 2,
+                10..=99 => 3,
+                _ => 4,
+            },
+            Operand::Absolute(..)
+            | Operand::ZeroPageIndexedWithX(..)
+            | Operand::ZeroPageIndexedWithY(..) => 5,
+            Operand::AbsoluteIndexedWithX(..)
+            | Operand::AbsoluteIndexedWithY(..)
+            | Operand::Indirect(..)
+            | Operand::ZeroPageIndexedWithXIndirect(..)
+            | Operand::ZeroPageIndirectIndexedWithY(..) => 7,
         };
-        str.fmt(f)
+        pad(f, len, |f| match *self {
+            Operand::Implied => Ok(()),
+            Operand::Immediate(value) => write!(f, "#${:02X}", value),
+            Operand::Accumulator => f.write_str("A"),
+            Operand::Relative(offset) => write!(f, "{:+}", offset),
+            Operand::Absolute(addr) => write!(f, "{}", addr.display()),
+            Operand::AbsoluteIndexedWithX(addr) => write!(f, "{},X", addr.display()),
+            Operand::AbsoluteIndexedWithY(addr) => write!(f, "{},Y", addr.display()),
+            Operand::Indirect(addr) => write!(f, "({})", addr.display()),
+            Operand::ZeroPage(zp) => write!(f, "${:02X}", zp),
+            Operand::ZeroPageIndexedWithX(zp) => write!(f, "${:02X},X", zp),
+            Operand::ZeroPageIndexedWithY(zp) => write!(f, "${:02X},Y", zp),
+            Operand::ZeroPageIndexedWithXIndirect(zp) => write!(f, "(${:02X},X)", zp),
+            Operand::ZeroPageIndirectIndexedWithY(zp) => write!(f, "(${:02X}),Y", zp),
+        })
     }
 }
 
@@ -228,4 +418,37 @@ mod tests {
             0xf212, // must be $F212, not $F112
         );
     }
+
+    #[test]
+    fn display_honors_width() {
+        assert_eq!(format!("{:8}|", Operand::Absolute(0x1234)), "$1234   |");
+        assert_eq!(format!("{:>8}|", Operand::Immediate(0x12)), "    #$12|");
+        assert_eq!(format!("{:4}|", Operand::Implied), "    |");
+    }
+
+    #[test]
+    fn display_length() {
+        // The padding relies on the precalculated length matching the output
+        for operand in [
+            Operand::Implied,
+            Operand::Immediate(0x12),
+            Operand::Accumulator,
+            Operand::Relative(0),
+            Operand::Relative(-9),
+            Operand::Relative(42),
+            Operand::Relative(-128),
+            Operand::Absolute(0x1234),
+            Operand::AbsoluteIndexedWithX(0x1234),
+            Operand::AbsoluteIndexedWithY(0x1234),
+            Operand::Indirect(0x1234),
+            Operand::ZeroPage(0x12),
+            Operand::ZeroPageIndexedWithX(0x12),
+            Operand::ZeroPageIndexedWithY(0x12),
+            Operand::ZeroPageIndexedWithXIndirect(0x12),
+            Operand::ZeroPageIndirectIndexedWithY(0x12),
+        ] {
+            let str = operand.to_string();
+            assert_eq!(format!("{:>8}", operand), format!("{:>8}", str));
+        }
+    }
 }
@@ -0,0 +1,238 @@
+//! MOS 6502 execution logs
+
+use super::{Instruction, Mos6502, Operand, StatusFlags};
+use crate::addr::Address;
+use crate::cpu::Cpu;
+use crate::mem::Addressable;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::BufRead;
+
+/// Number of preceding log lines to show when reporting a divergence
+const CONTEXT_LINES: usize = 5;
+
+/// An executed instruction with the register contents after executing it. It only holds plain
+/// data, so recording it for trace output or the instruction history doesn't allocate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceLine {
+    /// Address of the instruction
+    pub pc: u16,
+    bytes: [u8; 3],
+    len: u8,
+    /// Executed instruction
+    pub instruction: Instruction,
+    /// Operand of the instruction
+    pub operand: Operand,
+    /// Number of cycles the instruction took
+    pub cycles: usize,
+    /// Number of cycles simulated before the instruction started
+    pub start_cycle: u64,
+    /// Accumulator after execution
+    pub ac: u8,
+    /// X register after execution
+    pub x: u8,
+    /// Y register after execution
+    pub y: u8,
+    /// Status register after execution
+    pub sr: StatusFlags,
+    /// Stack pointer after execution
+    pub sp: u8,
+}
+
+impl TraceLine {
+    /// Create the line for the given executed instruction, taking register contents from the
+    /// given CPU, which just executed it in its last cycle (which isn't counted yet)
+    pub(super) fn new<M>(
+        cpu: &Mos6502<M>,
+        pc: u16,
+        bytes: &[u8],
+        instruction: Instruction,
+        operand: Operand,
+        cycles: usize,
+    ) -> TraceLine {
+        let mut buf = [0; 3];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        TraceLine {
+            pc,
+            bytes: buf,
+            len: bytes.len() as u8,
+            instruction,
+            operand,
+            cycles,
+            start_cycle: cpu.cycles + 1 - cycles as u64,
+            ac: cpu.ac,
+            x: cpu.x,
+            y: cpu.y,
+            sr: cpu.sr,
+            sp: cpu.sp,
+        }
+    }
+
+    /// Returns the raw bytes of the instruction
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}  {:8}  {:3} {:15}  -[{}]-> AC:{:02X} X:{:02X} Y:{:02X} SR:{:02X} SP:{:02X} NV-BDIZC:{:08b} CYC:{}",
+            self.pc.display(),
+            self.bytes.hexdump(0..self.len as u16),
+            self.instruction,
+            self.operand,
+            self.cycles,
+            self.ac,
+            self.x,
+            self.y,
+            self.sr.bits(),
+            self.sp,
+            self.sr.bits(),
+            self.start_cycle,
+        )
+    }
+}
+
+/// Formatter for canonical execution log lines (similar to the well known nestest log). Every
+/// line shows the state of the CPU before executing an instruction: PC, raw instruction bytes,
+/// mnemonic with operand, registers and the number of cycles the CPU simulated so far. The
+/// format is fixed-width and stable, so logs can be stored and compared line by line.
+#[derive(Debug, Default)]
+pub struct TraceFormatter;
+
+impl TraceFormatter {
+    /// Create a new trace formatter
+    pub fn new() -> TraceFormatter {
+        TraceFormatter
+    }
+
+    /// Format the log line for the instruction at the current PC of the given CPU
+    pub fn format<M: Addressable>(&self, cpu: &Mos6502<M>) -> String {
+        LogLine { cpu }.to_string()
+    }
+}
+
+/// Helper struct for displaying the log line for the current state of a CPU
+struct LogLine<'a, M> {
+    cpu: &'a Mos6502<M>,
+}
+
+impl<M: Addressable> fmt::Display for LogLine<'_, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cpu = self.cpu;
+        let mut pc = cpu.pc;
+        match cpu.decode(&mut pc) {
+            // Mnemonics have 3 characters, so padding the operand pads the whole column
+            Ok((_, instruction, operand)) => write!(
+                f,
+                "{:04X}  {:<8}  {} {:<11}",
+                cpu.pc,
+                cpu.mem.hexdump(cpu.pc.range(pc)),
+                instruction,
+                operand,
+            )?,
+            Err(err) => write!(f, "{:04X}  {:02X}        {:<15}", cpu.pc, err.opcode, "???")?,
+        }
+        write!(
+            f,
+            "  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            cpu.ac,
+            cpu.x,
+            cpu.y,
+            cpu.sr.bits(),
+            cpu.sp,
+            cpu.cycles,
+        )
+    }
+}
+
+/// Step the given CPU and compare the execution log line by line with the given expected log.
+/// Panics with the first diverging line and the preceding lines as context.
+pub fn run_and_compare<M: Addressable, R: BufRead>(cpu: &mut Mos6502<M>, expected_log: R) {
+    let formatter = TraceFormatter::new();
+    let mut context = VecDeque::with_capacity(CONTEXT_LINES);
+    for (n, expected) in expected_log.lines().enumerate() {
+        let expected = expected.expect("unable to read expected log");
+        let actual = formatter.format(cpu);
+        if actual != expected {
+            let context: Vec<String> = context.into_iter().collect();
+            panic!(
+                "execution log diverges at line {} (PC {})\n{}\nexpected: {}\n  actual: {}",
+                n + 1,
+                cpu.pc.display(),
+                context.join("\n"),
+                expected,
+                actual,
+            );
+        }
+        if context.len() == CONTEXT_LINES {
+            context.pop_front();
+        }
+        context.push_back(format!("          {}", actual));
+        cpu.step();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Ram;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    fn ruud_baltissen_cpu() -> Mos6502<Ram> {
+        let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+        cpu.mem
+            .load_file(0xe000, "share/test/ttl6502_v10.rom")
+            .unwrap();
+        cpu.reset();
+        cpu.step();
+        cpu
+    }
+
+    #[test]
+    fn format_line() {
+        let mut cpu = Mos6502::new(Ram::with_capacity(0xffff));
+        cpu.set_pc(0x1000);
+        cpu.set_a(0x12);
+        cpu.set_x(0x34);
+        cpu.set_y(0x56);
+        cpu.set_sp(0xfd);
+        cpu.memory_mut().setn(0x1000, [0xbd, 0x00, 0x02]); // BD 00 02: LDA $0200,X
+        cpu.cycles = 7;
+        let formatter = TraceFormatter::new();
+        assert_eq!(
+            formatter.format(&cpu),
+            "1000  BD 00 02  LDA $0200,X      A:12 X:34 Y:56 P:20 SP:FD CYC:7",
+        );
+        cpu.memory_mut().set(0x1000, 0xea); // EA: NOP
+        assert_eq!(
+            formatter.format(&cpu),
+            "1000  EA        NOP              A:12 X:34 Y:56 P:20 SP:FD CYC:7",
+        );
+        cpu.memory_mut().set(0x1000, 0xff); // FF: illegal
+        assert_eq!(
+            formatter.format(&cpu),
+            "1000  FF        ???              A:12 X:34 Y:56 P:20 SP:FD CYC:7",
+        );
+    }
+
+    #[test]
+    fn ruud_baltissen_startup_log() {
+        // Verified against share/test/ttl6502_v10.lst
+        let mut cpu = ruud_baltissen_cpu();
+        let log = File::open("share/test/ttl6502_v10_startup.log").unwrap();
+        run_and_compare(&mut cpu, BufReader::new(log));
+    }
+
+    #[test]
+    #[should_panic(expected = "execution log diverges at line 2 (PC $E001)")]
+    fn diverging_log() {
+        let mut cpu = ruud_baltissen_cpu();
+        let log = "E000  EA        NOP              A:00 X:00 Y:00 P:24 SP:00 CYC:6\n\
+                   FFEC  EA        NOP              A:00 X:00 Y:00 P:24 SP:00 CYC:8\n";
+        run_and_compare(&mut cpu, log.as_bytes());
+    }
+}
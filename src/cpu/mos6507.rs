@@ -0,0 +1,185 @@
+//! MOS 6507
+
+use super::mos6502::StatusFlags;
+use super::{Cpu, Mos6502};
+use crate::mem::{Addressable, Mirror};
+
+/// Mask of the 13 address lines of the MOS6507
+const ADDRESS_MASK: u16 = 0x1fff;
+
+/// The MOS6507 processor
+pub struct Mos6507<M> {
+    cpu: Mos6502<Mirror<M>>, // Core CPU is a MOS6502 with only 13 address lines
+}
+
+impl<M: Addressable> Mos6507<M> {
+    /// Create a new MOS6507 processor
+    pub fn new(mem: M) -> Mos6507<M> {
+        Mos6507 {
+            cpu: Mos6502::new(Mirror::new(mem, ADDRESS_MASK)),
+        }
+    }
+
+    /// Returns a reference to the memory the processor is connected to
+    pub fn memory(&self) -> &M {
+        self.cpu.memory().inner()
+    }
+
+    /// Returns a mutable reference to the memory the processor is connected to
+    pub fn memory_mut(&mut self) -> &mut M {
+        self.cpu.memory_mut().inner_mut()
+    }
+
+    /// Returns the total number of cycles simulated since creation (or since the counter was
+    /// last reset)
+    pub fn total_cycles(&self) -> u64 {
+        self.cpu.total_cycles()
+    }
+
+    /// Reset the total cycle counter to 0. Note that the counter is not reset by a CPU reset.
+    pub fn reset_cycles(&mut self) {
+        self.cpu.reset_cycles();
+    }
+
+    /// Returns the program counter (PC)
+    pub fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    /// Set the program counter (PC)
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.set_pc(pc);
+    }
+
+    /// Returns the accumulator (A)
+    pub fn a(&self) -> u8 {
+        self.cpu.a()
+    }
+
+    /// Set the accumulator (A)
+    pub fn set_a(&mut self, ac: u8) {
+        self.cpu.set_a(ac);
+    }
+
+    /// Returns the X register
+    pub fn x(&self) -> u8 {
+        self.cpu.x()
+    }
+
+    /// Set the X register
+    pub fn set_x(&mut self, x: u8) {
+        self.cpu.set_x(x);
+    }
+
+    /// Returns the Y register
+    pub fn y(&self) -> u8 {
+        self.cpu.y()
+    }
+
+    /// Set the Y register
+    pub fn set_y(&mut self, y: u8) {
+        self.cpu.set_y(y);
+    }
+
+    /// Returns the stack pointer (SP)
+    pub fn sp(&self) -> u8 {
+        self.cpu.sp()
+    }
+
+    /// Set the stack pointer (SP)
+    pub fn set_sp(&mut self, sp: u8) {
+        self.cpu.set_sp(sp);
+    }
+
+    /// Returns the status register (SR)
+    pub fn status(&self) -> StatusFlags {
+        self.cpu.status()
+    }
+
+    /// Set the status register (SR). The unused flag is always kept on.
+    pub fn set_status(&mut self, sr: StatusFlags) {
+        self.cpu.set_status(sr);
+    }
+
+    /// Do one clock cycle. Returns true if the current instruction (or interrupt sequence)
+    /// completed with this cycle.
+    pub fn tick(&mut self) -> bool {
+        self.cpu.tick()
+    }
+}
+
+impl<M: Addressable> Cpu for Mos6507<M> {
+    /// Reset the CPU
+    fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    /// Do one step (execute the next instruction). Return the number of cycles
+    /// that were simulated.
+    fn step(&mut self) -> usize {
+        self.cpu.step()
+    }
+
+    /// Interrupt the CPU (NMI)
+    fn nmi(&mut self) {
+        self.cpu.nmi();
+    }
+
+    /// Interrupt the CPU (IRQ)
+    fn irq(&mut self) {
+        self.cpu.irq();
+    }
+
+    /// Returns the program counter (PC)
+    fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    /// Returns true if the CPU is jammed
+    fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    /// Returns true if the next instruction is at a breakpoint
+    fn at_breakpoint(&self) -> bool {
+        self.cpu.at_breakpoint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::Ram;
+
+    fn cpu_with_program(addr: u16, program: &[u8]) -> Mos6507<Ram> {
+        let mut mem = Ram::with_capacity(ADDRESS_MASK);
+        mem.fill(0x0000, ADDRESS_MASK as usize + 1, 0xea); // EA: NOP
+        for (i, &data) in program.iter().enumerate() {
+            mem.set(addr + i as u16, data);
+        }
+        mem.set_le(0x1ffc, 0xf000_u16);
+        let mut cpu = Mos6507::new(mem);
+        cpu.reset();
+        cpu
+    }
+
+    #[test]
+    fn reset_vector_is_masked() {
+        let mut cpu = cpu_with_program(0x1000, &[0x8d, 0x80, 0x00]); // 8D 80 00: STA $0080
+        cpu.memory_mut().set(0x0080, 0x55);
+        cpu.step(); // RESET via $FFFC, which is $1FFC on the bus
+        cpu.step(); // STA at $F000, which is $1000 on the bus
+        assert_eq!(cpu.memory().get(0x0080), 0x00);
+    }
+
+    #[test]
+    fn code_wraps_at_end_of_address_space() {
+        let mut cpu = cpu_with_program(0x1000, &[0x4c, 0xfe, 0x1f]); // 4C FE 1F: JMP $1FFE
+        cpu.memory_mut().setn(0x1ffe, [0xa9, 0x42]); // A9 42: LDA #$42
+        cpu.memory_mut().setn(0x0000, [0x8d, 0x80, 0x00]); // 8D 80 00: STA $0080
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.memory().get(0x0080), 0x42);
+    }
+}
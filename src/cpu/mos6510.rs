@@ -1,34 +1,480 @@
 //! MOS 6510
 
-use super::{Cpu, Mos6502};
+use super::mos6502::{CpuState, StatusFlags, StepResult};
+use super::{Cpu, IrqLine, Mos6502};
+use crate::addr::Address;
 use crate::mem::Addressable;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::{fmt, mem};
+
+/// Address of the CPU port data direction register
+const PORT_DDR_ADDR: u16 = 0x0000;
+/// Address of the CPU port data register
+const PORT_DAT_ADDR: u16 = 0x0001;
+/// Power-on value of the CPU port data direction register (as set up by the C64 KERNAL)
+const PORT_DDR_DEFAULT: u8 = 0x2f;
+/// Power-on value of the CPU port data register. LORAM, HIRAM and CHAREN are high, which
+/// makes BASIC, KERNAL and I/O visible on a C64 (standard bank configuration).
+const PORT_DAT_DEFAULT: u8 = 0x37;
+/// Port bits that aren't connected on a C64. Configured as inputs, they read back the last
+/// value that was output until the charge decays.
+const PORT_UNCONNECTED_BITS: u8 = 0xc0;
+
+/// Complete state of a MOS6510 processor (without memory), e.g. for save states
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Mos6510State {
+    /// State of the core CPU
+    pub cpu: CpuState,
+    /// CPU port data direction register
+    pub port_ddr: u8,
+    /// CPU port data register
+    pub port_dat: u8,
+}
+
+/// How long unconnected port bits keep their level after being switched from output to input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PortDecay {
+    /// Unconnected bits read as 0 immediately
+    Immediate,
+    /// Unconnected bits keep their level forever
+    Never,
+    /// Unconnected bits read as 0 after the given number of cycles
+    After(u64),
+}
+
+impl Default for PortDecay {
+    /// Real hardware loses the level after a few hundred milliseconds
+    fn default() -> PortDecay {
+        PortDecay::After(350_000)
+    }
+}
+
+/// Registers of the processor I/O port and the levels driven to it from outside
+#[derive(Debug, Clone, Copy)]
+struct PortState {
+    ddr: u8,          // data direction register (bits set to 1 are outputs)
+    dat: u8,          // data register (output latches)
+    input: u8,        // levels driven to the port pins from outside
+    floating: u8,     // last output level of unconnected bits
+    floating_at: u64, // cycle when unconnected bits were last driven
+    now: u64,         // current cycle
+    decay: PortDecay, // decay of unconnected bits
+}
+
+impl PortState {
+    /// Restore power-on values of the port registers
+    fn reset(&mut self) {
+        self.ddr = PORT_DDR_DEFAULT;
+        self.dat = PORT_DAT_DEFAULT;
+        self.drive_unconnected();
+    }
+
+    /// Remember the level of unconnected bits that are currently configured as outputs
+    fn drive_unconnected(&mut self) {
+        let driven = self.ddr & PORT_UNCONNECTED_BITS;
+        if driven != 0 {
+            self.floating = (self.floating & !driven) | (self.dat & driven);
+            self.floating_at = self.now;
+        }
+    }
+
+    /// Returns the level of unconnected bits that are configured as inputs
+    fn unconnected(&self) -> u8 {
+        let charged = match self.decay {
+            PortDecay::Immediate => false,
+            PortDecay::Never => true,
+            PortDecay::After(cycles) => self.now.saturating_sub(self.floating_at) < cycles,
+        };
+        if charged {
+            self.floating & !self.ddr & PORT_UNCONNECTED_BITS
+        } else {
+            0
+        }
+    }
+}
+
+/// Shared handle to the pins of the MOS6510 I/O port. Clones of the handle refer to the same
+/// pins, so external hardware can observe the port output (e.g. the LORAM/HIRAM/CHAREN lines a
+/// C64 PLA uses for banking) and drive input bits (e.g. the datasette sense line) while the
+/// processor owns the port.
+#[derive(Debug, Clone)]
+pub struct PortLines(Rc<Cell<PortState>>);
+
+impl PortLines {
+    /// Create new port lines with power-on values (DDR=$2F, data=$37). Connected pins that are
+    /// configured as inputs read as 1 (pulled up) until something else is driven with
+    /// `set_input`.
+    pub fn new() -> PortLines {
+        let mut state = PortState {
+            ddr: 0,
+            dat: 0,
+            input: 0xff,
+            floating: 0,
+            floating_at: 0,
+            now: 0,
+            decay: PortDecay::default(),
+        };
+        state.reset();
+        PortLines(Rc::new(Cell::new(state)))
+    }
+
+    /// Returns the levels of the port pins. Bits configured as outputs show the output latch,
+    /// bits configured as inputs show what's driven from outside. Unconnected bits (6 and 7)
+    /// configured as inputs show the last output level until it decays.
+    pub fn levels(&self) -> u8 {
+        let state = self.0.get();
+        (state.dat & state.ddr)
+            | (state.input & !state.ddr & !PORT_UNCONNECTED_BITS)
+            | state.unconnected()
+    }
+
+    /// Returns the levels that are driven to the port pins from outside
+    pub fn input(&self) -> u8 {
+        self.0.get().input
+    }
+
+    /// Set the levels that are driven to the port pins from outside. Only bits configured as
+    /// inputs are affected.
+    pub fn set_input(&self, input: u8) {
+        self.update(|state| state.input = input);
+    }
+
+    fn update<F: FnOnce(&mut PortState)>(&self, f: F) {
+        let mut state = self.0.get();
+        f(&mut state);
+        self.0.set(state);
+    }
+}
+
+impl Default for PortLines {
+    fn default() -> PortLines {
+        PortLines::new()
+    }
+}
+
+/// Memory adapter for the processor I/O port of the MOS6510. Addresses $0000 (data direction
+/// register) and $0001 (data register) are hardwired to the port, all other accesses are passed
+/// to the wrapped memory.
+struct Port<M> {
+    mem: M,           // wrapped memory
+    lines: PortLines, // port registers and pins
+}
+
+impl<M: Addressable> Addressable for Port<M> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        match addr.to_u16() {
+            PORT_DDR_ADDR => self.lines.0.get().ddr,
+            PORT_DAT_ADDR => self.lines.levels(),
+            addr => self.mem.get(addr),
+        }
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        match addr.to_u16() {
+            PORT_DDR_ADDR => self.lines.update(|state| {
+                state.ddr = data;
+                state.drive_unconnected();
+            }),
+            PORT_DAT_ADDR => self.lines.update(|state| {
+                state.dat = data;
+                state.drive_unconnected();
+            }),
+            addr => self.mem.set(addr, data),
+        }
+    }
+}
 
 /// The MOS65010 processor
 pub struct Mos6510<M> {
-    cpu: Mos6502<M>, // Core CPU is a MOS6502
-    port_ddr: u8,    // CPU port data direction register
-    port_dat: u8,    // CPU port data register
+    cpu: Mos6502<Port<M>>, // Core CPU is a MOS6502 with the I/O port in front of the memory
+    stall_cycles: usize,   // stolen cycles that still need to be waited for
+    stolen_cycles: u64,    // total number of stolen cycles waited for
 }
 
 impl<M: Addressable> Mos6510<M> {
-    /// Create a new MOS6510 processor
+    /// Create a new MOS6510 processor. The I/O port starts with power-on values, see
+    /// `PortLines::new`.
     pub fn new(mem: M) -> Mos6510<M> {
-        // TODO: addresses $0000 (data direction) and $0001 (data) are hardwired for the processor I/O port
+        Mos6510::with_port_lines(mem, PortLines::new())
+    }
+
+    /// Create a new MOS6510 processor whose I/O port uses the given port lines, so that other
+    /// hardware holding a clone of the handle is connected to the port
+    pub fn with_port_lines(mem: M, lines: PortLines) -> Mos6510<M> {
         Mos6510 {
-            cpu: Mos6502::new(mem),
-            port_ddr: 0,
-            port_dat: 0,
+            cpu: Mos6502::new(Port { mem, lines }),
+            stall_cycles: 0,
+            stolen_cycles: 0,
         }
     }
 
-    /// Interrupt the CPU (NMI)
-    pub fn nmi(&mut self) {
-        self.cpu.nmi();
+    /// Returns the complete processor state (without memory)
+    pub fn save_state(&self) -> Mos6510State {
+        Mos6510State {
+            cpu: self.cpu.save_state(),
+            port_ddr: self.port_ddr(),
+            port_dat: self.port_data(),
+        }
     }
 
-    /// Interrupt the CPU (IRQ)
-    pub fn irq(&mut self) {
-        self.cpu.irq();
+    /// Restore the processor state from the given state (memory is left untouched)
+    pub fn load_state(&mut self, state: &Mos6510State) {
+        self.cpu.load_state(&state.cpu);
+        self.cpu.memory().lines.update(|port| {
+            port.ddr = state.port_ddr;
+            port.dat = state.port_dat;
+        });
+    }
+
+    /// Returns a reference to the memory the processor is connected to
+    pub fn memory(&self) -> &M {
+        &self.cpu.memory().mem
+    }
+
+    /// Returns a mutable reference to the memory the processor is connected to
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.cpu.memory_mut().mem
+    }
+
+    /// Consume the processor and return the memory it was connected to
+    pub fn into_inner(self) -> M {
+        self.cpu.into_inner().mem
+    }
+
+    /// Consume the processor and return its state together with the memory it was connected to
+    pub fn into_parts(self) -> (Mos6510State, M) {
+        let state = self.save_state();
+        (state, self.into_inner())
+    }
+
+    /// Returns the I/O port data direction register (bits set to 1 are outputs)
+    pub fn port_ddr(&self) -> u8 {
+        self.cpu.memory().lines.0.get().ddr
+    }
+
+    /// Returns the I/O port data register (output latches)
+    pub fn port_data(&self) -> u8 {
+        self.cpu.memory().lines.0.get().dat
+    }
+
+    /// Returns the levels of the I/O port pins, i.e. the output latches for bits configured as
+    /// outputs and the external input for bits configured as inputs. On a C64, bits 0-2 are
+    /// the LORAM/HIRAM/CHAREN lines the PLA uses for banking.
+    pub fn port_output(&self) -> u8 {
+        self.cpu.memory().lines.levels()
+    }
+
+    /// Set the levels that are driven to the I/O port pins from outside. Only bits configured
+    /// as inputs are affected.
+    pub fn set_port_input(&mut self, input: u8) {
+        self.cpu.memory().lines.set_input(input);
+    }
+
+    /// Connect the given shared IRQ line (level-sensitive)
+    pub fn connect_irq(&mut self, line: IrqLine) {
+        self.cpu.connect_irq(line);
+    }
+
+    /// Connect the given shared NMI line (edge-sensitive)
+    pub fn connect_nmi(&mut self, line: IrqLine) {
+        self.cpu.connect_nmi(line);
+    }
+
+    /// Pull the NMI input low (edge-sensitive, see `Mos6502::assert_nmi`)
+    pub fn assert_nmi(&mut self) {
+        self.cpu.assert_nmi();
+    }
+
+    /// Release the NMI input
+    pub fn deassert_nmi(&mut self) {
+        self.cpu.deassert_nmi();
+    }
+
+    /// Returns true if an NMI is pending
+    pub fn nmi_pending(&self) -> bool {
+        self.cpu.nmi_pending()
+    }
+
+    /// Returns true if an IRQ is requested
+    pub fn irq_pending(&self) -> bool {
+        self.cpu.irq_pending()
+    }
+
+    /// Returns true if IRQs are masked by the interrupt disable flag
+    pub fn irq_masked(&self) -> bool {
+        self.cpu.irq_masked()
+    }
+
+    /// Set how long unconnected I/O port bits keep their level after being switched to input
+    pub fn set_port_decay(&mut self, decay: PortDecay) {
+        self.cpu.memory().lines.update(|state| state.decay = decay);
+    }
+
+    /// Do one step like `step`, but also return what was executed. Stolen cycles are included
+    /// in the number of cycles.
+    pub fn step_traced(&mut self) -> StepResult {
+        let stalled = mem::take(&mut self.stall_cycles);
+        self.stolen_cycles += stalled as u64;
+        let mut result = self.cpu.step_traced();
+        self.update_port_clock();
+        result.cycles += stalled;
+        result
+    }
+
+    /// Let the I/O port know the current cycle (for decay of unconnected bits). Called after
+    /// every step, so port accesses of the next instruction see the cycle it starts at.
+    fn update_port_clock(&self) {
+        let now = self.total_cycles();
+        self.cpu.memory().lines.update(|state| state.now = now);
+    }
+
+    /// Returns a handle to the I/O port pins, e.g. to connect other hardware to the port
+    pub fn port_lines(&self) -> PortLines {
+        self.cpu.memory().lines.clone()
+    }
+
+    /// Returns the total number of cycles simulated since creation (or since the counter was
+    /// last reset)
+    pub fn total_cycles(&self) -> u64 {
+        self.cpu.total_cycles() + self.stolen_cycles
+    }
+
+    /// Reset the total cycle counter to 0. Note that the counter is not reset by a CPU reset.
+    pub fn reset_cycles(&mut self) {
+        self.cpu.reset_cycles();
+        self.stolen_cycles = 0;
+    }
+
+    /// Stall the processor for the given number of cycles, e.g. when the VIC-II takes over the
+    /// bus on a badline. The processor waits for the stolen cycles before executing the next
+    /// instruction. Cycles stolen multiple times add up.
+    pub fn steal_cycles(&mut self, cycles: usize) {
+        self.stall_cycles += cycles;
+    }
+
+    /// Returns the total number of stolen cycles the processor waited for (included in the
+    /// total cycle count)
+    pub fn stolen_cycles(&self) -> u64 {
+        self.stolen_cycles
+    }
+
+    /// Returns the program counter (PC)
+    pub fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    /// Set the program counter (PC)
+    pub fn set_pc(&mut self, pc: u16) {
+        self.cpu.set_pc(pc);
+    }
+
+    /// Returns the accumulator (A)
+    pub fn a(&self) -> u8 {
+        self.cpu.a()
+    }
+
+    /// Set the accumulator (A)
+    pub fn set_a(&mut self, ac: u8) {
+        self.cpu.set_a(ac);
+    }
+
+    /// Returns the X register
+    pub fn x(&self) -> u8 {
+        self.cpu.x()
+    }
+
+    /// Set the X register
+    pub fn set_x(&mut self, x: u8) {
+        self.cpu.set_x(x);
+    }
+
+    /// Returns the Y register
+    pub fn y(&self) -> u8 {
+        self.cpu.y()
+    }
+
+    /// Set the Y register
+    pub fn set_y(&mut self, y: u8) {
+        self.cpu.set_y(y);
+    }
+
+    /// Returns the stack pointer (SP)
+    pub fn sp(&self) -> u8 {
+        self.cpu.sp()
+    }
+
+    /// Set the stack pointer (SP)
+    pub fn set_sp(&mut self, sp: u8) {
+        self.cpu.set_sp(sp);
+    }
+
+    /// Returns the absolute address the stack pointer points to ($0100+SP)
+    pub fn stack_addr(&self) -> u16 {
+        self.cpu.stack_addr()
+    }
+
+    /// Returns the absolute address of the value on top of the stack
+    pub fn stack_top(&self) -> u16 {
+        self.cpu.stack_top()
+    }
+
+    /// Push the given byte onto the stack
+    pub fn push_byte(&mut self, value: u8) {
+        self.cpu.push_byte(value);
+    }
+
+    /// Push the given word onto the stack
+    pub fn push_word(&mut self, value: u16) {
+        self.cpu.push_word(value);
+    }
+
+    /// Pop a byte from the stack
+    pub fn pop_byte(&mut self) -> u8 {
+        self.cpu.pop_byte()
+    }
+
+    /// Pop a word from the stack
+    pub fn pop_word(&mut self) -> u16 {
+        self.cpu.pop_word()
+    }
+
+    /// Returns the status register (SR)
+    pub fn status(&self) -> StatusFlags {
+        self.cpu.status()
+    }
+
+    /// Set the status register (SR). The unused flag is always kept on.
+    pub fn set_status(&mut self, sr: StatusFlags) {
+        self.cpu.set_status(sr);
+    }
+
+    /// Do one clock cycle. Returns true if the current instruction (or interrupt sequence)
+    /// completed with this cycle.
+    pub fn tick(&mut self) -> bool {
+        if self.stall_cycles > 0 && self.cpu.is_between_instructions() {
+            self.stall_cycles -= 1;
+            self.stolen_cycles += 1;
+            self.update_port_clock();
+            return false;
+        }
+        let completed = self.cpu.tick();
+        self.update_port_clock();
+        completed
+    }
+}
+
+impl<M: Addressable> fmt::Display for Mos6510<M> {
+    /// Format a one-line register dump like the MOS6502, followed by the CPU port registers
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} DDR=${:02X} DAT=${:02X}",
+            self.cpu,
+            self.port_ddr(),
+            self.port_data()
+        )
     }
 }
 
@@ -36,19 +482,53 @@ impl<M: Addressable> Cpu for Mos6510<M> {
     /// Reset the CPU
     fn reset(&mut self) {
         self.cpu.reset();
+        self.cpu.memory().lines.update(PortState::reset);
     }
 
     /// Do one step (execute the next instruction). Return the number of cycles
     /// that were simulated.
+    /// Stolen cycles are waited for before executing the instruction and are included in the
+    /// returned number of cycles.
     fn step(&mut self) -> usize {
-        self.cpu.step()
+        let stalled = mem::take(&mut self.stall_cycles);
+        self.stolen_cycles += stalled as u64;
+        let cycles = self.cpu.step();
+        self.update_port_clock();
+        stalled + cycles
+    }
+
+    /// Interrupt the CPU (NMI)
+    fn nmi(&mut self) {
+        self.cpu.nmi();
+    }
+
+    /// Interrupt the CPU (IRQ)
+    fn irq(&mut self) {
+        self.cpu.irq();
+    }
+
+    /// Returns the program counter (PC)
+    fn pc(&self) -> u16 {
+        self.cpu.pc()
+    }
+
+    /// Returns true if the CPU is jammed
+    fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    /// Returns true if the next instruction is at a breakpoint
+    fn at_breakpoint(&self) -> bool {
+        self.cpu.at_breakpoint()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cpu::mos6502::{assemble, IRQ_VECTOR, NMI_VECTOR};
     use crate::mem::test::TestMemory;
+    use crate::mem::Ram;
 
     #[test]
     fn smoke() {
@@ -58,4 +538,205 @@ mod tests {
         cpu.irq();
         cpu.step();
     }
+
+    #[test]
+    fn registers() {
+        let mut cpu = Mos6510::new(TestMemory);
+        cpu.step(); // RESET
+        cpu.set_pc(0x00ea); // EA: NOP
+        cpu.set_a(0x12);
+        cpu.set_status(StatusFlags::empty());
+        cpu.step();
+        assert_eq!(cpu.pc(), 0x00eb);
+        assert_eq!(cpu.a(), 0x12);
+        assert_eq!(cpu.status(), StatusFlags::UNUSED_ALWAYS_ON_FLAG);
+    }
+
+    /// Write the I/O port registers like a program would
+    fn write_port<M: Addressable>(cpu: &mut Mos6510<M>, ddr: u8, dat: u8) {
+        cpu.cpu.memory_mut().set(0x0000, ddr);
+        cpu.cpu.memory_mut().set(0x0001, dat);
+    }
+
+    #[test]
+    fn io_port_direction() {
+        let mut cpu = Mos6510::new(Ram::zeroed(0xffff));
+        let program = assemble("LDA #$0F\nSTA $00\nLDA #$AA\nSTA $01\nLDA $01", 0x1000);
+        cpu.memory_mut().copy_from_slice(0x1000, &program.unwrap());
+        cpu.step(); // RESET
+        cpu.set_pc(0x1000);
+        cpu.set_port_input(0x55);
+        for _ in 0..5 {
+            cpu.step();
+        }
+        assert_eq!(cpu.port_ddr(), 0x0f);
+        assert_eq!(cpu.port_data(), 0xaa);
+        // Output bits show the latch, input bits show the external levels (except for the
+        // unconnected bits 6 and 7)
+        assert_eq!(cpu.a(), 0x1a);
+        assert_eq!(cpu.port_output(), 0x1a);
+        cpu.set_port_input(0xf0);
+        assert_eq!(cpu.cpu.memory().get(0x0001), 0x3a);
+        assert_eq!(cpu.cpu.memory().get(0x0000), 0x0f);
+        // Port registers don't reach the memory
+        assert_eq!(cpu.memory().get(0x0000), 0x00);
+        assert_eq!(cpu.memory().get(0x0001), 0x00);
+    }
+
+    #[test]
+    fn io_port_leaves_other_addresses() {
+        let mut cpu = Mos6510::new(Ram::zeroed(0xffff));
+        cpu.cpu.memory_mut().set(0x0002, 0x42);
+        cpu.cpu.memory_mut().set(0x00ff, 0x43);
+        assert_eq!(cpu.memory().get(0x0002), 0x42);
+        assert_eq!(cpu.memory().get(0x00ff), 0x43);
+        cpu.memory_mut().set(0x0003, 0x44);
+        assert_eq!(cpu.cpu.memory().get(0x0003), 0x44);
+    }
+
+    #[test]
+    fn io_port_power_on() {
+        let mut cpu = Mos6510::new(Ram::zeroed(0xffff));
+        assert_eq!(cpu.port_ddr(), 0x2f);
+        assert_eq!(cpu.port_data(), 0x37);
+        // Standard bank configuration: LORAM, HIRAM and CHAREN high (BASIC, KERNAL and I/O)
+        assert_eq!(cpu.port_output() & 0x07, 0x07);
+        write_port(&mut cpu, 0xff, 0x00);
+        assert_eq!(cpu.port_output(), 0x00);
+        cpu.reset();
+        assert_eq!(cpu.port_ddr(), 0x2f);
+        assert_eq!(cpu.port_data(), 0x37);
+    }
+
+    #[test]
+    fn io_port_unconnected_bits() {
+        let mut cpu = Mos6510::new(Ram::zeroed(0xffff));
+        cpu.step(); // RESET
+        cpu.set_port_decay(PortDecay::After(10));
+        write_port(&mut cpu, 0xef, 0xc0);
+        write_port(&mut cpu, 0x2f, 0x00);
+        assert_eq!(cpu.port_output() & 0xc0, 0xc0);
+        cpu.memory_mut().fill(0x0200, 6, 0xea); // EA: NOP
+        cpu.set_pc(0x0200);
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.port_output() & 0xc0, 0xc0);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.port_output() & 0xc0, 0x00);
+        write_port(&mut cpu, 0xef, 0x40);
+        cpu.set_port_decay(PortDecay::Never);
+        write_port(&mut cpu, 0x2f, 0x00);
+        assert_eq!(cpu.port_output() & 0xc0, 0x40);
+        cpu.set_port_decay(PortDecay::Immediate);
+        assert_eq!(cpu.port_output() & 0xc0, 0x00);
+    }
+
+    #[test]
+    fn stolen_cycles() {
+        let mut cpu = Mos6510::new(Ram::zeroed(0xffff));
+        cpu.memory_mut().fill(0x1000, 0x10, 0xea); // EA: NOP
+        cpu.memory_mut().set_le(IRQ_VECTOR, 0x2000_u16);
+        cpu.step(); // RESET
+        cpu.set_pc(0x1000);
+        cpu.set_status(StatusFlags::empty());
+        cpu.steal_cycles(40);
+        assert_eq!(cpu.step(), 42);
+        assert_eq!(cpu.step(), 2);
+        assert_eq!(cpu.stolen_cycles(), 40);
+        assert_eq!(cpu.total_cycles(), 6 + 40 + 4);
+        // Pending IRQ is serviced after the stall
+        cpu.irq();
+        cpu.steal_cycles(20);
+        cpu.steal_cycles(20);
+        assert_eq!(cpu.step(), 47);
+        assert_eq!(cpu.pc(), 0x2000);
+        // Ticking waits for stolen cycles too
+        cpu.set_pc(0x1000);
+        cpu.steal_cycles(3);
+        let ticks = (1..).find(|_| cpu.tick()).unwrap();
+        assert_eq!(ticks, 5);
+        assert_eq!(cpu.stolen_cycles(), 83);
+    }
+
+    #[test]
+    fn shared_port_lines() {
+        let lines = PortLines::new();
+        let mut cpu = Mos6510::with_port_lines(Ram::zeroed(0xffff), lines.clone());
+        write_port(&mut cpu, 0x2f, 0x37);
+        assert_eq!(lines.levels() & 0x07, 0x07);
+        write_port(&mut cpu, 0x2f, 0x35);
+        assert_eq!(lines.levels() & 0x07, 0x05);
+        // Cassette sense (bit 4) is an input
+        lines.set_input(0x00);
+        assert_eq!(cpu.cpu.memory().get(0x0001) & 0x10, 0x00);
+        cpu.port_lines().set_input(0x10);
+        assert_eq!(cpu.cpu.memory().get(0x0001) & 0x10, 0x10);
+        assert_eq!(lines.input(), 0x10);
+    }
+
+    #[test]
+    fn register_dump() {
+        let mut cpu = Mos6510::new(TestMemory);
+        cpu.set_pc(0xfce2);
+        cpu.set_status(StatusFlags::INTERRUPT_DISABLE_FLAG);
+        write_port(&mut cpu, 0x2f, 0x37);
+        assert_eq!(
+            cpu.to_string(),
+            "PC=$FCE2 A=$00 X=$00 Y=$00 SP=$00 P=$24 [nv-bdIzc] DDR=$2F DAT=$37"
+        );
+    }
+
+    /// Deliver an NMI to any CPU and return where execution continues
+    fn handle_nmi<C: Cpu>(cpu: &mut C) -> u16 {
+        cpu.nmi();
+        cpu.step();
+        cpu.pc()
+    }
+
+    #[test]
+    fn generic_interrupt_access() {
+        let mem = || {
+            let mut mem = Ram::zeroed(0xffff);
+            mem.set_le(NMI_VECTOR, 0x1234_u16);
+            mem
+        };
+        let mut cpu = Mos6510::new(mem());
+        cpu.step(); // RESET
+        assert_eq!(handle_nmi(&mut cpu), 0x1234);
+        let mut cpu = Mos6502::new(mem());
+        cpu.step(); // RESET
+        assert_eq!(handle_nmi(&mut cpu), 0x1234);
+    }
+
+    #[test]
+    fn memory_access() {
+        let mut cpu = Mos6510::new(Ram::zeroed(0xffff));
+        cpu.memory_mut().set_le(NMI_VECTOR, 0x1234_u16);
+        cpu.memory_mut().set(0x1234, 0xea); // EA: NOP
+        cpu.step(); // RESET
+        assert_eq!(handle_nmi(&mut cpu), 0x1234);
+        assert_eq!(cpu.memory().get(0x1234), 0xea);
+        write_port(&mut cpu, 0x2f, 0x00);
+        let (state, mem) = cpu.into_parts();
+        assert_eq!(state.cpu.pc, 0x1234);
+        assert_eq!(state.port_ddr, 0x2f);
+        assert_eq!(mem.get(0x1234), 0xea);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut cpu = Mos6510::new(TestMemory);
+        cpu.step(); // RESET
+        cpu.set_pc(0x00ea); // EA: NOP
+        write_port(&mut cpu, 0x2f, 0x37);
+        let state = cpu.save_state();
+        cpu.step();
+        write_port(&mut cpu, 0x2f, 0x35);
+        assert_ne!(cpu.save_state(), state);
+        cpu.load_state(&state);
+        assert_eq!(cpu.save_state(), state);
+        assert_eq!(cpu.pc(), 0x00ea);
+    }
 }
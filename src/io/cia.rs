@@ -0,0 +1,375 @@
+//! MOS 6526 Complex Interface Adapter (CIA)
+//!
+//! Register reference: http://www.zimmers.net/cbmpics/cbm/c64/cia.txt
+//!
+//! Timers count system clock cycles only (counting CNT pulses isn't supported). Time of day
+//! clock and serial port registers only store written values yet.
+
+use crate::addr::Address;
+use crate::cpu::IrqLine;
+use crate::mem::Addressable;
+use std::cell::Cell;
+
+/// Mask of the address lines the CIA decodes. Its 16 registers are mirrored across the whole
+/// area it is mapped to (e.g. $DC00-$DCFF on a C64).
+const ADDRESS_MASK: u16 = 0x0f;
+
+/// Port A data register
+const PRA: u16 = 0x00;
+/// Port B data register
+const PRB: u16 = 0x01;
+/// Port A data direction register
+const DDRA: u16 = 0x02;
+/// Port B data direction register
+const DDRB: u16 = 0x03;
+/// Timer A low byte
+const TA_LO: u16 = 0x04;
+/// Timer A high byte
+const TA_HI: u16 = 0x05;
+/// Timer B low byte
+const TB_LO: u16 = 0x06;
+/// Timer B high byte
+const TB_HI: u16 = 0x07;
+/// Interrupt control register
+const ICR: u16 = 0x0d;
+/// Control register A
+const CRA: u16 = 0x0e;
+/// Control register B
+const CRB: u16 = 0x0f;
+
+/// Control register bit: timer started
+const CR_START: u8 = 1 << 0;
+/// Control register bit: stop timer on underflow (one-shot mode)
+const CR_ONE_SHOT: u8 = 1 << 3;
+/// Control register bit: load latch into the timer (strobe, always reads as 0)
+const CR_LOAD: u8 = 1 << 4;
+/// Control register B bits: timer B counts timer A underflows
+const CRB_COUNT_TA: u8 = 0b10 << 5;
+
+/// Interrupt flag: timer A underflow
+const INT_TIMER_A: u8 = 1 << 0;
+/// Interrupt flag: timer B underflow
+const INT_TIMER_B: u8 = 1 << 1;
+/// Interrupt flag bit that is set if any enabled interrupt occurred (or which selects whether
+/// to set or clear bits when writing the interrupt mask)
+const INT_ANY: u8 = 1 << 7;
+
+/// A 16 bit down-counting interval timer
+#[derive(Debug, Clone, Default)]
+struct Timer {
+    counter: u16, // current value
+    latch: u16,   // value to reload on underflow
+    control: u8,  // control register
+}
+
+impl Timer {
+    fn is_running(&self) -> bool {
+        self.control & CR_START != 0
+    }
+
+    /// Write the control register
+    fn set_control(&mut self, data: u8) {
+        if data & CR_LOAD != 0 {
+            self.counter = self.latch;
+        }
+        self.control = data & !CR_LOAD;
+    }
+
+    /// Write the low or high byte of the latch. Writing the high byte of a stopped timer also
+    /// loads the timer.
+    fn set_latch(&mut self, high: bool, data: u8) {
+        let [lo, hi] = self.latch.to_le_bytes();
+        self.latch = if high {
+            u16::from_le_bytes([lo, data])
+        } else {
+            u16::from_le_bytes([data, hi])
+        };
+        if high && !self.is_running() {
+            self.counter = self.latch;
+        }
+    }
+
+    /// Count down once. Returns true if the timer underflowed, in which case it is reloaded
+    /// from the latch (and stopped in one-shot mode).
+    fn count(&mut self) -> bool {
+        if self.counter > 0 {
+            self.counter -= 1;
+            return false;
+        }
+        self.counter = self.latch;
+        if self.control & CR_ONE_SHOT != 0 {
+            self.control &= !CR_START;
+        }
+        true
+    }
+}
+
+/// A function that returns the levels driven to a port from outside, given the levels of the
+/// other port (e.g. a keyboard matrix connecting both ports). Pins are pulled up, so returning
+/// #$FF means nothing is driven.
+pub type PortInput = Box<dyn Fn(u8) -> u8>;
+
+/// The CIA chip. Addresses are relative to the start of its register window.
+pub struct Cia {
+    pra: u8,                         // port A output latch
+    prb: u8,                         // port B output latch
+    ddra: u8,                        // port A data direction (bits set to 1 are outputs)
+    ddrb: u8,                        // port B data direction (bits set to 1 are outputs)
+    timer_a: Timer,                  // timer A
+    timer_b: Timer,                  // timer B
+    registers: [u8; 16],             // values written to unmodelled registers
+    int_flags: Cell<u8>,             // occurred interrupts (acknowledged by reading)
+    int_mask: u8,                    // enabled interrupts
+    int_line: Option<(IrqLine, u8)>, // interrupt line and source id to assert on it
+    port_a_inputs: Vec<PortInput>,   // external levels driven to port A
+    port_b_inputs: Vec<PortInput>,   // external levels driven to port B
+}
+
+impl Cia {
+    /// Create a new CIA with all registers cleared and no interrupt line connected
+    pub fn new() -> Cia {
+        Cia {
+            pra: 0,
+            prb: 0,
+            ddra: 0,
+            ddrb: 0,
+            timer_a: Timer::default(),
+            timer_b: Timer::default(),
+            registers: [0; 16],
+            int_flags: Cell::new(0),
+            int_mask: 0,
+            int_line: None,
+            port_a_inputs: Vec::new(),
+            port_b_inputs: Vec::new(),
+        }
+    }
+
+    /// Connect the interrupt output to the given shared interrupt line, where it asserts the
+    /// given source (e.g. the IRQ line for CIA1 and the NMI line for CIA2 on a C64)
+    pub fn connect_interrupt(&mut self, line: IrqLine, source: u8) {
+        self.int_line = Some((line, source));
+        self.update_interrupt();
+    }
+
+    /// Add a function that determines levels driven to port A from outside. It gets the levels
+    /// of port B. Several inputs can be connected to the same port (e.g. keyboard and joystick).
+    pub fn add_port_a_input<F: Fn(u8) -> u8 + 'static>(&mut self, input: F) {
+        self.port_a_inputs.push(Box::new(input));
+    }
+
+    /// Add a function that determines levels driven to port B from outside. It gets the levels
+    /// of port A.
+    pub fn add_port_b_input<F: Fn(u8) -> u8 + 'static>(&mut self, input: F) {
+        self.port_b_inputs.push(Box::new(input));
+    }
+
+    /// Returns the levels of the port A pins. Outputs and external inputs are wired-AND, i.e.
+    /// external hardware can pull pins low even if they are configured as outputs.
+    pub fn port_a(&self) -> u8 {
+        let output = self.pra | !self.ddra;
+        let other = self.prb | !self.ddrb;
+        self.port_a_inputs
+            .iter()
+            .fold(output, |levels, input| levels & input(other))
+    }
+
+    /// Returns the levels of the port B pins (see `port_a`)
+    pub fn port_b(&self) -> u8 {
+        let output = self.prb | !self.ddrb;
+        let other = self.pra | !self.ddra;
+        self.port_b_inputs
+            .iter()
+            .fold(output, |levels, input| levels & input(other))
+    }
+
+    /// Returns the current value of timer A
+    pub fn timer_a(&self) -> u16 {
+        self.timer_a.counter
+    }
+
+    /// Returns the current value of timer B
+    pub fn timer_b(&self) -> u16 {
+        self.timer_b.counter
+    }
+
+    /// Returns the occurred interrupts (without acknowledging them like reading the ICR does)
+    pub fn interrupt_flags(&self) -> u8 {
+        self.int_flags.get()
+    }
+
+    /// Advance by the given number of cycles (usually the number of cycles a CPU step took)
+    pub fn advance(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            let ta_underflow = self.timer_a.is_running() && self.timer_a.count();
+            if ta_underflow {
+                self.int_flags.set(self.int_flags.get() | INT_TIMER_A);
+            }
+            let tb_counts = if self.timer_b.control & CRB_COUNT_TA != 0 {
+                ta_underflow
+            } else {
+                true
+            };
+            if self.timer_b.is_running() && tb_counts && self.timer_b.count() {
+                self.int_flags.set(self.int_flags.get() | INT_TIMER_B);
+            }
+        }
+        self.update_interrupt();
+    }
+
+    /// Assert the interrupt line if an enabled interrupt occurred, release it otherwise
+    fn update_interrupt(&self) {
+        let asserted = self.int_flags.get() & self.int_mask != 0;
+        if let Some((ref line, source)) = self.int_line {
+            line.set(source, asserted);
+        }
+    }
+}
+
+impl Default for Cia {
+    fn default() -> Cia {
+        Cia::new()
+    }
+}
+
+impl Addressable for Cia {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        match addr.to_u16() & ADDRESS_MASK {
+            PRA => self.port_a(),
+            PRB => self.port_b(),
+            DDRA => self.ddra,
+            DDRB => self.ddrb,
+            TA_LO => self.timer_a.counter as u8,
+            TA_HI => (self.timer_a.counter >> 8) as u8,
+            TB_LO => self.timer_b.counter as u8,
+            TB_HI => (self.timer_b.counter >> 8) as u8,
+            // Reading the ICR acknowledges all interrupts
+            ICR => {
+                let flags = self.int_flags.replace(0);
+                self.update_interrupt();
+                if flags & self.int_mask != 0 {
+                    flags | INT_ANY
+                } else {
+                    flags
+                }
+            }
+            CRA => self.timer_a.control,
+            CRB => self.timer_b.control,
+            reg => self.registers[reg as usize],
+        }
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        match addr.to_u16() & ADDRESS_MASK {
+            PRA => self.pra = data,
+            PRB => self.prb = data,
+            DDRA => self.ddra = data,
+            DDRB => self.ddrb = data,
+            TA_LO => self.timer_a.set_latch(false, data),
+            TA_HI => self.timer_a.set_latch(true, data),
+            TB_LO => self.timer_b.set_latch(false, data),
+            TB_HI => self.timer_b.set_latch(true, data),
+            ICR => {
+                if data & INT_ANY != 0 {
+                    self.int_mask |= data & !INT_ANY;
+                } else {
+                    self.int_mask &= !data;
+                }
+                self.update_interrupt();
+            }
+            CRA => self.timer_a.set_control(data),
+            CRB => self.timer_b.set_control(data),
+            reg => self.registers[reg as usize] = data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports() {
+        let mut cia = Cia::new();
+        assert_eq!(cia.get(PRA), 0xff);
+        cia.set(DDRA, 0xff);
+        cia.set(PRA, 0xfe);
+        assert_eq!(cia.get(PRB), 0xff);
+        // Connect bit 0 of both ports
+        cia.add_port_b_input(|a| if a & 0x01 == 0 { 0xfe } else { 0xff });
+        assert_eq!(cia.get(PRB), 0xfe);
+        cia.set(PRA, 0xff);
+        assert_eq!(cia.get(PRB), 0xff);
+        // Further inputs are wired-AND
+        cia.add_port_b_input(|_| 0x7f);
+        assert_eq!(cia.get(PRB), 0x7f);
+    }
+
+    #[test]
+    fn continuous_timer() {
+        let line = IrqLine::new();
+        let mut cia = Cia::new();
+        cia.connect_interrupt(line.clone(), 0);
+        cia.set_le(TA_LO, 0x0004_u16);
+        cia.set(ICR, INT_ANY | INT_TIMER_A);
+        cia.set(CRA, CR_START);
+        cia.advance(4);
+        assert_eq!(cia.timer_a(), 0);
+        assert!(!line.is_asserted());
+        cia.advance(1);
+        assert_eq!(cia.timer_a(), 4);
+        assert_eq!(cia.interrupt_flags(), INT_TIMER_A);
+        assert!(line.is_asserted());
+        // Reading the ICR acknowledges the interrupt
+        assert_eq!(cia.get(ICR), INT_ANY | INT_TIMER_A);
+        assert_eq!(cia.get(ICR), 0);
+        assert!(!line.is_asserted());
+        cia.advance(5);
+        assert_eq!(cia.get(CRA) & CR_START, CR_START);
+        assert_eq!(cia.get(ICR), INT_ANY | INT_TIMER_A);
+    }
+
+    #[test]
+    fn one_shot_timer() {
+        let mut cia = Cia::new();
+        cia.set_le(TB_LO, 0x0100_u16);
+        cia.set(CRB, CR_START | CR_ONE_SHOT);
+        cia.advance(0x100);
+        assert_eq!((cia.timer_b(), cia.interrupt_flags()), (0, 0));
+        cia.advance(1);
+        assert_eq!((cia.timer_b(), cia.interrupt_flags()), (0x100, INT_TIMER_B));
+        assert_eq!(cia.get(CRB) & CR_START, 0);
+        // Disabled interrupts are flagged, but not signalled
+        assert_eq!(cia.get(ICR), INT_TIMER_B);
+        cia.advance(10);
+        assert_eq!(cia.timer_b(), 0x100);
+    }
+
+    #[test]
+    fn timer_b_counts_timer_a_underflows() {
+        let mut cia = Cia::new();
+        cia.set_le(TA_LO, 0x0001_u16);
+        cia.set_le(TB_LO, 0x0002_u16);
+        cia.set(CRA, CR_START);
+        cia.set(CRB, CR_START | CRB_COUNT_TA);
+        cia.advance(4);
+        assert_eq!(cia.timer_b(), 0x0000);
+        assert_eq!(cia.interrupt_flags(), INT_TIMER_A);
+        cia.advance(2);
+        assert_eq!(cia.interrupt_flags(), INT_TIMER_A | INT_TIMER_B);
+    }
+
+    #[test]
+    fn reload_while_running() {
+        let mut cia = Cia::new();
+        cia.set_le(TA_LO, 0x1000_u16);
+        cia.set(CRA, CR_START);
+        cia.advance(0x10);
+        // Writing the latch of a running timer doesn't change the counter until forced
+        cia.set_le(TA_LO, 0x2000_u16);
+        assert_eq!(cia.timer_a(), 0x0ff0);
+        cia.set(CRA, CR_START | CR_LOAD);
+        assert_eq!(cia.timer_a(), 0x2000);
+        assert_eq!(cia.get(CRA), CR_START);
+        assert_eq!(cia.get(TA_HI), 0x20);
+    }
+}
@@ -0,0 +1,9 @@
+//! I/O chips
+
+pub use self::cia::{Cia, PortInput};
+pub use self::sid::Sid;
+pub use self::vic::{LineHandler, VicII};
+
+mod cia;
+mod sid;
+mod vic;
@@ -0,0 +1,129 @@
+//! MOS 6581 Sound Interface Device (SID)
+//!
+//! Register reference: http://www.oxyron.de/html/registers_sid.html
+//!
+//! This is a register scaffold only: written values are captured so they can be used for sound
+//! synthesis later, but no sound is generated yet.
+
+use crate::addr::Address;
+use crate::mem::Addressable;
+
+/// Number of write-only registers (voice, filter and volume registers)
+const NUM_REGISTERS: usize = 25;
+/// Mask of the address lines the SID decodes. Its 32 byte register window is mirrored across
+/// the whole area it is mapped to (e.g. $D400-$D7FF on a C64).
+const ADDRESS_MASK: u16 = 0x1f;
+
+/// Paddle X register (read-only)
+const POTX: u16 = 0x19;
+/// Paddle Y register (read-only)
+const POTY: u16 = 0x1a;
+/// Oscillator 3 output register (read-only)
+const OSC3: u16 = 0x1b;
+/// Envelope 3 output register (read-only)
+const ENV3: u16 = 0x1c;
+
+/// The SID chip. Addresses are relative to the start of its register window.
+#[derive(Debug, Clone)]
+pub struct Sid {
+    registers: [u8; NUM_REGISTERS], // values written to the write-only registers
+    pots: [u8; 2],                  // paddle positions
+    bus: u8,                        // last value on the data bus
+}
+
+impl Sid {
+    /// Create a new SID with all registers cleared and no paddles connected
+    pub fn new() -> Sid {
+        Sid {
+            registers: [0; NUM_REGISTERS],
+            pots: [0xff, 0xff],
+            bus: 0,
+        }
+    }
+
+    /// Returns the values last written to the write-only registers
+    pub fn registers(&self) -> &[u8; NUM_REGISTERS] {
+        &self.registers
+    }
+
+    /// Returns the value last written to the given register
+    pub fn register(&self, reg: usize) -> u8 {
+        self.registers[reg]
+    }
+
+    /// Returns the frequency value of the given voice (0-2)
+    pub fn frequency(&self, voice: usize) -> u16 {
+        let base = voice * 7;
+        u16::from_le_bytes([self.registers[base], self.registers[base + 1]])
+    }
+
+    /// Set the paddle positions that are returned by the POTX/POTY registers
+    pub fn set_pots(&mut self, x: u8, y: u8) {
+        self.pots = [x, y];
+    }
+}
+
+impl Default for Sid {
+    fn default() -> Sid {
+        Sid::new()
+    }
+}
+
+impl Addressable for Sid {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        match addr.to_u16() & ADDRESS_MASK {
+            POTX => self.pots[0],
+            POTY => self.pots[1],
+            // TODO: return oscillator and envelope output once voices are synthesized
+            OSC3 | ENV3 => 0x00,
+            // Write-only and unused registers return what was last on the data bus
+            _ => self.bus,
+        }
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        let reg = (addr.to_u16() & ADDRESS_MASK) as usize;
+        if reg < NUM_REGISTERS {
+            self.registers[reg] = data;
+        }
+        self.bus = data;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mem::MmioDevice;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn voice_frequency() {
+        let sid = Rc::new(RefCell::new(Sid::new()));
+        let mut io = MmioDevice::new();
+        io.map(0x0400..=0x07ff, sid.clone());
+        io.set_le(0x0400, 0x1cd6_u16);
+        io.set_le(0x0427, 0x0001_u16); // mirror of voice 2 frequency
+        assert_eq!(sid.borrow().register(0x00), 0xd6);
+        assert_eq!(sid.borrow().register(0x01), 0x1c);
+        assert_eq!(sid.borrow().frequency(0), 0x1cd6);
+        assert_eq!(sid.borrow().frequency(1), 0x0001);
+        assert_eq!(sid.borrow().frequency(2), 0x0000);
+    }
+
+    #[test]
+    fn reads() {
+        let mut sid = Sid::new();
+        sid.set(0x18, 0x0f);
+        assert_eq!(sid.get(0x00), 0x0f);
+        assert_eq!(sid.get(0x18), 0x0f);
+        assert_eq!(sid.get(0x19), 0xff);
+        sid.set_pots(0x12, 0x34);
+        assert_eq!(sid.get(0x19), 0x12);
+        assert_eq!(sid.get(0x3a), 0x34);
+        assert_eq!(sid.get(0x1c), 0x00);
+        sid.set(0x1b, 0x55);
+        assert_eq!(sid.get(0x1b), 0x00);
+        assert_eq!(sid.registers().iter().filter(|&&r| r != 0).count(), 1);
+    }
+}
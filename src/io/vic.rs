@@ -0,0 +1,148 @@
+//! MOS 6569 Video Interface Chip II (VIC-II), PAL version
+//!
+//! Register reference: http://www.zimmers.net/cbmpics/cbm/c64/vic-ii.txt
+//!
+//! Only the raster timing is modelled yet, no graphics are rendered.
+
+use crate::addr::Address;
+use crate::mem::Addressable;
+
+/// Number of cycles per raster line
+const CYCLES_PER_LINE: u16 = 63;
+/// Number of raster lines per frame
+const LINES_PER_FRAME: u16 = 312;
+/// Number of registers. Reads of the unused registers above return #$FF.
+const NUM_REGISTERS: usize = 0x2f;
+/// Mask of the address lines the VIC-II decodes. Its 64 byte register window is mirrored across
+/// the whole area it is mapped to (e.g. $D000-$D3FF on a C64).
+const ADDRESS_MASK: u16 = 0x3f;
+
+/// Control register 1 (bit 7 is bit 8 of the raster line)
+const CONTROL1: u16 = 0x11;
+/// Raster line register (bits 0-7)
+const RASTER: u16 = 0x12;
+
+/// A handler that is invoked at the start of every raster line with the line number
+pub type LineHandler = Box<dyn FnMut(u16)>;
+
+/// The VIC-II chip. Addresses are relative to the start of its register window.
+pub struct VicII {
+    registers: [u8; NUM_REGISTERS], // values written to the registers
+    raster: u16,                    // current raster line
+    line_cycle: u16,                // current cycle within the raster line
+    on_line: Option<LineHandler>,   // invoked at the start of every raster line
+}
+
+impl VicII {
+    /// Create a new VIC-II, starting at the first cycle of raster line 0
+    pub fn new() -> VicII {
+        VicII {
+            registers: [0; NUM_REGISTERS],
+            raster: 0,
+            line_cycle: 0,
+            on_line: None,
+        }
+    }
+
+    /// Returns the current raster line
+    pub fn raster(&self) -> u16 {
+        self.raster
+    }
+
+    /// Returns the current cycle within the raster line
+    pub fn line_cycle(&self) -> u16 {
+        self.line_cycle
+    }
+
+    /// Set a handler that is invoked at the start of every raster line with the line number
+    pub fn set_line_handler<F: FnMut(u16) + 'static>(&mut self, handler: F) {
+        self.on_line = Some(Box::new(handler));
+    }
+
+    /// Remove the raster line handler
+    pub fn remove_line_handler(&mut self) {
+        self.on_line = None;
+    }
+
+    /// Advance by the given number of cycles (usually the number of cycles a CPU step took)
+    pub fn advance(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.line_cycle += 1;
+            if self.line_cycle == CYCLES_PER_LINE {
+                self.line_cycle = 0;
+                self.raster = (self.raster + 1) % LINES_PER_FRAME;
+                if let Some(ref mut handler) = self.on_line {
+                    handler(self.raster);
+                }
+            }
+        }
+    }
+}
+
+impl Default for VicII {
+    fn default() -> VicII {
+        VicII::new()
+    }
+}
+
+impl Addressable for VicII {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        match addr.to_u16() & ADDRESS_MASK {
+            CONTROL1 => {
+                (self.registers[CONTROL1 as usize] & 0x7f) | ((self.raster >> 1) as u8 & 0x80)
+            }
+            RASTER => self.raster as u8,
+            reg if (reg as usize) < NUM_REGISTERS => self.registers[reg as usize],
+            _ => 0xff,
+        }
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        // TODO: writes to CONTROL1 bit 7 and RASTER set the raster interrupt compare line
+        let reg = (addr.to_u16() & ADDRESS_MASK) as usize;
+        if reg < NUM_REGISTERS {
+            self.registers[reg] = data;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn raster_lines() {
+        let mut vic = VicII::new();
+        let lines = Rc::new(Cell::new(0));
+        let counter = lines.clone();
+        vic.set_line_handler(move |_| counter.set(counter.get() + 1));
+        vic.advance(62);
+        assert_eq!((vic.raster(), vic.line_cycle()), (0, 62));
+        assert_eq!(lines.get(), 0);
+        vic.advance(1);
+        assert_eq!((vic.raster(), vic.line_cycle()), (1, 0));
+        assert_eq!(lines.get(), 1);
+        vic.advance(63 * 299 + 10);
+        assert_eq!((vic.raster(), vic.line_cycle()), (300, 10));
+        assert_eq!(lines.get(), 300);
+        assert_eq!(vic.get(0x12), 0x2c);
+        assert_eq!(vic.get(0x11) & 0x80, 0x80);
+        vic.advance(63 * 12);
+        assert_eq!(vic.raster(), 0);
+        assert_eq!(lines.get(), 312);
+        assert_eq!(vic.get(0x11) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn registers() {
+        let mut vic = VicII::new();
+        vic.set(0x20, 0x0e);
+        vic.set(0x11, 0x9b);
+        assert_eq!(vic.get(0x20), 0x0e);
+        assert_eq!(vic.get(0x60), 0x0e);
+        assert_eq!(vic.get(0x11), 0x1b);
+        assert_eq!(vic.get(0x2f), 0xff);
+    }
+}
@@ -0,0 +1,11 @@
+//! Emulator platform for 8-bit computers
+
+#![warn(missing_docs, unused)]
+
+pub mod addr;
+pub mod c64;
+pub mod cpu;
+pub mod io;
+pub mod mem;
+
+mod pad;
@@ -7,13 +7,9 @@
 // Even more PLA details: http://skoe.de/docs/c64-dissected/pla/c64_pla_dissected_r1.1_a4ss.pdf
 
 #![warn(missing_docs, unused)]
-#![allow(dead_code)]
 
-mod addr;
-mod cpu;
-mod mem;
+use rusty64::{cpu, mem};
 
-#[cfg(not(test))]
 fn main() {
     env_logger::init();
 
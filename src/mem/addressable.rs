@@ -1,6 +1,7 @@
 //! Generic addressing
 
 use crate::addr::{Address, Integer};
+use crate::pad::pad;
 use std::fmt::{self, Write};
 
 /// A trait for anything that has an address bus and can get/set data. The address (any type that
@@ -56,17 +57,117 @@ pub trait Addressable {
         other_addr: A2,
         size: usize,
     ) {
+        let (self_addr, other_addr) = (self_addr.to_u16(), other_addr.to_u16());
         for i in 0..size {
             self.set(
-                self_addr.offset(i as i16),
-                other.get(other_addr.offset(i as i16)),
+                self_addr.wrapping_add(i as u16),
+                other.get(other_addr.wrapping_add(i as u16)),
             );
         }
     }
 
+    /// Copy data from the given slice to the given address
+    fn copy_from_slice<A: Address>(&mut self, addr: A, data: &[u8]) {
+        let addr = addr.to_u16();
+        for (offset, byte) in data.iter().enumerate() {
+            self.set(addr.wrapping_add(offset as u16), *byte);
+        }
+    }
+
+    /// Write the given value to the given number of consecutive addresses starting at the given
+    /// address
+    fn fill<A: Address>(&mut self, start: A, len: usize, value: u8) {
+        let start = start.to_u16();
+        for i in 0..len {
+            self.set(start.wrapping_add(i as u16), value);
+        }
+    }
+
+    /// Returns the bytes at the given addresses, e.g. for inspecting a range of memory. Like
+    /// `get`, this panics if an address is beyond the memory bounds (see `Ram::slice` for a
+    /// fallible alternative).
+    fn read_range<A: Address, I: Iterator<Item = A>>(&self, iter: I) -> Vec<u8> {
+        iter.map(|addr| self.get(addr)).collect()
+    }
+
+    /// Compare the bytes at the given addresses with another memory. Returns the addresses where
+    /// both memories differ, together with the values of this and the other memory.
+    fn diff<A: Address, M: Addressable, I: Iterator<Item = A>>(
+        &self,
+        other: &M,
+        iter: I,
+    ) -> Vec<(A, u8, u8)> {
+        iter.filter_map(|addr| {
+            let (this, that) = (self.get(addr.to_u16()), other.get(addr.to_u16()));
+            (this != that).then_some((addr, this, that))
+        })
+        .collect()
+    }
+
+    /// Calculate the CRC-32 checksum (as used by zip and PNG) of the bytes at the given addresses
+    fn checksum<A: Address, I: Iterator<Item = A>>(&self, iter: I) -> u32 {
+        !iter.fold(!0, |crc, addr| {
+            (0..8).fold(crc ^ self.get(addr) as u32, |crc, _| {
+                (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+            })
+        })
+    }
+
     /// Return an object for displaying a hexdump of the given address range
-    fn hexdump<A: Address, I: Iterator<Item = A> + Clone>(&self, iter: I) -> HexDump<I, Self> {
-        HexDump { mem: self, iter }
+    fn hexdump<A: Address, I: Iterator<Item = A> + Clone>(&self, iter: I) -> HexDump<'_, I, Self> {
+        HexDump {
+            mem: self,
+            iter,
+            ascii: false,
+        }
+    }
+
+    /// Return an object for displaying a multi-line hexdump of the given address range. Every
+    /// line starts with the address of its first byte, followed by the given number of bytes.
+    fn hexdump_lines<A: Address, I: Iterator<Item = A> + Clone>(
+        &self,
+        iter: I,
+        per_line: usize,
+    ) -> HexDumpLines<'_, I, Self> {
+        HexDumpLines {
+            mem: self,
+            iter,
+            per_line,
+        }
+    }
+
+    /// Return an object for displaying a hexdump of the given address range, followed by the
+    /// printable ASCII characters of the range (like `hexdump -C`)
+    fn hexdump_ascii<A: Address, I: Iterator<Item = A> + Clone>(
+        &self,
+        iter: I,
+    ) -> HexDump<'_, I, Self> {
+        HexDump {
+            mem: self,
+            iter,
+            ascii: true,
+        }
+    }
+}
+
+/// Object-safe variant of the `Addressable` trait. It allows using different kinds of
+/// addressable objects as trait objects (e.g. `Box<dyn DynAddressable>`) which implement
+/// `Addressable` again. Every addressable object automatically implements this trait.
+pub trait DynAddressable {
+    /// Memory read: returns the data at the given address
+    fn dyn_get(&self, addr: u16) -> u8;
+
+    /// Memory write: set the data at the given address
+    fn dyn_set(&mut self, addr: u16, data: u8);
+}
+
+impl<M: Addressable> DynAddressable for M {
+    fn dyn_get(&self, addr: u16) -> u8 {
+        self.get(addr)
+    }
+
+    fn dyn_set(&mut self, addr: u16, data: u8) {
+        self.set(addr, data)
     }
 }
 
@@ -74,27 +175,73 @@ pub trait Addressable {
 pub struct HexDump<'a, I, M: 'a + ?Sized> {
     mem: &'a M,
     iter: I,
+    ascii: bool,
 }
 
 impl<'a, A: Address, I: Iterator<Item = A> + Clone, M: Addressable> fmt::Display
     for HexDump<'a, I, M>
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut str = String::new();
+        let count = self.iter.clone().count();
+        let mut len = (count * 3).saturating_sub(1);
+        if self.ascii {
+            len += count + 3;
+        }
+        pad(f, len, |f| {
+            let mut iter = self.iter.clone().peekable();
+            while let Some(addr) = iter.next() {
+                write!(f, "{:02X}", self.mem.get(addr))?;
+                if iter.peek().is_some() {
+                    f.write_char(' ')?;
+                }
+            }
+            if self.ascii {
+                f.write_str(" |")?;
+                for addr in self.iter.clone() {
+                    match self.mem.get(addr) {
+                        ch @ 0x20..=0x7e => f.write_char(ch as char)?,
+                        _ => f.write_char('.')?,
+                    }
+                }
+                f.write_char('|')?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Helper struct for displaying a multi-line hexdump of an address range
+pub struct HexDumpLines<'a, I, M: 'a + ?Sized> {
+    mem: &'a M,
+    iter: I,
+    per_line: usize,
+}
+
+impl<'a, A: Address, I: Iterator<Item = A> + Clone, M: Addressable> fmt::Display
+    for HexDumpLines<'a, I, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut iter = self.iter.clone().peekable();
         while let Some(addr) = iter.next() {
-            write!(str, "{:02X}", self.mem.get(addr))?;
+            write!(f, "{}  {:02X}", addr.display(), self.mem.get(addr))?;
+            for _ in 1..self.per_line {
+                match iter.next() {
+                    Some(addr) => write!(f, " {:02X}", self.mem.get(addr))?,
+                    None => break,
+                }
+            }
             if iter.peek().is_some() {
-                write!(str, " ")?;
+                writeln!(f)?;
             }
         }
-        str.fmt(f)
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::super::test::TestMemory;
+    use super::super::Ram;
     use super::*;
     use crate::addr::Masked;
 
@@ -170,6 +317,20 @@ mod tests {
         assert_eq!(0x13121110_u32, data.get_le(Masked(0x12fe, 0xff00)));
     }
 
+    #[test]
+    fn read_range() {
+        let data = TestMemory;
+        assert_eq!(
+            data.read_range(0x0012..0x0016_u16),
+            [0x12, 0x13, 0x14, 0x15]
+        );
+        assert_eq!(
+            data.read_range(0xfffe_u16.range(0x0001)),
+            [0xfd, 0xfe, 0x00]
+        );
+        assert!(data.read_range(0x0012..0x0012_u16).is_empty());
+    }
+
     #[test]
     fn set_byte() {
         let mut data = TestMemory;
@@ -249,6 +410,57 @@ mod tests {
         data2.copy(0x8000, &data1, 0x0080, 0x0080);
     }
 
+    #[test]
+    fn copying_from_slice() {
+        let mut data = Ram::zeroed(0x03ff);
+        data.copy_from_slice(0x0123, &[0x12, 0x34, 0x56]);
+        assert_eq!(data.getn(0x0122), [0x00, 0x12, 0x34, 0x56, 0x00]);
+    }
+
+    #[test]
+    fn copying_whole_address_space() {
+        let data: Vec<u8> = (0..0x10000).map(|i| (i % 251) as u8).collect();
+        let mut mem = Ram::zeroed(0xffff);
+        mem.copy_from_slice(0x0000, &data);
+        assert_eq!(mem.as_slice(), &data[..]);
+        let mut other = Ram::zeroed(0xffff);
+        other.copy(0x0000, &mem, 0x0000, 0x10000);
+        assert_eq!(other.as_slice(), &data[..]);
+        // Copying wraps around at the end of the address space
+        other.copy_from_slice(0xffff, &[0xaa, 0xbb]);
+        assert_eq!((other.get(0xffff), other.get(0x0000)), (0xaa, 0xbb));
+    }
+
+    #[test]
+    fn filling() {
+        let mut data = Ram::zeroed(0x03ff);
+        data.fill(0x0200, 0x100, 0x20);
+        assert_eq!(data.get(0x01ff), 0x00);
+        assert!((0x0200..0x0300).all(|addr| data.get(addr) == 0x20));
+        assert_eq!(data.get(0x0300), 0x00);
+    }
+
+    #[test]
+    fn comparing() {
+        let mut data = Ram::zeroed(0x03ff);
+        data.fill(0x0000, 0x0400, 0x55);
+        let mut other = Ram::zeroed(0x03ff);
+        other.fill(0x0000, 0x0400, 0x55);
+        assert_eq!(data.diff(&other, 0x0000..0x0400), []);
+        other.set(0x0123, 0xaa);
+        assert_eq!(data.diff(&other, 0x0000..0x0400), [(0x0123, 0x55, 0xaa)]);
+        assert_eq!(data.diff(&other, 0x0200..0x0400), []);
+    }
+
+    #[test]
+    fn calculating_checksum() {
+        let mut data = Ram::zeroed(0x03ff);
+        data.copy_from_slice(0x0100, b"123456789");
+        assert_eq!(data.checksum(0x0100..0x0109), 0xcbf4_3926);
+        assert_eq!(data.checksum(0x0100..0x0100), 0x0000_0000);
+        assert_eq!(TestMemory.checksum(0x0000..0x0100), 0x2905_8c73);
+    }
+
     #[test]
     fn dumping_memory() {
         let data = TestMemory;
@@ -264,4 +476,41 @@ mod tests {
             "     01 02 03 04",
         );
     }
+
+    #[test]
+    fn dumping_memory_lines() {
+        let data = TestMemory;
+        let dump = format!("{}", data.hexdump_lines(0x1000_u16..0x1020, 8));
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "$1000  10 11 12 13 14 15 16 17",
+                "$1008  18 19 1A 1B 1C 1D 1E 1F",
+                "$1010  20 21 22 23 24 25 26 27",
+                "$1018  28 29 2A 2B 2C 2D 2E 2F",
+            ]
+        );
+        assert_eq!(
+            format!("{}", data.hexdump_lines(0x1000_u16..0x100a, 8)),
+            "$1000  10 11 12 13 14 15 16 17\n$1008  18 19",
+        );
+    }
+
+    #[test]
+    fn dumping_memory_with_ascii() {
+        let data = TestMemory;
+        assert_eq!(
+            format!("{}", data.hexdump_ascii(0x0040..0x0044)),
+            "40 41 42 43 |@ABC|",
+        );
+        assert_eq!(
+            format!("{}", data.hexdump_ascii(0x007e..0x0082)),
+            "7E 7F 80 81 |~...|",
+        );
+        assert_eq!(
+            format!("{}", data.hexdump_ascii(0x0100..0x0102)),
+            "01 02 |..|",
+        );
+    }
 }
@@ -0,0 +1,188 @@
+//! Bank-switched memory
+
+use super::{Addressable, DynAddressable};
+use crate::addr::Address;
+use std::ops::RangeInclusive;
+
+/// A memory layer that is mapped to an address range and active depending on the current
+/// configuration
+struct Layer<C> {
+    range: RangeInclusive<u16>,
+    mem: Box<dyn DynAddressable>,
+    writable: bool,
+    active: Box<dyn Fn(&C) -> bool>,
+}
+
+impl<C> Layer<C> {
+    fn maps(&self, addr: u16, config: &C) -> bool {
+        self.range.contains(&addr) && (self.active)(config)
+    }
+}
+
+/// Bank-switched memory. Holds several layers of addressable objects that are mapped to address
+/// ranges and routes every access to the active layer for the accessed address. Whether a layer
+/// is active is decided by its selector, based on the current configuration, which usually is a
+/// small struct of control lines (e.g. LORAM/HIRAM/CHAREN/GAME/EXROM on a C64).
+///
+/// Layers added later have priority over layers added earlier. Writes to a read-only layer go
+/// to the next active layer below it (e.g. writes to a ROM area end up in the RAM underneath).
+pub struct Banked<C> {
+    layers: Vec<Layer<C>>,
+    config: C,
+}
+
+impl<C> Banked<C> {
+    /// Create new bank-switched memory without any layers, using the given initial configuration
+    pub fn new(config: C) -> Banked<C> {
+        Banked {
+            layers: Vec::new(),
+            config,
+        }
+    }
+
+    /// Map the given memory to the given address range. The layer is used for reads and writes
+    /// whenever the given selector returns true for the current configuration. Addresses are
+    /// translated so that the start of the range is address 0 of the layer.
+    pub fn map<M, F>(&mut self, range: RangeInclusive<u16>, mem: M, active: F)
+    where
+        M: Addressable + 'static,
+        F: Fn(&C) -> bool + 'static,
+    {
+        self.add_layer(range, Box::new(mem), true, Box::new(active));
+    }
+
+    /// Map the given memory read-only to the given address range. Like `map`, but writes are
+    /// passed to the next active layer below instead.
+    pub fn map_read_only<M, F>(&mut self, range: RangeInclusive<u16>, mem: M, active: F)
+    where
+        M: Addressable + 'static,
+        F: Fn(&C) -> bool + 'static,
+    {
+        self.add_layer(range, Box::new(mem), false, Box::new(active));
+    }
+
+    fn add_layer(
+        &mut self,
+        range: RangeInclusive<u16>,
+        mem: Box<dyn DynAddressable>,
+        writable: bool,
+        active: Box<dyn Fn(&C) -> bool>,
+    ) {
+        self.layers.push(Layer {
+            range,
+            mem,
+            writable,
+            active,
+        });
+    }
+
+    /// Returns the current configuration
+    pub fn config(&self) -> &C {
+        &self.config
+    }
+
+    /// Returns the current configuration for modification
+    pub fn config_mut(&mut self) -> &mut C {
+        &mut self.config
+    }
+
+    /// Switch to the given configuration
+    pub fn set_config(&mut self, config: C) {
+        self.config = config;
+    }
+}
+
+impl<C> Addressable for Banked<C> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        let addr = addr.to_u16();
+        match self
+            .layers
+            .iter()
+            .rev()
+            .find(|layer| layer.maps(addr, &self.config))
+        {
+            Some(layer) => layer.mem.dyn_get(addr - layer.range.start()),
+            None => panic!("banked: Read from unmapped address ({})", addr.display()),
+        }
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        let addr = addr.to_u16();
+        let config = &self.config;
+        match self
+            .layers
+            .iter_mut()
+            .rev()
+            .find(|layer| layer.writable && layer.maps(addr, config))
+        {
+            Some(layer) => layer.mem.dyn_set(addr - layer.range.start(), data),
+            None => panic!("banked: Write to unmapped address ({})", addr.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Ram;
+    use super::*;
+
+    #[derive(Default)]
+    struct Lines {
+        loram: bool,
+        hiram: bool,
+    }
+
+    fn memory() -> Banked<Lines> {
+        let mut basic = Ram::with_capacity(0x1fff);
+        basic.set(0x0000, 0xba);
+        let mut kernal = Ram::with_capacity(0x1fff);
+        kernal.set(0x0000, 0xea);
+        let mut ram = Ram::new();
+        ram.set(0xa000, 0x11);
+        ram.set(0xe000, 0x22);
+        let mut mem = Banked::new(Lines::default());
+        mem.map(0x0000..=0xffff, ram, |_| true);
+        mem.map_read_only(0xa000..=0xbfff, basic, |l: &Lines| l.loram && l.hiram);
+        mem.map_read_only(0xe000..=0xffff, kernal, |l: &Lines| l.hiram);
+        mem
+    }
+
+    #[test]
+    fn switching_changes_active_layer() {
+        let mut mem = memory();
+        assert_eq!(mem.get(0xa000), 0x11);
+        assert_eq!(mem.get(0xe000), 0x22);
+        mem.config_mut().hiram = true;
+        assert_eq!(mem.get(0xa000), 0x11);
+        assert_eq!(mem.get(0xe000), 0xea);
+        mem.set_config(Lines {
+            loram: true,
+            hiram: true,
+        });
+        assert_eq!(mem.get(0xa000), 0xba);
+        assert_eq!(mem.get(0xe000), 0xea);
+        mem.config_mut().hiram = false;
+        assert_eq!(mem.get(0xa000), 0x11);
+        assert_eq!(mem.get(0xe000), 0x22);
+    }
+
+    #[test]
+    fn write_to_read_only_layer_goes_below() {
+        let mut mem = memory();
+        mem.set_config(Lines {
+            loram: true,
+            hiram: true,
+        });
+        mem.set(0xe000, 0x33);
+        assert_eq!(mem.get(0xe000), 0xea);
+        mem.config_mut().hiram = false;
+        assert_eq!(mem.get(0xe000), 0x33);
+    }
+
+    #[test]
+    #[should_panic(expected = "unmapped address")]
+    fn read_unmapped() {
+        let mem: Banked<()> = Banked::new(());
+        mem.get(0x1234);
+    }
+}
@@ -0,0 +1,118 @@
+//! Address bus
+
+use super::{Addressable, DynAddressable};
+use crate::addr::Address;
+use log::debug;
+use std::ops::RangeInclusive;
+
+/// A device connected to the address bus
+struct Device {
+    range: RangeInclusive<u16>,
+    mem: Box<dyn DynAddressable>,
+}
+
+/// Address bus that connects several devices (e.g. RAM, ROM and I/O) to address ranges and
+/// dispatches every access to the device that owns the accessed address. Devices see addresses
+/// relative to the start of their range. If ranges overlap, devices added later have priority.
+/// Reads from unmapped addresses return the open bus value, writes to unmapped addresses are
+/// ignored.
+pub struct AddressBus {
+    devices: Vec<Device>,
+    open_bus: u8,
+}
+
+impl AddressBus {
+    /// Create a new address bus without any devices
+    pub fn new() -> AddressBus {
+        AddressBus {
+            devices: Vec::new(),
+            open_bus: 0xff,
+        }
+    }
+
+    /// Set the value that reads from unmapped addresses return (defaults to #$FF)
+    pub fn set_open_bus(&mut self, data: u8) {
+        self.open_bus = data;
+    }
+
+    /// Connect the given device to the given address range
+    pub fn map<M: Addressable + 'static>(&mut self, range: RangeInclusive<u16>, mem: M) {
+        self.map_boxed(range, Box::new(mem));
+    }
+
+    /// Connect the given boxed device to the given address range
+    pub fn map_boxed(&mut self, range: RangeInclusive<u16>, mem: Box<dyn DynAddressable>) {
+        self.devices.push(Device { range, mem });
+    }
+
+    fn device(&self, addr: u16) -> Option<&Device> {
+        self.devices.iter().rev().find(|d| d.range.contains(&addr))
+    }
+
+    fn device_mut(&mut self, addr: u16) -> Option<&mut Device> {
+        self.devices
+            .iter_mut()
+            .rev()
+            .find(|d| d.range.contains(&addr))
+    }
+}
+
+impl Default for AddressBus {
+    fn default() -> AddressBus {
+        AddressBus::new()
+    }
+}
+
+impl Addressable for AddressBus {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        let addr = addr.to_u16();
+        match self.device(addr) {
+            Some(device) => device.mem.dyn_get(addr - device.range.start()),
+            None => {
+                debug!("bus: Read from unmapped address ({})", addr.display());
+                self.open_bus
+            }
+        }
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        let addr = addr.to_u16();
+        match self.device_mut(addr) {
+            Some(device) => device.mem.dyn_set(addr - device.range.start(), data),
+            None => debug!(
+                "bus: Ignoring write to unmapped address ({})",
+                addr.display()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Ram, Rom};
+    use super::*;
+
+    #[test]
+    fn dispatch_to_devices() {
+        let mut bus = AddressBus::new();
+        bus.map(0x0000..=0x7fff, Ram::zeroed(0x7fff));
+        bus.map(0xe000..=0xffff, Rom::new("share/c64/kernal.rom"));
+        bus.set(0x1234, 0x55);
+        assert_eq!(bus.get(0x1234), 0x55);
+        assert_eq!(bus.get(0xe123), 0x60);
+        assert_eq!(bus.get(0xa000), 0xff);
+        bus.set(0xe123, 0x55);
+        assert_eq!(bus.get(0xe123), 0x60);
+    }
+
+    #[test]
+    fn later_devices_have_priority() {
+        let mut bus = AddressBus::new();
+        bus.map(0x0000..=0xffff, Ram::zeroed(0xffff));
+        bus.map_boxed(0xd000..=0xdfff, Box::new(Ram::zeroed(0x0fff)));
+        bus.set(0xd020, 0x01);
+        assert_eq!(bus.get(0xd020), 0x01);
+        assert_eq!(bus.get(0xc020), 0x00);
+        assert_eq!(bus.get(0xe020), 0x00);
+    }
+}
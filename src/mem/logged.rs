@@ -0,0 +1,143 @@
+//! Access logging
+
+use super::Addressable;
+use crate::addr::Address;
+use std::cell::{Ref, RefCell};
+
+/// Kind of a memory access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// Memory read
+    Read,
+    /// Memory write
+    Write,
+}
+
+/// A recorded memory access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    /// Kind of access
+    pub kind: AccessKind,
+    /// Accessed address
+    pub addr: u16,
+    /// Data that was read or written
+    pub data: u8,
+}
+
+/// Addressable adapter that records every access to the wrapped memory. Useful for debugging
+/// and for tests that need to check which addresses a routine accesses.
+#[derive(Debug)]
+pub struct Logged<M> {
+    mem: M,
+    log: RefCell<Vec<Access>>,
+}
+
+impl<M: Addressable> Logged<M> {
+    /// Wrap the given memory and start recording accesses
+    pub fn new(mem: M) -> Logged<M> {
+        Logged {
+            mem,
+            log: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Returns all accesses recorded so far
+    pub fn log(&self) -> Ref<'_, [Access]> {
+        Ref::map(self.log.borrow(), |log| log.as_slice())
+    }
+
+    /// Forget all accesses recorded so far
+    pub fn clear_log(&mut self) {
+        self.log.get_mut().clear();
+    }
+
+    /// Returns a reference to the wrapped memory
+    pub fn inner(&self) -> &M {
+        &self.mem
+    }
+
+    /// Consume the adapter and return the wrapped memory
+    pub fn into_inner(self) -> M {
+        self.mem
+    }
+}
+
+impl<M: Addressable> Addressable for Logged<M> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        let data = self.mem.get(addr);
+        self.log.borrow_mut().push(Access {
+            kind: AccessKind::Read,
+            addr: addr.to_u16(),
+            data,
+        });
+        data
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        self.log.get_mut().push(Access {
+            kind: AccessKind::Write,
+            addr: addr.to_u16(),
+            data,
+        });
+        self.mem.set(addr, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Ram;
+    use super::*;
+    use crate::cpu::{Cpu, Mos6502};
+
+    fn read(addr: u16, data: u8) -> Access {
+        Access {
+            kind: AccessKind::Read,
+            addr,
+            data,
+        }
+    }
+
+    fn write(addr: u16, data: u8) -> Access {
+        Access {
+            kind: AccessKind::Write,
+            addr,
+            data,
+        }
+    }
+
+    #[test]
+    fn record_accesses() {
+        let mut mem = Logged::new(Ram::zeroed(0x03ff));
+        mem.set(0x0123, 0x55);
+        assert_eq!(mem.get(0x0123), 0x55);
+        assert_eq!(*mem.log(), [write(0x0123, 0x55), read(0x0123, 0x55)]);
+        mem.clear_log();
+        assert!(mem.log().is_empty());
+    }
+
+    #[test]
+    fn record_cpu_accesses() {
+        let mut ram = Ram::zeroed(0xffff);
+        ram.set_le(0xfffc, 0x1000_u16);
+        ram.setn(0x1000, [0xa5, 0x80]); // A5 80: LDA $80
+        ram.setn(0x1002, [0x8d, 0x00, 0x03]); // 8D 00 03: STA $0300
+        ram.set(0x0080, 0x42);
+        let mut cpu = Mos6502::new(Logged::new(ram));
+        cpu.step(); // RESET
+        cpu.memory_mut().clear_log();
+        cpu.step();
+        cpu.step();
+        assert_eq!(
+            *cpu.memory().log(),
+            [
+                read(0x1000, 0xa5),
+                read(0x1001, 0x80),
+                read(0x0080, 0x42),
+                read(0x1002, 0x8d),
+                read(0x1003, 0x00),
+                read(0x1004, 0x03),
+                write(0x0300, 0x42),
+            ]
+        );
+    }
+}
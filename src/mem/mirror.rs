@@ -0,0 +1,78 @@
+//! Mirrored memory
+
+use super::Addressable;
+use crate::addr::Address;
+
+/// Addressable adapter that masks every address before passing the access to the wrapped
+/// memory, so that the memory is mirrored across a larger address range. Useful for memory
+/// that is echoed in the address space (e.g. the 2k RAM of the NES that appears four times in
+/// 8k) or to emulate processors with fewer address lines (e.g. the MOS6507 which only has 13
+/// address lines).
+#[derive(Debug)]
+pub struct Mirror<M> {
+    mem: M,
+    mask: u16,
+}
+
+impl<M: Addressable> Mirror<M> {
+    /// Wrap the given memory and mask all addresses with the given mask
+    pub fn new(mem: M, mask: u16) -> Mirror<M> {
+        Mirror { mem, mask }
+    }
+
+    /// Returns the address mask
+    pub fn mask(&self) -> u16 {
+        self.mask
+    }
+
+    /// Returns a reference to the wrapped memory
+    pub fn inner(&self) -> &M {
+        &self.mem
+    }
+
+    /// Returns a mutable reference to the wrapped memory
+    pub fn inner_mut(&mut self) -> &mut M {
+        &mut self.mem
+    }
+
+    /// Consume the adapter and return the wrapped memory
+    pub fn into_inner(self) -> M {
+        self.mem
+    }
+}
+
+impl<M: Addressable> Addressable for Mirror<M> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        self.mem.get(addr.to_u16() & self.mask)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        self.mem.set(addr.to_u16() & self.mask, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Ram;
+    use super::*;
+
+    #[test]
+    fn mirror_small_ram() {
+        let mut mem = Mirror::new(Ram::zeroed(0x00ff), 0x00ff);
+        mem.set(0x0000, 0x55);
+        assert_eq!(mem.get(0x0100), 0x55);
+        mem.set(0x0112, 0xaa);
+        assert_eq!(mem.get(0x0012), 0xaa);
+        assert_eq!(mem.inner().get(0x0012), 0xaa);
+    }
+
+    #[test]
+    fn masks_addresses() {
+        let mut mem = Mirror::new(Ram::with_capacity(0x1fff), 0x1fff);
+        mem.inner_mut().set(0x1ffc, 0x12);
+        assert_eq!(mem.get(0xfffc), 0x12);
+        mem.set(0x2000, 0x34);
+        assert_eq!(mem.inner().get(0x0000), 0x34);
+        assert_eq!(mem.get_le::<_, 2, u16>(0x3fff) & 0xff00, 0x3400);
+    }
+}
@@ -0,0 +1,116 @@
+//! Memory-mapped I/O
+
+use super::{AddressBus, Addressable};
+use crate::addr::Address;
+use std::ops::RangeInclusive;
+
+/// Register window that forwards accesses to closures
+struct FnRegisters<R, W> {
+    read: R,
+    write: W,
+}
+
+impl<R: Fn(u16) -> u8, W: FnMut(u16, u8)> Addressable for FnRegisters<R, W> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        (self.read)(addr.to_u16())
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        (self.write)(addr.to_u16(), data)
+    }
+}
+
+/// Memory-mapped I/O area. Dispatches reads and writes to the handler that is registered for
+/// the accessed address (e.g. the registers of VIC, SID and CIAs in the I/O area of a C64).
+/// Handlers see addresses relative to the start of their window. Reads from unmapped addresses
+/// return the open bus value, writes to unmapped addresses are ignored.
+pub struct MmioDevice {
+    bus: AddressBus,
+}
+
+impl MmioDevice {
+    /// Create a new I/O area without any registered handlers
+    pub fn new() -> MmioDevice {
+        MmioDevice {
+            bus: AddressBus::new(),
+        }
+    }
+
+    /// Set the value that reads from unmapped addresses return (defaults to #$FF)
+    pub fn set_open_bus(&mut self, data: u8) {
+        self.bus.set_open_bus(data);
+    }
+
+    /// Register the given addressable object as the handler for the given register window
+    pub fn map<M: Addressable + 'static>(&mut self, range: RangeInclusive<u16>, handler: M) {
+        self.bus.map(range, handler);
+    }
+
+    /// Register the given closures as read and write handlers for the given register window
+    pub fn map_fn<R, W>(&mut self, range: RangeInclusive<u16>, read: R, write: W)
+    where
+        R: Fn(u16) -> u8 + 'static,
+        W: FnMut(u16, u8) + 'static,
+    {
+        self.map(range, FnRegisters { read, write });
+    }
+}
+
+impl Default for MmioDevice {
+    fn default() -> MmioDevice {
+        MmioDevice::new()
+    }
+}
+
+impl Addressable for MmioDevice {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        self.bus.get(addr)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        self.bus.set(addr, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Ram;
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    #[test]
+    fn register_handlers() {
+        let border = Rc::new(Cell::new(0x0e));
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut io = MmioDevice::new();
+        let (value, log) = (border.clone(), writes.clone());
+        io.map_fn(
+            0xd020..=0xd020,
+            move |_| value.get(),
+            move |offset, data| log.borrow_mut().push((offset, data)),
+        );
+        assert_eq!(io.get(0xd020), 0x0e);
+        io.set(0xd020, 0x01);
+        assert_eq!(*writes.borrow(), [(0x0000, 0x01)]);
+        border.set(0x06);
+        assert_eq!(io.get(0xd020), 0x06);
+    }
+
+    #[test]
+    fn relative_addresses() {
+        let mut io = MmioDevice::new();
+        io.map(0xd400..=0xd7ff, Ram::with_capacity(0x03ff));
+        io.set(0xd418, 0x0f);
+        assert_eq!(io.get(0xd418), 0x0f);
+    }
+
+    #[test]
+    fn open_bus() {
+        let mut io = MmioDevice::new();
+        io.set(0xde00, 0x12);
+        assert_eq!(io.get(0xde00), 0xff);
+        io.set_open_bus(0x00);
+        assert_eq!(io.get(0xde00), 0x00);
+    }
+}
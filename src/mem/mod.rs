@@ -1,13 +1,26 @@
 //! Generic addressing (memory)
 
-pub use self::addressable::Addressable;
-pub use self::ram::Ram;
-pub use self::rom::Rom;
+pub use self::addressable::{Addressable, DynAddressable};
+pub use self::banked::Banked;
+pub use self::bus::AddressBus;
+pub use self::logged::{Access, AccessKind, Logged};
+pub use self::mirror::Mirror;
+pub use self::mmio::MmioDevice;
+pub use self::ram::{ProtectPolicy, Ram, RamInit};
+pub use self::readonly::ReadOnly;
+pub use self::rom::{Rom, RomError};
 
 mod addressable;
+mod banked;
+mod bus;
+mod logged;
+mod mirror;
+mod mmio;
 mod ram;
+mod readonly;
 mod rom;
 mod shared;
+mod slice;
 
 #[cfg(test)]
 pub mod test;
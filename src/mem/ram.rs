@@ -2,31 +2,326 @@
 
 use super::Addressable;
 use crate::addr::Address;
+#[cfg(not(feature = "tracing"))]
+use log::info;
+use log::warn;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// Initial contents of RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamInit {
+    /// All bytes are $00
+    Zero,
+    /// Repeating pattern of $00, $55 and $AA
+    Pattern0055AA,
+    /// Alternating bands of 64 bytes $00 and 64 bytes $FF, like the DRAM of a real C64 after
+    /// power-on (some software relies on it)
+    #[default]
+    C64Stripes,
+    /// Pseudo-random bytes generated from a random seed (different on every run)
+    Random,
+    /// Pseudo-random bytes generated from the given seed (identical on every run)
+    Seeded(u64),
+}
+
+impl RamInit {
+    /// Returns the initial contents of the given number of bytes
+    fn fill(self, len: usize) -> Vec<u8> {
+        match self {
+            RamInit::Zero => vec![0; len],
+            RamInit::Pattern0055AA => (0..len).map(|i| [0x00, 0x55, 0xaa][i % 3]).collect(),
+            RamInit::C64Stripes => (0..len)
+                .map(|i| if i & 0x40 == 0 { 0x00 } else { 0xff })
+                .collect(),
+            RamInit::Random => RamInit::Seeded(rand::random()).fill(len),
+            RamInit::Seeded(seed) => {
+                let mut rng = SplitMix64(seed);
+                let mut data: Vec<u8> = (0..len.div_ceil(8))
+                    .flat_map(|_| rng.next().to_le_bytes())
+                    .collect();
+                data.truncate(len);
+                data
+            }
+        }
+    }
+}
+
+/// What happens on writes to write-protected addresses of RAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtectPolicy {
+    /// Ignore the write and log a warning
+    #[default]
+    Warn,
+    /// Ignore the write silently
+    Ignore,
+    /// Panic, which is useful to catch guest bugs in tests
+    Panic,
+}
+
+/// SplitMix64 pseudo-random number generator, which is fast and good enough to fill memory.
+/// See https://prng.di.unimi.it/splitmix64.c
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
 
 /// Generic read/write memory (RAM)
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ram {
     data: Vec<u8>,
     last_addr: u16,
+    #[cfg_attr(feature = "serde", serde(default))]
+    seed: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    protected: Vec<RangeInclusive<u16>>, // write-protected address ranges
+    #[cfg_attr(feature = "serde", serde(skip))]
+    protect_policy: ProtectPolicy, // what happens on writes to protected addresses
 }
 
 impl Ram {
     /// Create new RAM with full capacity of its address range. The whole address space is filled
-    /// with random bytes initially.
+    /// with the power-on pattern of a real C64 initially.
     pub fn new() -> Ram {
-        Ram::with_capacity(!0)
+        Ram::with_init(!0, RamInit::C64Stripes)
     }
 
     /// Create new RAM which will be addressable from 0 to the given address. The whole address
     /// space is filled with random bytes initially.
     pub fn with_capacity(last_addr: u16) -> Ram {
-        let data = (0..=last_addr).map(|_| rand::random()).collect();
-        Ram { data, last_addr }
+        Ram::with_init(last_addr, RamInit::Random)
+    }
+
+    /// Create new RAM which will be addressable from 0 to the given address. The whole address
+    /// space is filled with zeros initially, which is useful for deterministic tests.
+    pub fn zeroed(last_addr: u16) -> Ram {
+        Ram::with_init(last_addr, RamInit::Zero)
+    }
+
+    /// Create new RAM which will be addressable from 0 to the given address, with the given
+    /// initial contents
+    pub fn with_init(last_addr: u16, init: RamInit) -> Ram {
+        // Choose the seed here, so it can be reported to reproduce a run
+        let init = match init {
+            RamInit::Random => RamInit::Seeded(rand::random()),
+            init => init,
+        };
+        let seed = match init {
+            RamInit::Seeded(seed) => Some(seed),
+            _ => None,
+        };
+        Ram {
+            data: init.fill(last_addr as usize + 1),
+            last_addr,
+            seed,
+            protected: Vec::new(),
+            protect_policy: ProtectPolicy::default(),
+        }
+    }
+
+    /// Create new RAM which will be addressable from 0 to the given address. The whole address
+    /// space is filled with pseudo-random bytes generated from the given seed, so the contents
+    /// are identical for identical seeds.
+    pub fn with_seed(last_addr: u16, seed: u64) -> Ram {
+        Ram::with_init(last_addr, RamInit::Seeded(seed))
+    }
+
+    /// Returns the seed the initial contents were generated from, if they are pseudo-random.
+    /// Creating RAM with this seed reproduces the initial contents.
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    /// Create new RAM with the given contents. It will be addressable from 0 to the last byte
+    /// of the given data.
+    pub fn from_vec(data: Vec<u8>) -> Ram {
+        let last_addr = match data.len() {
+            0 => panic!("ram: Unable to create empty RAM"),
+            len if len > 65536 => panic!("ram: Unable to create RAM larger 64k"),
+            len => (len - 1) as u16,
+        };
+        Ram {
+            data,
+            last_addr,
+            seed: None,
+            protected: Vec::new(),
+            protect_policy: ProtectPolicy::default(),
+        }
+    }
+
+    /// Create new RAM with a copy of the given contents. It will be addressable from 0 to the
+    /// last byte of the given data.
+    pub fn from_bytes(data: &[u8]) -> Ram {
+        Ram::from_vec(data.to_vec())
     }
 
     /// Returns the capacity of the RAM
     pub fn capacity(&self) -> usize {
         self.data.len()
     }
+
+    /// Write-protect the given address range, e.g. after copying a ROM image into it. Writes
+    /// to protected addresses are handled according to the protect policy, reads are
+    /// unaffected. Only writes through `Addressable` are checked, loading and restoring
+    /// contents still works.
+    pub fn protect(&mut self, range: RangeInclusive<u16>) {
+        if !range.is_empty() {
+            self.protected.push(range);
+        }
+    }
+
+    /// Remove write protection from the given address range
+    pub fn unprotect(&mut self, range: RangeInclusive<u16>) {
+        if range.is_empty() {
+            return;
+        }
+        let (start, end) = range.into_inner();
+        self.protected = self
+            .protected
+            .iter()
+            .flat_map(|protected| {
+                // Keep the parts of protected ranges below and above the given range
+                let (first, last) = (*protected.start(), *protected.end());
+                let below = (first < start).then(|| first..=last.min(start - 1));
+                let above = (last > end).then(|| first.max(end + 1)..=last);
+                below.into_iter().chain(above)
+            })
+            .collect();
+    }
+
+    /// Returns true if the given address is write-protected
+    pub fn is_protected(&self, addr: u16) -> bool {
+        self.protected.iter().any(|range| range.contains(&addr))
+    }
+
+    /// Set what happens on writes to write-protected addresses (default: ignore and warn)
+    pub fn set_protect_policy(&mut self, policy: ProtectPolicy) {
+        self.protect_policy = policy;
+    }
+
+    /// Returns the whole memory contents
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns a copy of the whole memory contents
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+
+    /// Overwrite the whole memory contents with the given bytes (e.g. a previously taken
+    /// snapshot). The number of bytes must match the capacity of the RAM.
+    pub fn restore(&mut self, bytes: &[u8]) {
+        assert_eq!(
+            bytes.len(),
+            self.data.len(),
+            "ram: Snapshot size doesn't match capacity"
+        );
+        self.data[..].copy_from_slice(bytes);
+    }
+
+    /// Copy the given bytes to memory starting at the given address. Fails without changing
+    /// memory if the data doesn't fit.
+    pub fn load_bytes(&mut self, addr: u16, data: &[u8]) -> io::Result<()> {
+        let end = addr as usize + data.len();
+        if end > self.data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "data at {} with {} bytes exceeds memory bounds ({})",
+                    addr.display(),
+                    data.len(),
+                    self.last_addr.display()
+                ),
+            ));
+        }
+        self.data[addr as usize..end].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns the memory contents of the given address range. Unlike `read_range`, which
+    /// panics on addresses beyond the memory bounds, this fails if the range wraps around or
+    /// exceeds the memory bounds.
+    pub fn slice(&self, range: RangeInclusive<u16>) -> io::Result<&[u8]> {
+        let (start, end) = (*range.start(), *range.end());
+        if start > end || end > self.last_addr {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "range {}-{} wraps around or exceeds memory bounds ({})",
+                    start.display(),
+                    end.display(),
+                    self.last_addr.display()
+                ),
+            ));
+        }
+        Ok(&self.data[start as usize..=end as usize])
+    }
+
+    /// Write the memory contents of the given address range to the given file. Fails if the
+    /// range wraps around or exceeds the memory bounds.
+    pub fn save_range<P: AsRef<Path>>(
+        &self,
+        range: RangeInclusive<u16>,
+        path: P,
+    ) -> io::Result<()> {
+        let data = self.slice(range)?;
+        File::create(path)?.write_all(data)
+    }
+
+    /// Load the contents of the given file to memory starting at the given address. Returns the
+    /// number of loaded bytes. Fails without changing memory if the file doesn't fit.
+    pub fn load_file<P: AsRef<Path>>(&mut self, addr: u16, path: P) -> io::Result<usize> {
+        #[cfg(feature = "tracing")]
+        tracing::info!(path = %path.as_ref().display(), addr, "loading file");
+        #[cfg(not(feature = "tracing"))]
+        info!(
+            "ram: Loading {} to {}",
+            path.as_ref().display(),
+            addr.display()
+        );
+        let mut data = Vec::new();
+        File::open(path)?.read_to_end(&mut data)?;
+        self.load_bytes(addr, &data)?;
+        Ok(data.len())
+    }
+
+    /// Load a C64 program file (PRG). The first two bytes of the file are the load address
+    /// (little-endian), the remaining bytes are copied to memory starting at the load address.
+    /// Returns the load address and the length of the program.
+    pub fn load_prg<P: AsRef<Path>>(&mut self, path: P) -> io::Result<(u16, usize)> {
+        #[cfg(feature = "tracing")]
+        tracing::info!(path = %path.as_ref().display(), "loading PRG");
+        #[cfg(not(feature = "tracing"))]
+        info!("ram: Loading PRG from {}", path.as_ref().display());
+        self.load_prg_from(File::open(path)?)
+    }
+
+    /// Load a C64 program (PRG) from the given reader. See `load_prg`.
+    pub fn load_prg_from<R: Read>(&mut self, mut reader: R) -> io::Result<(u16, usize)> {
+        let mut header = [0; 2];
+        reader.read_exact(&mut header)?;
+        let load_addr = u16::from_le_bytes(header);
+        let mut program = Vec::new();
+        reader.read_to_end(&mut program)?;
+        self.load_bytes(load_addr, &program)?;
+        Ok((load_addr, program.len()))
+    }
+}
+
+impl Default for Ram {
+    fn default() -> Ram {
+        Ram::new()
+    }
 }
 
 impl Addressable for Ram {
@@ -49,6 +344,19 @@ impl Addressable for Ram {
                 self.last_addr.display()
             );
         }
+        if !self.protected.is_empty() && self.is_protected(addr.to_u16()) {
+            match self.protect_policy {
+                ProtectPolicy::Warn => warn!(
+                    "ram: Ignoring write to write-protected memory ({})",
+                    addr.display()
+                ),
+                ProtectPolicy::Ignore => (),
+                ProtectPolicy::Panic => {
+                    panic!("ram: Write to write-protected memory ({})", addr.display())
+                }
+            }
+            return;
+        }
         self.data[addr.to_u16() as usize] = data;
     }
 }
@@ -69,10 +377,238 @@ mod tests {
         assert_eq!(memory.capacity(), 1024);
     }
 
+    #[test]
+    fn create_zeroed() {
+        let memory = Ram::zeroed(0x03ff);
+        assert_eq!(memory.capacity(), 1024);
+        assert!((0x0000..=0x03ff).all(|addr: u16| memory.get(addr) == 0x00));
+    }
+
+    #[test]
+    fn create_with_c64_stripes() {
+        let memory = Ram::new();
+        assert_eq!(memory.get(0x0000), 0x00);
+        assert_eq!(memory.get(0x003f), 0x00);
+        assert_eq!(memory.get(0x0040), 0xff);
+        assert_eq!(memory.get(0x007f), 0xff);
+        assert_eq!(memory.get(0x0080), 0x00);
+        assert_eq!(memory.get(0xffff), 0xff);
+    }
+
+    #[test]
+    fn create_with_pattern() {
+        let memory = Ram::with_init(0x00ff, RamInit::Pattern0055AA);
+        let bytes: Vec<u8> = (0x0000..0x0005_u16).map(|addr| memory.get(addr)).collect();
+        assert_eq!(bytes, [0x00, 0x55, 0xaa, 0x00, 0x55]);
+    }
+
+    #[test]
+    fn create_seeded() {
+        let memory1 = Ram::with_init(0x03ff, RamInit::Seeded(42));
+        let memory2 = Ram::with_init(0x03ff, RamInit::Seeded(42));
+        assert_eq!(memory1.snapshot(), memory2.snapshot());
+        let memory3 = Ram::with_init(0x03ff, RamInit::Seeded(43));
+        assert_ne!(memory1.snapshot(), memory3.snapshot());
+    }
+
+    #[test]
+    fn create_with_seed() {
+        let memory1 = Ram::with_seed(0x03ff, 0x1234_5678);
+        let memory2 = Ram::with_seed(0x03ff, 0x1234_5678);
+        assert_eq!(memory1.seed(), Some(0x1234_5678));
+        assert_eq!(memory1.snapshot(), memory2.snapshot());
+        let memory3 = Ram::with_seed(0x03ff, 0x1234_5679);
+        assert_ne!(memory1.snapshot(), memory3.snapshot());
+        // Random contents can be reproduced from their seed
+        let memory = Ram::with_capacity(0x03fe);
+        let seed = memory.seed().unwrap();
+        assert_eq!(Ram::with_seed(0x03fe, seed).snapshot(), memory.snapshot());
+        assert_eq!(Ram::zeroed(0x03ff).seed(), None);
+    }
+
+    #[test]
+    fn write_protection() {
+        let mut memory = Ram::zeroed(0x03ff);
+        memory.fill(0x0100, 0x100, 0x55);
+        memory.protect(0x0100..=0x01ff);
+        memory.set(0x0180, 0xaa);
+        assert_eq!(memory.get(0x0180), 0x55);
+        assert!(memory.is_protected(0x01ff));
+        assert!(!memory.is_protected(0x0200));
+        // Writes straddling the boundary only affect the unprotected part
+        memory.setn(0x00fe, [0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(memory.getn::<u16, 4>(0x00fe), [0x11, 0x22, 0x55, 0x55]);
+        memory.set_le(0x01ff_u16, 0xbbcc_u16);
+        assert_eq!(memory.getn::<u16, 2>(0x01ff), [0x55, 0xbb]);
+        // Unprotect a part in the middle
+        memory.unprotect(0x0140..=0x017f);
+        memory.set(0x0140, 0xaa);
+        memory.set(0x0180, 0xaa);
+        assert_eq!(memory.get(0x0140), 0xaa);
+        assert_eq!(memory.get(0x0180), 0x55);
+        assert!(memory.is_protected(0x013f));
+        memory.unprotect(0x0000..=0xffff);
+        memory.set(0x0180, 0xaa);
+        assert_eq!(memory.get(0x0180), 0xaa);
+    }
+
+    #[test]
+    #[should_panic(expected = "write-protected memory ($0123)")]
+    fn write_protection_panics() {
+        let mut memory = Ram::zeroed(0x03ff);
+        memory.protect(0x0100..=0x01ff);
+        memory.set_protect_policy(ProtectPolicy::Panic);
+        memory.set(0x0123, 0xaa);
+    }
+
+    #[test]
+    fn save_range() {
+        let mut memory = Ram::zeroed(0x07ff);
+        for (i, addr) in (0x0400..=0x07e7_u16).enumerate() {
+            memory.set(addr, i as u8);
+        }
+        let file = tempfile::NamedTempFile::new().unwrap();
+        memory.save_range(0x0400..=0x07e7, file.path()).unwrap();
+        let data = std::fs::read(file.path()).unwrap();
+        assert_eq!(data.len(), 1000);
+        assert_eq!(data, memory.read_range(0x0400..=0x07e7_u16));
+        assert_eq!(data, memory.as_slice()[0x0400..=0x07e7]);
+        let mut other = Ram::zeroed(0x07ff);
+        other.load_file(0x0400, file.path()).unwrap();
+        assert_eq!(other.as_slice(), memory.as_slice());
+    }
+
+    #[test]
+    fn save_invalid_range() {
+        let memory = Ram::zeroed(0x03ff);
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let err = memory.save_range(0x0400..=0x07e7, file.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "range $0400-$07E7 wraps around or exceeds memory bounds ($03FF)"
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        let range = 0x0200..=0x0100;
+        assert!(memory.save_range(range, file.path()).is_err());
+    }
+
+    #[test]
+    fn read_ranges() {
+        let mut memory = Ram::zeroed(0x03ff);
+        memory.setn(0x03fe, [0x12, 0x34]);
+        assert_eq!(memory.slice(0x03fe..=0x03ff).unwrap(), [0x12, 0x34]);
+        assert_eq!(memory.read_range(0x03fe..=0x03ff_u16), [0x12, 0x34]);
+        let err = memory.slice(0x03fe..=0x0400).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            err.to_string(),
+            "range $03FE-$0400 wraps around or exceeds memory bounds ($03FF)"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Read beyond memory bounds ($0400 > $03FF)")]
+    fn read_range_beyond_bounds() {
+        let memory = Ram::zeroed(0x03ff);
+        memory.read_range(0x03fe..=0x0400_u16);
+    }
+
+    #[test]
+    fn create_from_vec() {
+        let mut memory = Ram::from_vec(vec![0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(memory.capacity(), 4);
+        assert_eq!(memory.get(0x0000), 0x12);
+        assert_eq!(memory.get(0x0003), 0x78);
+        memory.set(0x0002, 0x55);
+        assert_eq!(memory.get(0x0002), 0x55);
+    }
+
+    #[test]
+    fn create_from_bytes() {
+        let memory = Ram::from_bytes(&[0xa9, 0x00, 0x60]);
+        assert_eq!(memory.capacity(), 3);
+        assert_eq!(memory.get(0x0002), 0x60);
+    }
+
+    #[test]
+    fn load_bytes() {
+        let mut memory = Ram::zeroed(0x03ff);
+        memory.load_bytes(0x03fd, &[0x01, 0x02, 0x03]).unwrap();
+        assert_eq!(memory.get(0x03ff), 0x03);
+        let err = memory.load_bytes(0x03fe, &[0xff, 0xff, 0xff]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "data at $03FE with 3 bytes exceeds memory bounds ($03FF)"
+        );
+        assert_eq!(memory.get(0x03fe), 0x02);
+    }
+
+    #[test]
+    fn load_file() {
+        let mut memory = Ram::zeroed(0xffff);
+        let len = memory
+            .load_file(0xe000, "share/test/ttl6502_v10.rom")
+            .unwrap();
+        assert_eq!(len, 8192);
+        let mut memory = Ram::zeroed(0x1fff);
+        let err = memory
+            .load_file(0x0001, "share/test/ttl6502_v10.rom")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(memory.get(0x1fff), 0x00);
+    }
+
+    #[test]
+    #[should_panic(expected = "Write beyond memory bounds")]
+    fn write_beyond_bounds() {
+        let mut memory = Ram::from_vec(vec![0x12, 0x34, 0x56, 0x78]);
+        memory.set(0x0004, 0x55);
+    }
+
     #[test]
     fn read_write() {
         let mut memory = Ram::with_capacity(0x03ff);
         memory.set(0x0123, 0x55);
         assert_eq!(memory.get(0x0123), 0x55);
     }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut memory = Ram::with_capacity(0x03ff);
+        memory.set(0x0123, 0x55);
+        let snapshot = memory.snapshot();
+        assert_eq!(snapshot.len(), 1024);
+        memory.set(0x0123, 0xaa);
+        memory.set(0x0200, 0x12);
+        memory.restore(&snapshot);
+        assert_eq!(memory.get(0x0123), 0x55);
+        assert_eq!(memory.snapshot(), snapshot);
+    }
+
+    #[test]
+    #[should_panic(expected = "Snapshot size")]
+    fn restore_wrong_size() {
+        let mut memory = Ram::with_capacity(0x03ff);
+        memory.restore(&[0; 16]);
+    }
+
+    #[test]
+    fn load_prg() {
+        let mut memory = Ram::new();
+        let prg = [0x01, 0x08, 0x0b, 0x08, 0x0a, 0x00, 0x9e];
+        let (addr, len) = memory.load_prg_from(&prg[..]).unwrap();
+        assert_eq!((addr, len), (0x0801, 5));
+        assert_eq!(memory.get(0x0801), 0x0b);
+        assert_eq!(memory.get(0x0805), 0x9e);
+    }
+
+    #[test]
+    fn load_prg_beyond_bounds() {
+        let mut memory = Ram::with_capacity(0x03ff);
+        let prg = [0xfe, 0x03, 0x01, 0x02, 0x03];
+        assert!(memory.load_prg_from(&prg[..]).is_err());
+        assert!(memory.load_prg_from(&[0x00][..]).is_err());
+    }
 }
@@ -0,0 +1,67 @@
+//! Read-only memory regions
+
+use super::Addressable;
+use crate::addr::Address;
+use log::warn;
+
+/// Addressable adapter that makes the wrapped memory read-only. Reads are passed to the wrapped
+/// memory, writes are ignored (like writes to a ROM). Useful to protect ROM images that were
+/// loaded into RAM.
+#[derive(Debug)]
+pub struct ReadOnly<M> {
+    mem: M,
+}
+
+impl<M: Addressable> ReadOnly<M> {
+    /// Wrap the given memory and make it read-only
+    pub fn new(mem: M) -> ReadOnly<M> {
+        ReadOnly { mem }
+    }
+
+    /// Returns a reference to the wrapped memory
+    pub fn inner(&self) -> &M {
+        &self.mem
+    }
+
+    /// Returns a mutable reference to the wrapped memory, which allows writing to it
+    pub fn inner_mut(&mut self) -> &mut M {
+        &mut self.mem
+    }
+
+    /// Consume the adapter and return the wrapped memory
+    pub fn into_inner(self) -> M {
+        self.mem
+    }
+}
+
+impl<M: Addressable> Addressable for ReadOnly<M> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        self.mem.get(addr)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, _data: u8) {
+        warn!(
+            "readonly: Ignoring write to read-only memory ({})",
+            addr.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Ram;
+    use super::*;
+
+    #[test]
+    fn write_does_nothing() {
+        let mut ram = Ram::zeroed(0x03ff);
+        ram.set(0x0123, 0x55);
+        let mut memory = ReadOnly::new(ram);
+        assert_eq!(memory.get(0x0123), 0x55);
+        memory.set(0x0123, 0xaa);
+        assert_eq!(memory.get(0x0123), 0x55);
+        assert_eq!(memory.inner().get(0x0123), 0x55);
+        memory.inner_mut().set(0x0123, 0xaa);
+        assert_eq!(memory.get(0x0123), 0xaa);
+    }
+}
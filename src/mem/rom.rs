@@ -2,44 +2,126 @@
 
 use super::Addressable;
 use crate::addr::Address;
+#[cfg(not(feature = "tracing"))]
 use log::{info, warn};
-use std::env;
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
 use std::path::Path;
+use std::{error, fmt};
+
+/// An error that occured while loading a ROM
+#[derive(Debug)]
+pub enum RomError {
+    /// The ROM file couldn't be read
+    Io(io::Error),
+    /// The ROM doesn't contain any data
+    Empty,
+    /// The ROM is larger than the address space (with the given size in bytes)
+    TooLarge(usize),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomError::Io(ref err) => write!(f, "Unable to load ROM: {}", err),
+            RomError::Empty => write!(f, "Unable to load empty ROM"),
+            RomError::TooLarge(size) => {
+                write!(f, "Unable to load ROM larger 64k ({} bytes)", size)
+            }
+        }
+    }
+}
+
+impl error::Error for RomError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            RomError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> RomError {
+        RomError::Io(err)
+    }
+}
 
 /// Generic read-only memory (ROM)
 pub struct Rom {
-    data: Vec<u8>,
+    data: Cow<'static, [u8]>,
     last_addr: u16,
 }
 
 impl Rom {
-    /// Create new ROM with contents of the given file
-    pub fn new<P: AsRef<Path>>(path: P) -> Rom {
-        let filename = env::current_dir().unwrap().join("share").join(path);
-        info!("rom: Loading ROM from {}", filename.display());
+    /// Create new ROM with contents of the given file. The path is used as given, i.e. relative
+    /// paths are relative to the current directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Rom, RomError> {
+        let path = path.as_ref();
+        #[cfg(feature = "tracing")]
+        tracing::info!(path = %path.display(), "loading ROM");
+        #[cfg(not(feature = "tracing"))]
+        info!("rom: Loading ROM from {}", path.display());
         let mut data = Vec::new();
-        let mut f = match File::open(&filename) {
-            Err(err) => panic!("rom: Unable to open ROM: {}", err),
-            Ok(f) => f,
-        };
-        let len = match f.read_to_end(&mut data) {
-            Err(err) => panic!("rom: Unable to load ROM: {}", err),
-            Ok(0) => panic!("rom: Unable to load empty ROM"),
-            Ok(len) if len > 65536 => panic!("rom: Unable to load ROM larger 64k"),
-            Ok(len) => len,
+        File::open(path)?.read_to_end(&mut data)?;
+        Rom::try_from_bytes(Cow::Owned(data))
+    }
+
+    /// Create new ROM with contents of the given file. Panics if the ROM can't be loaded, use
+    /// `open` to handle errors instead.
+    pub fn new<P: AsRef<Path>>(path: P) -> Rom {
+        Rom::open(path).unwrap_or_else(|err| panic!("rom: {}", err))
+    }
+
+    /// Create new ROM with contents of the given file. Panics if the file doesn't have the
+    /// given size.
+    pub fn with_expected_size<P: AsRef<Path>>(path: P, size: usize) -> Rom {
+        let rom = Rom::new(path);
+        if rom.capacity() != size {
+            panic!(
+                "rom: Unexpected ROM size ({} bytes, expected {} bytes)",
+                rom.capacity(),
+                size
+            );
+        }
+        rom
+    }
+
+    /// Create new ROM with a copy of the given contents. Panics if the contents are empty or
+    /// larger than 64k.
+    pub fn from_bytes<D: Into<Vec<u8>>>(data: D) -> Rom {
+        Rom::try_from_bytes(Cow::Owned(data.into())).unwrap_or_else(|err| panic!("rom: {}", err))
+    }
+
+    /// Create new ROM with the given static contents without copying them (e.g. an image
+    /// embedded with `include_bytes!`). Panics if the contents are empty or larger than 64k.
+    pub fn from_static(data: &'static [u8]) -> Rom {
+        Rom::try_from_bytes(Cow::Borrowed(data)).unwrap_or_else(|err| panic!("rom: {}", err))
+    }
+
+    /// Create new ROM with the given contents if it has a valid size
+    fn try_from_bytes(data: Cow<'static, [u8]>) -> Result<Rom, RomError> {
+        let len = match data.len() {
+            0 => return Err(RomError::Empty),
+            len if len > 65536 => return Err(RomError::TooLarge(len)),
+            len => len,
         };
-        Rom {
+        Ok(Rom {
             data,
             last_addr: (len - 1) as u16,
-        }
+        })
     }
 
     /// Returns the capacity of the ROM
     pub fn capacity(&self) -> usize {
         self.data.len()
     }
+
+    /// Calculate the CRC-32 checksum of the whole ROM, e.g. to identify known ROM images
+    pub fn crc32(&self) -> u32 {
+        self.checksum((0..self.data.len()).map(|addr| addr as u16))
+    }
 }
 
 impl Addressable for Rom {
@@ -55,6 +137,9 @@ impl Addressable for Rom {
     }
 
     fn set<A: Address>(&mut self, addr: A, _data: u8) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(addr = addr.to_u16(), "ignoring write to ROM");
+        #[cfg(not(feature = "tracing"))]
         warn!(
             "rom: Ignoring write to read-only memory ({})",
             addr.display()
@@ -68,19 +153,112 @@ mod tests {
 
     #[test]
     fn create_with_file_contents() {
-        let memory = Rom::new("c64/kernal.rom");
+        let memory = Rom::new("share/c64/kernal.rom");
         assert_eq!(memory.capacity(), 8192);
     }
 
     #[test]
     fn read() {
-        let memory = Rom::new("c64/kernal.rom");
+        let memory = Rom::new("share/c64/kernal.rom");
         assert_eq!(memory.get(0x0123), 0x60);
     }
 
+    #[test]
+    fn checksum() {
+        // Original C64 KERNAL revision 3 (901227-03)
+        let memory = Rom::new("share/c64/kernal.rom");
+        assert_eq!(memory.checksum(0x0000..0x2000), 0xdbe3_e7c7);
+    }
+
+    #[test]
+    fn open_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = Rom::open(dir.path().join("kernal.rom")).err().unwrap();
+        assert!(matches!(err, RomError::Io(ref err) if err.kind() == io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn open_empty_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(matches!(Rom::open(file.path()), Err(RomError::Empty)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unable to load empty ROM")]
+    fn create_with_empty_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        Rom::new(file.path());
+    }
+
+    #[test]
+    fn crc32() {
+        let memory = Rom::new("share/c64/kernal.rom");
+        assert_eq!(memory.crc32(), 0xdbe3_e7c7);
+        let memory = Rom::from_bytes(vec![0xff; 0x10000]);
+        assert_eq!(memory.crc32(), memory.checksum(0x0000..=0xffff_u16));
+    }
+
+    #[test]
+    fn create_with_expected_size() {
+        let memory = Rom::with_expected_size("share/c64/kernal.rom", 8192);
+        assert_eq!(memory.capacity(), 8192);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected ROM size")]
+    fn create_with_unexpected_size() {
+        Rom::with_expected_size("share/c64/kernal.rom", 4096);
+    }
+
+    #[test]
+    fn create_from_bytes() {
+        let memory = Rom::from_bytes(vec![0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(memory.capacity(), 4);
+        assert_eq!(memory.get(0x0000), 0x12);
+        assert_eq!(memory.get(0x0001), 0x34);
+        assert_eq!(memory.get(0x0002), 0x56);
+        assert_eq!(memory.get(0x0003), 0x78);
+    }
+
+    #[test]
+    fn create_from_static() {
+        static DATA: [u8; 3] = [0xa9, 0x00, 0x60];
+        let memory = Rom::from_static(&DATA);
+        assert_eq!(memory.capacity(), 3);
+        assert_eq!(memory.get(0x0002), 0x60);
+        let memory = Rom::from_bytes(&DATA[..2]);
+        assert_eq!(memory.capacity(), 2);
+    }
+
+    #[test]
+    fn create_with_max_size() {
+        let memory = Rom::from_bytes(vec![0xea; 0x10000]);
+        assert_eq!(memory.capacity(), 0x10000);
+        assert_eq!(memory.get(0xffff), 0xea);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unable to load empty ROM")]
+    fn create_from_empty_bytes() {
+        Rom::from_static(&[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unable to load ROM larger 64k (65537 bytes)")]
+    fn create_from_too_many_bytes() {
+        Rom::from_bytes(vec![0; 0x10001]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Read beyond memory bounds")]
+    fn read_beyond_bounds() {
+        let memory = Rom::from_bytes(vec![0x12, 0x34, 0x56, 0x78]);
+        memory.get(0x0004);
+    }
+
     #[test]
     fn write_does_nothing() {
-        let mut memory = Rom::new("c64/kernal.rom");
+        let mut memory = Rom::new("share/c64/kernal.rom");
         memory.set(0x0123, 0x55);
         assert!(memory.get(0x0123) != 0x55);
     }
@@ -1,10 +1,20 @@
 //! Generic implementations for shared (wrapped) addressable objects
 
-use super::Addressable;
+use super::{Addressable, DynAddressable};
 use crate::addr::Address;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+impl Addressable for Box<dyn DynAddressable> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        (**self).dyn_get(addr.to_u16())
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        (**self).dyn_set(addr.to_u16(), data)
+    }
+}
+
 impl<M: Addressable> Addressable for RefCell<M> {
     fn get<A: Address>(&self, addr: A) -> u8 {
         self.borrow().get(addr)
@@ -41,6 +51,14 @@ mod tests {
         assert_eq!(mem.get(0x56), 0x78);
     }
 
+    #[test]
+    fn read_write_boxed() {
+        let mut mem: Box<dyn DynAddressable> = Box::new(Ram::new());
+        mem.set(0x12, 0x34);
+        assert_eq!(mem.get(0x12), 0x34);
+        assert_eq!(mem.get_le::<_, 2, u16>(0x12) & 0x00ff, 0x0034);
+    }
+
     #[test]
     fn read_write_shared() {
         let mut mem1 = Rc::new(RefCell::new(Ram::new()));
@@ -0,0 +1,142 @@
+//! Generic implementations for plain byte buffers
+//!
+//! Buffers are addressed from 0 to their length. Accessing an address beyond the end of a
+//! buffer panics (instead of wrapping around), since it's usually a bug in a test or device.
+
+use super::Addressable;
+use crate::addr::Address;
+
+/// Read the byte at the given address of the given buffer, panics if the address is out of
+/// bounds
+fn get<A: Address>(data: &[u8], addr: A) -> u8 {
+    match data.get(addr.to_u16() as usize) {
+        Some(&value) => value,
+        None => panic!(
+            "slice: Read beyond memory bounds ({} >= {})",
+            addr.display(),
+            data.len()
+        ),
+    }
+}
+
+/// Write the byte at the given address of the given buffer, panics if the address is out of
+/// bounds
+fn set<A: Address>(data: &mut [u8], addr: A, value: u8) {
+    let len = data.len();
+    match data.get_mut(addr.to_u16() as usize) {
+        Some(byte) => *byte = value,
+        None => panic!(
+            "slice: Write beyond memory bounds ({} >= {})",
+            addr.display(),
+            len
+        ),
+    }
+}
+
+impl Addressable for Vec<u8> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        get(self, addr)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        set(self, addr, data)
+    }
+}
+
+impl<const N: usize> Addressable for [u8; N] {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        get(self, addr)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        set(self, addr, data)
+    }
+}
+
+impl Addressable for Box<[u8]> {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        get(self, addr)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        set(self, addr, data)
+    }
+}
+
+impl Addressable for &mut [u8] {
+    fn get<A: Address>(&self, addr: A) -> u8 {
+        get(self, addr)
+    }
+
+    fn set<A: Address>(&mut self, addr: A, data: u8) {
+        set(self, addr, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{Cpu, Mos6502};
+
+    #[test]
+    fn read_write_vec() {
+        let mut mem = vec![0x00; 0x0400];
+        mem.set_le(0x0123, 0x1234_u16);
+        assert_eq!(mem.get_le::<_, 2, u16>(0x0123), 0x1234);
+        assert_eq!(mem[0x0123..0x0125], [0x34, 0x12]);
+    }
+
+    #[test]
+    fn read_write_slice() {
+        let mut buffer = [0x00; 16];
+        let mut mem = &mut buffer[..];
+        mem.set_le(0x0004, 0x12345678_u32);
+        assert_eq!(mem.get_le::<_, 4, u32>(0x0004), 0x12345678);
+        assert_eq!(buffer[4..8], [0x78, 0x56, 0x34, 0x12]);
+    }
+
+    #[test]
+    fn read_write_array() {
+        let mut mem = [0x00; 16];
+        mem.set_le(0x0004, 0x1234_u16);
+        assert_eq!(mem.get_le::<_, 2, u16>(0x0004), 0x1234);
+        assert_eq!(mem[4..6], [0x34, 0x12]);
+    }
+
+    #[test]
+    fn read_write_boxed_slice() {
+        let mut mem = vec![0x00; 16].into_boxed_slice();
+        mem.set_be(0x0004, 0x1234_u16);
+        assert_eq!(mem.get_be::<_, 2, u16>(0x0004), 0x1234);
+        assert_eq!(mem[4..6], [0x12, 0x34]);
+    }
+
+    #[test]
+    fn run_program_from_vec() {
+        let mut mem = vec![0x00; 0x10000];
+        mem.set_le(0xfffc, 0x1000_u16);
+        // A9 42: LDA #$42 / 8D 00 02: STA $0200
+        mem.copy_from_slice(0x1000, &[0xa9, 0x42, 0x8d, 0x00, 0x02]);
+        let mut cpu = Mos6502::new(mem);
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!(cpu.pc(), 0x1005);
+        assert_eq!(cpu.memory().get(0x0200), 0x42);
+    }
+
+    #[test]
+    #[should_panic(expected = "Write beyond memory bounds")]
+    fn write_beyond_bounds() {
+        let mut buffer = [0x00; 16];
+        let mut mem = &mut buffer[..];
+        mem.set(0x0010, 0x55);
+    }
+
+    #[test]
+    #[should_panic(expected = "Read beyond memory bounds")]
+    fn read_beyond_bounds() {
+        let mem = vec![0x00; 16];
+        mem.get(0x0010);
+    }
+}
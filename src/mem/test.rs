@@ -7,9 +7,11 @@ use crate::mem::Addressable;
 /// Reading the memory always returns a data byte that equals the sum of the lower and higher
 /// nibble of the requested address. Writing the memory asserts that the set data byte equals the
 /// sum of the lower and hight nibble of the requested address.
+#[derive(Default)]
 pub struct TestMemory;
 
 impl TestMemory {
+    /// Create new test-memory
     pub fn new() -> TestMemory {
         TestMemory
     }
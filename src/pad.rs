@@ -0,0 +1,50 @@
+//! Padding for Display implementations
+
+use std::fmt::{self, Alignment, Write};
+
+/// Write content of the given length to the formatter, padded according to the formatter's
+/// width, fill and alignment (left-aligned by default, like `Formatter::pad`). Unlike
+/// `Formatter::pad`, the content is written directly, without formatting it into a temporary
+/// string first.
+pub(crate) fn pad<F>(f: &mut fmt::Formatter, len: usize, content: F) -> fmt::Result
+where
+    F: FnOnce(&mut fmt::Formatter) -> fmt::Result,
+{
+    let padding = f.width().map_or(0, |width| width.saturating_sub(len));
+    let (before, after) = match f.align() {
+        Some(Alignment::Right) => (padding, 0),
+        Some(Alignment::Center) => (padding / 2, padding - padding / 2),
+        _ => (0, padding),
+    };
+    let fill = f.fill();
+    for _ in 0..before {
+        f.write_char(fill)?;
+    }
+    content(f)?;
+    for _ in 0..after {
+        f.write_char(fill)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Hello;
+
+    impl fmt::Display for Hello {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            pad(f, 5, |f| f.write_str("hello"))
+        }
+    }
+
+    #[test]
+    fn padding() {
+        assert_eq!(format!("{}", Hello), "hello");
+        assert_eq!(format!("{:3}", Hello), "hello");
+        assert_eq!(format!("{:8}|", Hello), "hello   |");
+        assert_eq!(format!("{:>8}", Hello), "   hello");
+        assert_eq!(format!("{:*^9}", Hello), "**hello**");
+    }
+}
@@ -0,0 +1,81 @@
+//! Serialization of CPU and memory state
+
+#![cfg(feature = "serde")]
+
+use rusty64::cpu::mos6502::{CpuState, StatusFlags};
+use rusty64::cpu::{Cpu, Mos6502, Mos6510State};
+use rusty64::mem::{Addressable, Ram, Rom};
+
+fn ruud_baltissen_cpu() -> Mos6502<Ram> {
+    let mut cpu = Mos6502::new(Ram::zeroed(0xffff));
+    let rom = Rom::new("share/test/ttl6502_v10.rom");
+    cpu.memory_mut().copy(0xe000, &rom, 0x0000, rom.capacity());
+    cpu.reset();
+    cpu
+}
+
+fn run(cpu: &mut Mos6502<Ram>, steps: usize) {
+    for _ in 0..steps {
+        cpu.step();
+        // TODO: This skips decimal mode tests for now
+        if cpu.pc() == 0xf5b6 {
+            cpu.set_pc(0xf5e6);
+        }
+    }
+}
+
+#[test]
+fn status_flags_round_trip() {
+    let sr = StatusFlags::CARRY_FLAG | StatusFlags::UNUSED_ALWAYS_ON_FLAG;
+    let json = serde_json::to_string(&sr).unwrap();
+    assert_eq!(json, "33");
+    assert_eq!(serde_json::from_str::<StatusFlags>(&json).unwrap(), sr);
+}
+
+#[test]
+fn cpu_state_round_trip() {
+    let mut cpu = ruud_baltissen_cpu();
+    run(&mut cpu, 20);
+    let state = cpu.save_state();
+    let json = serde_json::to_string(&state).unwrap();
+    assert_eq!(serde_json::from_str::<CpuState>(&json).unwrap(), state);
+}
+
+#[test]
+fn mos6510_state_round_trip() {
+    let state = Mos6510State {
+        cpu: ruud_baltissen_cpu().save_state(),
+        port_ddr: 0x2f,
+        port_dat: 0x37,
+    };
+    let json = serde_json::to_string(&state).unwrap();
+    assert_eq!(serde_json::from_str::<Mos6510State>(&json).unwrap(), state);
+}
+
+#[test]
+fn ram_round_trip() {
+    let mut ram = Ram::with_capacity(0x00ff);
+    ram.set(0x0012, 0x34);
+    let json = serde_json::to_string(&ram).unwrap();
+    let restored: Ram = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored.capacity(), 256);
+    assert_eq!(restored.snapshot(), ram.snapshot());
+}
+
+#[test]
+fn resume_saved_state() {
+    let mut cpu = ruud_baltissen_cpu();
+    run(&mut cpu, 1000);
+    let json = serde_json::to_string(&cpu.to_state()).unwrap();
+    drop(cpu);
+    let (state, ram): (CpuState, Ram) = serde_json::from_str(&json).unwrap();
+    let mut cpu = Mos6502::from_state(&state, ram);
+    run(&mut cpu, 2000);
+    let status = cpu.memory().get(0x0003);
+    assert!(
+        status == 0xfe,
+        "stopped at ${:04X} with status #${:02X}",
+        cpu.pc(),
+        status,
+    );
+}
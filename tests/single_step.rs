@@ -0,0 +1,286 @@
+//! MOS 6502 SingleStepTests (ProcessorTests) runner
+//!
+//! The SingleStepTests suite provides 10,000 test cases per opcode. Each case consists of an
+//! initial state, the expected final state after executing one instruction and the list of bus
+//! cycles the instruction takes.
+//! See also https://github.com/SingleStepTests/65x02
+//!
+//! The test data isn't part of this repository. To run the tests, put the per-opcode JSON files
+//! (`00.json` .. `ff.json`) into `share/test/65x02/6502/v1` (or point the SINGLE_STEP_TESTS
+//! environment variable to the directory containing them) and run the ignored tests. The test
+//! fails if no test files can be found.
+
+use rusty64::cpu::mos6502::{CpuState, Instruction, StatusFlags};
+use rusty64::cpu::{Cpu, Mos6502};
+use rusty64::mem::{Addressable, Ram};
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Default location of the per-opcode test files
+const DEFAULT_DIR: &str = "share/test/65x02/6502/v1";
+
+/// A single test case
+#[derive(Debug, Deserialize)]
+struct TestCase {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    cycles: Vec<(u16, u8, String)>,
+}
+
+/// CPU and memory state of a test case
+#[derive(Debug, Deserialize)]
+struct State {
+    pc: u16,
+    s: u8,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+/// Outcome of running a single test case
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Final state and cycle count match
+    Passed,
+    /// Final state matches, but the cycle count differs
+    CycleMismatch(String),
+    /// Final registers or memory differ
+    StateMismatch(String),
+    /// Case needs a feature that isn't supported yet (e.g. decimal mode)
+    Unsupported,
+}
+
+/// Summary of running all test cases of one opcode
+#[derive(Debug, Default)]
+struct Summary {
+    passed: usize,
+    cycle_mismatches: usize,
+    state_mismatches: usize,
+    unsupported: usize,
+    first_failure: Option<String>,
+}
+
+/// Run a single test case on the given CPU
+fn run_case(cpu: &mut Mos6502<Ram>, case: &TestCase) -> Outcome {
+    let initial = &case.initial;
+    for &(addr, data) in &initial.ram {
+        cpu.memory_mut().set(addr, data);
+    }
+    cpu.load_state(&CpuState {
+        pc: initial.pc,
+        ac: initial.a,
+        x: initial.x,
+        y: initial.y,
+        sr: StatusFlags::from_bits_retain(initial.p),
+        sp: initial.s,
+        reset: false,
+        nmi: false,
+        irq: false,
+        halted: false,
+        pending_cycles: 0,
+        instruction_pc: None,
+        instruction_cycles_done: 0,
+        cycles: 0,
+    });
+
+    // Skip illegal opcodes and decimal mode arithmetic which aren't supported yet
+    let instruction = match cpu.peek_instruction() {
+        Ok((_, instruction, _)) => instruction,
+        Err(_) => return Outcome::Unsupported,
+    };
+    if matches!(instruction, Instruction::ADC | Instruction::SBC)
+        && StatusFlags::from_bits_retain(initial.p).contains(StatusFlags::DECIMAL_FLAG)
+    {
+        return Outcome::Unsupported;
+    }
+
+    let cycles = cpu.step();
+
+    let expected = &case.expected;
+    let state = cpu.save_state();
+    let actual = (
+        state.pc,
+        state.sp,
+        state.ac,
+        state.x,
+        state.y,
+        state.sr.bits(),
+    );
+    let wanted = (
+        expected.pc,
+        expected.s,
+        expected.a,
+        expected.x,
+        expected.y,
+        expected.p,
+    );
+    if actual != wanted {
+        return Outcome::StateMismatch(format!(
+            "{}: expected PC:{:04X} SP:{:02X} AC:{:02X} X:{:02X} Y:{:02X} SR:{:02X}, \
+             got PC:{:04X} SP:{:02X} AC:{:02X} X:{:02X} Y:{:02X} SR:{:02X}",
+            case.name,
+            wanted.0,
+            wanted.1,
+            wanted.2,
+            wanted.3,
+            wanted.4,
+            wanted.5,
+            actual.0,
+            actual.1,
+            actual.2,
+            actual.3,
+            actual.4,
+            actual.5,
+        ));
+    }
+    for &(addr, data) in &expected.ram {
+        let value = cpu.memory().get(addr);
+        if value != data {
+            return Outcome::StateMismatch(format!(
+                "{}: expected #${:02X} at ${:04X}, got #${:02X}",
+                case.name, data, addr, value
+            ));
+        }
+    }
+    if cycles != case.cycles.len() {
+        return Outcome::CycleMismatch(format!(
+            "{}: expected {} cycles, got {}",
+            case.name,
+            case.cycles.len(),
+            cycles
+        ));
+    }
+    Outcome::Passed
+}
+
+/// Run all test cases of the given JSON test file
+fn run_cases(cpu: &mut Mos6502<Ram>, json: &str) -> Summary {
+    let cases: Vec<TestCase> = serde_json::from_str(json).expect("invalid test case file");
+    let mut summary = Summary::default();
+    for case in &cases {
+        let outcome = run_case(cpu, case);
+        match outcome {
+            Outcome::Passed => summary.passed += 1,
+            Outcome::Unsupported => summary.unsupported += 1,
+            Outcome::CycleMismatch(ref msg) | Outcome::StateMismatch(ref msg) => {
+                if matches!(outcome, Outcome::CycleMismatch(..)) {
+                    summary.cycle_mismatches += 1;
+                } else {
+                    summary.state_mismatches += 1;
+                }
+                if summary.first_failure.is_none() {
+                    summary.first_failure = Some(msg.clone());
+                }
+            }
+        }
+    }
+    summary
+}
+
+#[test]
+fn run_single_case() {
+    let json = r#"[{
+        "name": "a9 42 ea",
+        "initial": { "pc": 4096, "s": 253, "a": 0, "x": 1, "y": 2, "p": 38,
+                     "ram": [[4096, 169], [4097, 66], [4098, 234]] },
+        "final": { "pc": 4098, "s": 253, "a": 66, "x": 1, "y": 2, "p": 36,
+                   "ram": [[4096, 169], [4097, 66], [4098, 234]] },
+        "cycles": [[4096, 169, "read"], [4097, 66, "read"]]
+    }]"#;
+    let mut cpu = Mos6502::new(Ram::new());
+    let summary = run_cases(&mut cpu, json);
+    assert_eq!(summary.passed, 1);
+    assert_eq!(summary.first_failure, None);
+}
+
+#[test]
+fn detect_mismatching_case() {
+    let json = r#"[{
+        "name": "a9 42 ea",
+        "initial": { "pc": 4096, "s": 253, "a": 0, "x": 1, "y": 2, "p": 38,
+                     "ram": [[4096, 169], [4097, 66], [4098, 234]] },
+        "final": { "pc": 4098, "s": 253, "a": 67, "x": 1, "y": 2, "p": 36,
+                   "ram": [[4096, 169], [4097, 66], [4098, 234]] },
+        "cycles": [[4096, 169, "read"], [4097, 66, "read"]]
+    }]"#;
+    let mut cpu = Mos6502::new(Ram::new());
+    let summary = run_cases(&mut cpu, json);
+    assert_eq!(summary.state_mismatches, 1);
+    assert!(summary.first_failure.unwrap().starts_with("a9 42 ea: "));
+}
+
+#[test]
+fn detect_cycle_mismatch() {
+    let json = r#"[{
+        "name": "a9 42 ea",
+        "initial": { "pc": 4096, "s": 253, "a": 0, "x": 1, "y": 2, "p": 38,
+                     "ram": [[4096, 169], [4097, 66], [4098, 234]] },
+        "final": { "pc": 4098, "s": 253, "a": 66, "x": 1, "y": 2, "p": 36,
+                   "ram": [[4096, 169], [4097, 66], [4098, 234]] },
+        "cycles": [[4096, 169, "read"], [4097, 66, "read"], [4098, 234, "read"]]
+    }]"#;
+    let mut cpu = Mos6502::new(Ram::new());
+    let summary = run_cases(&mut cpu, json);
+    assert_eq!(summary.cycle_mismatches, 1);
+    assert_eq!(
+        summary.first_failure.unwrap(),
+        "a9 42 ea: expected 3 cycles, got 2"
+    );
+}
+
+#[test]
+#[ignore = "needs SingleStepTests data in share/test/65x02/6502/v1"]
+fn single_step_tests() {
+    let dir = env::var_os("SINGLE_STEP_TESTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DIR));
+    let mut cpu = Mos6502::new(Ram::new());
+    let (mut found, mut skipped, mut failed) = (0, Vec::new(), Vec::new());
+    for opcode in 0..=255_u8 {
+        let path = dir.join(format!("{:02x}.json", opcode));
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(_) => {
+                skipped.push(opcode);
+                continue;
+            }
+        };
+        found += 1;
+        let summary = run_cases(&mut cpu, &json);
+        let total = summary.passed
+            + summary.cycle_mismatches
+            + summary.state_mismatches
+            + summary.unsupported;
+        if summary.unsupported == total {
+            skipped.push(opcode);
+            continue;
+        }
+        println!(
+            "{:02X}: {:5}/{} passed, {} cycle mismatches, {} state mismatches, {} unsupported",
+            opcode,
+            summary.passed,
+            total,
+            summary.cycle_mismatches,
+            summary.state_mismatches,
+            summary.unsupported,
+        );
+        if let Some(msg) = summary.first_failure {
+            println!("    first failure: {}", msg);
+        }
+        if summary.state_mismatches > 0 || summary.cycle_mismatches > 0 {
+            failed.push(opcode);
+        }
+    }
+    assert!(found > 0, "no test files found in {}", dir.display());
+    println!(
+        "{} opcodes skipped (not implemented or missing)",
+        skipped.len()
+    );
+    assert!(failed.is_empty(), "failed opcodes: {:02X?}", failed);
+}